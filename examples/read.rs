@@ -1,4 +1,5 @@
-#![allow(unused_doc_comment)]
+#![allow(unused_doc_comments)]
+#![allow(deprecated)]
 
 extern crate dimensioned;
 use dimensioned::si;
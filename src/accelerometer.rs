@@ -1,16 +1,22 @@
 //! Interface to the accelerometer.
 
-use common::Vector3;
+use crate::bus::{I2CWriteRead, ACCELEROMETER_ADDRESS};
+use crate::common::Vector3;
 use dimensioned::{si, ucum};
-use errors::{Error, ErrorKind, Result, ResultExt};
+use crate::errors::{BusResultExt, Error, ErrorKind, Result, ResultExt};
 use i2cdev::core::I2CDevice;
 use i2cdev::linux::LinuxI2CDevice;
-use registers;
+use crate::registers;
 use std::ops::{Deref, DerefMut};
+use std::time::Duration;
 
 
-/// The I2C address of the accelerometer.
-const I2C_ADDRESS: u16 = 0x32 >> 1;
+/// The number of samples held by the FIFO.
+const FIFO_DEPTH: usize = 32;
+
+
+/// The acceleration past which an axis counts as pointing up or down, in milligravities.
+const ORIENTATION_THRESHOLD_MG: f32 = 750.0;
 
 
 /// Interface to an LSM303 digital accelerometer.
@@ -21,6 +27,8 @@ where
     device: Dev,
     scale: Scale,
     rate: Rate,
+    mode: AccelMode,
+    enabled_axes: Vector3<bool>,
 }
 
 
@@ -29,8 +37,13 @@ pub type AccelerationVector = Vector3<si::MeterPerSecond2<f64>>;
 
 
 /// Settings for the scale of the acceleration measurement.
+///
+/// The default scale is `Scale2G`, as set by `Accelerometer::from_i2c_device`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Scale {
     /// +/- 2G
+    #[default]
     Scale2G,
     /// +/- 4G
     Scale4G,
@@ -41,10 +54,91 @@ pub enum Scale {
 }
 
 
+/// The operating modes of the accelerometer.
+///
+/// These trade resolution for power:
+/// the output is 12 bits in high resolution mode,
+/// 10 bits in normal mode, and 8 bits in low-power mode.
+/// The default mode is `HighResolution`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AccelMode {
+    /// 8 bit output, at the lowest power.
+    LowPower,
+    /// 10 bit output.
+    Normal,
+    /// 12 bit output.
+    #[default]
+    HighResolution,
+}
+
+
+impl AccelMode {
+    /// How far the left justified output must be shifted right.
+    fn shift(&self) -> u32 {
+        match *self {
+            AccelMode::LowPower => 8,
+            AccelMode::Normal => 6,
+            AccelMode::HighResolution => 4,
+        }
+    }
+}
+
+
+impl Scale {
+    /// The sensitivity of the measurement in the given mode, in mg/LSB.
+    ///
+    /// Refer to Table 3; those figures are for high resolution (12 bit) output.
+    /// Each bit of resolution that is dropped doubles the value of an LSB.
+    ///
+    /// ```
+    /// # use lsm303::accelerometer::{AccelMode, Scale};
+    /// let table = [
+    ///     (Scale::Scale2G, [16.0, 4.0, 1.0]),
+    ///     (Scale::Scale4G, [32.0, 8.0, 2.0]),
+    ///     (Scale::Scale8G, [64.0, 16.0, 4.0]),
+    ///     (Scale::Scale16G, [192.0, 48.0, 12.0]),
+    /// ];
+    /// let modes = [AccelMode::LowPower, AccelMode::Normal, AccelMode::HighResolution];
+    /// for &(scale, expected) in &table {
+    ///     for (&mode, &mg) in modes.iter().zip(&expected) {
+    ///         assert_eq!(scale.mg_per_lsb(mode), mg, "{:?} {:?}", scale, mode);
+    ///     }
+    /// }
+    /// ```
+    pub fn mg_per_lsb(&self, mode: AccelMode) -> f32 {
+        let sensitivity = match *self {
+            Scale::Scale2G => 1.0,
+            Scale::Scale4G => 2.0,
+            Scale::Scale8G => 4.0,
+            // This one doesn't follow the pattern - is the datasheet correct?
+            Scale::Scale16G => 12.0,
+        };
+        match mode {
+            AccelMode::LowPower => sensitivity * 16.0,
+            AccelMode::Normal => sensitivity * 4.0,
+            AccelMode::HighResolution => sensitivity,
+        }
+    }
+
+    /// The sensitivity of the interrupt thresholds, in mg/LSB.
+    ///
+    /// Refer to the description of INT1_THS_A.
+    fn threshold_mg_per_lsb(&self) -> f32 {
+        match *self {
+            Scale::Scale2G => 16.0,
+            Scale::Scale4G => 32.0,
+            Scale::Scale8G => 62.0,
+            Scale::Scale16G => 186.0,
+        }
+    }
+}
+
+
 /// Available values for the data rate.
 ///
 /// See Table 20 of the LSM303 datasheet.
-/// The default rate is `10 Hz`.
+/// The default rate is `50 Hz`.
 ///
 /// While most rates are valid for both normal
 /// and low-power mode, note that the available
@@ -55,10 +149,12 @@ pub enum Scale {
 /// # fn main() { test().unwrap(); }
 /// # fn test() -> lsm303::Result<()> {
 /// let mut accel = Accelerometer::new("/dev/i2c-1")?;
-/// accel.set_rate(Rate::Rate100Hz)?;
+/// accel.set_data_rate(Rate::Rate100Hz)?;
 /// # Ok(())
 /// # }
 /// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Rate {
     /// Power down mode
     PowerDown,
@@ -69,6 +165,7 @@ pub enum Rate {
     /// Normal / low-power mode (25 Hz)
     Rate25Hz,
     /// Normal / low-power mode (50 Hz)
+    #[default]
     Rate50Hz,
     /// Normal / low-power mode (100 Hz)
     Rate100Hz,
@@ -85,6 +182,623 @@ pub enum Rate {
 }
 
 
+/// Configuration of the INT1 interrupt generator.
+///
+/// Each axis can raise an interrupt when its acceleration goes above
+/// (high) or below (low) the threshold, for at least the duration.
+/// By default, any enabled event raises the interrupt;
+/// `and_combination` requires all of them at once instead.
+///
+/// The threshold and duration are in raw units:
+/// the threshold LSB depends on the scale (16 mg at +/- 2G),
+/// and the duration LSB is one sample period.
+///
+/// ```no_run
+/// # use lsm303::accelerometer::{Accelerometer, InterruptConfig};
+/// # fn main() { test().unwrap(); }
+/// # fn test() -> lsm303::Result<()> {
+/// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+/// // Wake on shake: any axis above 1 g at +/- 2G.
+/// let cfg = InterruptConfig::new()
+///     .x_high(true)
+///     .y_high(true)
+///     .z_high(true)
+///     .threshold(62)
+///     .latched(true);
+/// sensor.configure_interrupt1(cfg)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InterruptConfig {
+    events: registers::IntCfgA,
+    threshold: u8,
+    duration: u8,
+    latched: bool,
+}
+
+
+impl Default for InterruptConfig {
+    fn default() -> InterruptConfig {
+        InterruptConfig {
+            events: registers::IntCfgA::empty(),
+            threshold: 0,
+            duration: 0,
+            latched: false,
+        }
+    }
+}
+
+
+impl InterruptConfig {
+    /// Start with every event disabled.
+    pub fn new() -> InterruptConfig {
+        InterruptConfig::default()
+    }
+
+    /// Enable or disable a single event flag.
+    fn event(mut self, flag: registers::IntCfgA, enabled: bool) -> InterruptConfig {
+        self.events.set(flag, enabled);
+        self
+    }
+
+    /// Interrupt when the X axis is above the threshold.
+    pub fn x_high(self, enabled: bool) -> InterruptConfig {
+        self.event(registers::IntCfgA::XHIE, enabled)
+    }
+
+    /// Interrupt when the X axis is below the threshold.
+    pub fn x_low(self, enabled: bool) -> InterruptConfig {
+        self.event(registers::IntCfgA::XLIE, enabled)
+    }
+
+    /// Interrupt when the Y axis is above the threshold.
+    pub fn y_high(self, enabled: bool) -> InterruptConfig {
+        self.event(registers::IntCfgA::YHIE, enabled)
+    }
+
+    /// Interrupt when the Y axis is below the threshold.
+    pub fn y_low(self, enabled: bool) -> InterruptConfig {
+        self.event(registers::IntCfgA::YLIE, enabled)
+    }
+
+    /// Interrupt when the Z axis is above the threshold.
+    pub fn z_high(self, enabled: bool) -> InterruptConfig {
+        self.event(registers::IntCfgA::ZHIE, enabled)
+    }
+
+    /// Interrupt when the Z axis is below the threshold.
+    pub fn z_low(self, enabled: bool) -> InterruptConfig {
+        self.event(registers::IntCfgA::ZLIE, enabled)
+    }
+
+    /// Require all of the enabled events, rather than any of them.
+    pub fn and_combination(self, enabled: bool) -> InterruptConfig {
+        self.event(registers::IntCfgA::AOI, enabled)
+    }
+
+    /// Recognize the direction of the acceleration, rather than its change.
+    ///
+    /// With `and_combination`, the interrupt is held while the device
+    /// rests in one of the six positions; without it, it is raised on moving between them.
+    pub fn six_direction(self, enabled: bool) -> InterruptConfig {
+        self.event(registers::IntCfgA::_6D, enabled)
+    }
+
+    /// Set the threshold, in raw units. Only the low 7 bits are used.
+    pub fn threshold(mut self, threshold: u8) -> InterruptConfig {
+        self.threshold = threshold & 0x7F;
+        self
+    }
+
+    /// Set the minimum duration, in samples. Only the low 7 bits are used.
+    pub fn duration(mut self, duration: u8) -> InterruptConfig {
+        self.duration = duration & 0x7F;
+        self
+    }
+
+    /// Hold the interrupt until the source is read.
+    pub fn latched(mut self, latched: bool) -> InterruptConfig {
+        self.latched = latched;
+        self
+    }
+}
+
+
+/// The events that raised the INT1 interrupt, as read from INT1_SRC_A.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InterruptSource {
+    /// One or more events are active.
+    pub active: bool,
+    pub x_high: bool,
+    pub x_low: bool,
+    pub y_high: bool,
+    pub y_low: bool,
+    pub z_high: bool,
+    pub z_low: bool,
+}
+
+
+impl InterruptSource {
+    /// Decode the contents of INT1_SRC_A.
+    fn from_bits(flags: registers::IntSrcA) -> InterruptSource {
+        use crate::registers::IntSrcA as R;
+        InterruptSource {
+            active: flags.contains(R::IA),
+            x_high: flags.contains(R::XH),
+            x_low: flags.contains(R::XL),
+            y_high: flags.contains(R::YH),
+            y_low: flags.contains(R::YL),
+            z_high: flags.contains(R::ZH),
+            z_low: flags.contains(R::ZL),
+        }
+    }
+}
+
+
+/// The face of the device that is pointing up.
+///
+/// See `Accelerometer::read_orientation`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Orientation {
+    /// The positive X axis points up.
+    XUp,
+    /// The positive X axis points down.
+    XDown,
+    /// The positive Y axis points up.
+    YUp,
+    /// The positive Y axis points down.
+    YDown,
+    /// The positive Z axis points up, as when lying flat.
+    ZUp,
+    /// The positive Z axis points down, as when lying upside down.
+    ZDown,
+}
+
+
+impl Orientation {
+    /// Decode the contents of INT1_SRC_A in 6D position recognition.
+    ///
+    /// Returns `None` unless exactly one axis is past the threshold.
+    fn from_bits(flags: registers::IntSrcA) -> Option<Orientation> {
+        use crate::registers::IntSrcA as R;
+
+        if !flags.contains(R::IA) {
+            return None;
+        }
+        let faces = [
+            (R::XH, Orientation::XUp),
+            (R::XL, Orientation::XDown),
+            (R::YH, Orientation::YUp),
+            (R::YL, Orientation::YDown),
+            (R::ZH, Orientation::ZUp),
+            (R::ZL, Orientation::ZDown),
+        ];
+        let mut found = faces.iter().filter(|&&(flag, _)| flags.contains(flag));
+        match (found.next(), found.next()) {
+            (Some(&(_, orientation)), None) => Some(orientation),
+            _ => None,
+        }
+    }
+}
+
+
+impl Rate {
+    /// The nominal sample rate, in Hz.
+    fn hz(&self) -> f32 {
+        match *self {
+            Rate::PowerDown => 0.0,
+            Rate::Rate1Hz => 1.0,
+            Rate::Rate10Hz => 10.0,
+            Rate::Rate25Hz => 25.0,
+            Rate::Rate50Hz => 50.0,
+            Rate::Rate100Hz => 100.0,
+            Rate::Rate200Hz => 200.0,
+            Rate::Rate400Hz => 400.0,
+            Rate::Rate1620Hz => 1620.0,
+            Rate::Rate1344Hz => 1344.0,
+            Rate::Rate5376Hz => 5376.0,
+        }
+    }
+
+    /// Whether the chip measures at this rate in the given mode.
+    ///
+    /// The fastest ODR settings mean different rates in low-power mode;
+    /// see Table 20 of the datasheet.
+    fn supports(&self, mode: AccelMode) -> bool {
+        match *self {
+            Rate::Rate1620Hz | Rate::Rate5376Hz => mode == AccelMode::LowPower,
+            Rate::Rate1344Hz => mode != AccelMode::LowPower,
+            _ => true,
+        }
+    }
+}
+
+
+/// Configuration of click (tap) detection.
+///
+/// A single click is an acceleration above the threshold
+/// that ends within the time limit.
+/// A double click is a second one that starts after the latency,
+/// but within the window.
+/// The threshold is in raw units, with the same sensitivity as
+/// `InterruptConfig::threshold`; the times are in sample periods.
+///
+/// ```no_run
+/// # use lsm303::accelerometer::{Accelerometer, ClickConfig};
+/// # fn main() { test().unwrap(); }
+/// # fn test() -> lsm303::Result<()> {
+/// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+/// let cfg = ClickConfig::new()
+///     .z_single(true)
+///     .z_double(true)
+///     .threshold(40)
+///     .time_limit(10)
+///     .time_latency(20)
+///     .time_window(255);
+/// sensor.configure_click(cfg)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClickConfig {
+    events: registers::ClickCfgA,
+    threshold: u8,
+    time_limit: u8,
+    time_latency: u8,
+    time_window: u8,
+}
+
+
+impl Default for ClickConfig {
+    fn default() -> ClickConfig {
+        ClickConfig {
+            events: registers::ClickCfgA::empty(),
+            threshold: 0,
+            time_limit: 0,
+            time_latency: 0,
+            time_window: 0,
+        }
+    }
+}
+
+
+impl ClickConfig {
+    /// Start with click detection disabled on every axis.
+    pub fn new() -> ClickConfig {
+        ClickConfig::default()
+    }
+
+    /// Enable or disable a single event flag.
+    fn event(mut self, flag: registers::ClickCfgA, enabled: bool) -> ClickConfig {
+        self.events.set(flag, enabled);
+        self
+    }
+
+    /// Detect single clicks on the X axis.
+    pub fn x_single(self, enabled: bool) -> ClickConfig {
+        self.event(registers::ClickCfgA::XS, enabled)
+    }
+
+    /// Detect double clicks on the X axis.
+    pub fn x_double(self, enabled: bool) -> ClickConfig {
+        self.event(registers::ClickCfgA::XD, enabled)
+    }
+
+    /// Detect single clicks on the Y axis.
+    pub fn y_single(self, enabled: bool) -> ClickConfig {
+        self.event(registers::ClickCfgA::YS, enabled)
+    }
+
+    /// Detect double clicks on the Y axis.
+    pub fn y_double(self, enabled: bool) -> ClickConfig {
+        self.event(registers::ClickCfgA::YD, enabled)
+    }
+
+    /// Detect single clicks on the Z axis.
+    pub fn z_single(self, enabled: bool) -> ClickConfig {
+        self.event(registers::ClickCfgA::ZS, enabled)
+    }
+
+    /// Detect double clicks on the Z axis.
+    pub fn z_double(self, enabled: bool) -> ClickConfig {
+        self.event(registers::ClickCfgA::ZD, enabled)
+    }
+
+    /// Set the threshold, in raw units. Only the low 7 bits are used.
+    pub fn threshold(mut self, threshold: u8) -> ClickConfig {
+        self.threshold = threshold & 0x7F;
+        self
+    }
+
+    /// Set the longest a click may last. Only the low 7 bits are used.
+    pub fn time_limit(mut self, time_limit: u8) -> ClickConfig {
+        self.time_limit = time_limit & 0x7F;
+        self
+    }
+
+    /// Set the time after a click during which another is ignored.
+    pub fn time_latency(mut self, time_latency: u8) -> ClickConfig {
+        self.time_latency = time_latency;
+        self
+    }
+
+    /// Set the time after the latency during which a second click
+    /// makes a double click.
+    pub fn time_window(mut self, time_window: u8) -> ClickConfig {
+        self.time_window = time_window;
+        self
+    }
+}
+
+
+/// The click that was detected, as read from CLICK_SRC_A.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClickSource {
+    /// A click was detected.
+    pub active: bool,
+    /// The click was a single click.
+    pub single: bool,
+    /// The click was a double click.
+    pub double: bool,
+    /// The click was in the negative direction.
+    pub negative: bool,
+    /// The click was along the X axis.
+    pub x: bool,
+    /// The click was along the Y axis.
+    pub y: bool,
+    /// The click was along the Z axis.
+    pub z: bool,
+}
+
+
+impl ClickSource {
+    /// Decode the contents of CLICK_SRC_A.
+    fn from_bits(flags: registers::ClickSrcA) -> ClickSource {
+        use crate::registers::ClickSrcA as R;
+        ClickSource {
+            active: flags.contains(R::IA_click),
+            single: flags.contains(R::SCLICK),
+            double: flags.contains(R::DCLICK),
+            negative: flags.contains(R::Sign),
+            x: flags.contains(R::X),
+            y: flags.contains(R::Y),
+            z: flags.contains(R::Z),
+        }
+    }
+}
+
+
+/// The modes of the high pass filter.
+///
+/// See the description of CTRL_REG2_A in the LSM303 datasheet.
+/// The default mode is `Normal`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HighPassMode {
+    /// Filter continuously; the filter is reset by reading REFERENCE_A.
+    Normal,
+    /// Subtract the value of REFERENCE_A from the output.
+    Reference,
+    /// Reset the filter whenever an interrupt is raised.
+    AutoReset,
+}
+
+
+/// The cutoff frequencies of the high pass filter.
+///
+/// The frequency is proportional to the data rate;
+/// at 50 Hz, they are about 1, 0.5, 0.2, and 0.1 Hz.
+/// See application note AN3308.
+/// The default cutoff is `Highest`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HighPassCutoff {
+    /// The highest cutoff, which removes slow changes most strongly.
+    Highest,
+    /// The second highest cutoff.
+    High,
+    /// The second lowest cutoff.
+    Low,
+    /// The lowest cutoff, which lets the slowest changes through.
+    Lowest,
+}
+
+
+impl HighPassMode {
+    /// The HPM bits of CTRL_REG2_A for this mode.
+    fn bits(&self) -> registers::CtrlReg2Af {
+        use crate::registers::CtrlReg2Af as R;
+        match *self {
+            HighPassMode::Normal => R::empty(),
+            HighPassMode::Reference => /* -- */ R::HPM0,
+            HighPassMode::AutoReset => R::HPM1 | R::HPM0,
+        }
+    }
+}
+
+
+impl HighPassCutoff {
+    /// The HPCF bits of CTRL_REG2_A for this cutoff.
+    fn bits(&self) -> registers::CtrlReg2Af {
+        use crate::registers::CtrlReg2Af as R;
+        match *self {
+            HighPassCutoff::Highest => R::empty(),
+            HighPassCutoff::High => /* ---- */ R::HPCF1,
+            HighPassCutoff::Low => R::HPCF2,
+            HighPassCutoff::Lowest => R::HPCF2 | R::HPCF1,
+        }
+    }
+}
+
+
+/// A configuration for the high pass filter of the accelerometer.
+///
+/// The filter removes the constant component of the acceleration,
+/// such as gravity, which is useful for detecting taps and vibration.
+/// It can be applied to the output registers and FIFO,
+/// to click detection, and to each of the interrupt generators, independently.
+/// By default, it is applied to none of them.
+///
+/// ```no_run
+/// # use lsm303::accelerometer::{Accelerometer, HighPassConfig, HighPassCutoff};
+/// # fn main() { test().unwrap(); }
+/// # fn test() -> lsm303::Result<()> {
+/// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+/// let cfg = HighPassConfig::new()
+///     .cutoff(HighPassCutoff::Low)
+///     .output(true)
+///     .click(true);
+/// sensor.configure_high_pass(cfg)?;
+/// sensor.reset_reference()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HighPassConfig {
+    mode: HighPassMode,
+    cutoff: HighPassCutoff,
+    paths: registers::CtrlReg2Af,
+    reference: u8,
+}
+
+
+impl Default for HighPassConfig {
+    fn default() -> HighPassConfig {
+        HighPassConfig {
+            mode: HighPassMode::Normal,
+            cutoff: HighPassCutoff::Highest,
+            paths: registers::CtrlReg2Af::empty(),
+            reference: 0,
+        }
+    }
+}
+
+
+impl HighPassConfig {
+    /// Start with the filter bypassed everywhere.
+    pub fn new() -> HighPassConfig {
+        HighPassConfig::default()
+    }
+
+    /// Enable or disable the filter on a single path.
+    fn path(mut self, flag: registers::CtrlReg2Af, enabled: bool) -> HighPassConfig {
+        self.paths.set(flag, enabled);
+        self
+    }
+
+    /// Set the mode of the filter.
+    pub fn mode(mut self, mode: HighPassMode) -> HighPassConfig {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the cutoff frequency of the filter.
+    pub fn cutoff(mut self, cutoff: HighPassCutoff) -> HighPassConfig {
+        self.cutoff = cutoff;
+        self
+    }
+
+    /// Set the value to subtract in `HighPassMode::Reference`, in raw units.
+    pub fn reference(mut self, reference: u8) -> HighPassConfig {
+        self.reference = reference;
+        self
+    }
+
+    /// Filter the output registers and the FIFO.
+    pub fn output(self, enabled: bool) -> HighPassConfig {
+        self.path(registers::CtrlReg2Af::FDS, enabled)
+    }
+
+    /// Filter the input to click detection.
+    pub fn click(self, enabled: bool) -> HighPassConfig {
+        self.path(registers::CtrlReg2Af::HPCLICK, enabled)
+    }
+
+    /// Filter the input to interrupt generator 1.
+    pub fn interrupt1(self, enabled: bool) -> HighPassConfig {
+        self.path(registers::CtrlReg2Af::HPIS1, enabled)
+    }
+
+    /// Filter the input to interrupt generator 2.
+    pub fn interrupt2(self, enabled: bool) -> HighPassConfig {
+        self.path(registers::CtrlReg2Af::HPIS2, enabled)
+    }
+}
+
+
+/// The modes of the accelerometer FIFO.
+///
+/// See the description of FIFO_CTRL_REG_A in the LSM303 datasheet.
+/// The default mode is `Bypass`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FifoMode {
+    /// The FIFO is not used; only the latest sample is available.
+    Bypass,
+    /// Collect samples until the FIFO is full, and then stop.
+    Fifo,
+    /// Collect samples continuously, discarding the oldest when full.
+    Stream,
+    /// Stream until INT1 is raised, and then stop when full.
+    StreamToFifo,
+}
+
+
+impl FifoMode {
+    /// The FM bits of FIFO_CTRL_REG_A for this mode.
+    fn bits(&self) -> registers::FifoCtrlRegA {
+        use crate::registers::FifoCtrlRegA as R;
+        match *self {
+            FifoMode::Bypass => R::empty(),
+            FifoMode::Fifo => /* -- */ R::FM0,
+            FifoMode::Stream => R::FM1,
+            FifoMode::StreamToFifo => R::FM1 | R::FM0,
+        }
+    }
+}
+
+
+/// The state of the FIFO, as read from FIFO_SRC_REG_A.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FifoStatus {
+    /// The number of unread samples, up to 32.
+    pub level: u8,
+    /// The level has reached the watermark.
+    pub watermark: bool,
+    /// The FIFO is full, and samples have been lost or ignored.
+    pub overrun: bool,
+}
+
+
+impl FifoStatus {
+    /// Decode the contents of FIFO_SRC_REG_A.
+    fn from_bits(flags: registers::FifoSrcRegA) -> FifoStatus {
+        use crate::registers::FifoSrcRegA as R;
+        let overrun = flags.contains(R::OVRN_FIFO);
+        let stored = flags & (R::FSS4 | R::FSS3 | R::FSS2 | R::FSS1 | R::FSS0);
+        // The stored count only has room for 31; a full FIFO is signalled by the overrun.
+        let level = if overrun { FIFO_DEPTH as u8 } else { stored.bits() };
+        FifoStatus {
+            level,
+            watermark: flags.contains(R::WTM),
+            overrun,
+        }
+    }
+}
+
+
+/// Round a value to the nearest 7 bit register value.
+fn to_7_bits(value: f32) -> u8 {
+    value.round().clamp(0.0, 127.0) as u8
+}
+
+
 impl Accelerometer<LinuxI2CDevice> {
     /// Initialize the accelerometer for a Linux I2C device.
     ///
@@ -96,7 +810,7 @@ impl Accelerometer<LinuxI2CDevice> {
     where
         Path: AsRef<::std::path::Path>,
     {
-        let device = LinuxI2CDevice::new(&path, I2C_ADDRESS).chain_err(|| {
+        let device = LinuxI2CDevice::new(&path, ACCELEROMETER_ADDRESS).chain_err(|| {
             ErrorKind::FailedToOpenDevice
         })?;
 
@@ -105,9 +819,29 @@ impl Accelerometer<LinuxI2CDevice> {
 }
 
 
+#[cfg(feature = "embedded-hal")]
+impl<I2C, E> Accelerometer<crate::hal::HalDevice<I2C>>
+where
+    I2C: ::embedded_hal::blocking::i2c::Write<Error = E>
+        + ::embedded_hal::blocking::i2c::WriteRead<Error = E>,
+    E: ::std::fmt::Debug,
+{
+    /// Initialize the accelerometer on an `embedded-hal` I2C bus.
+    ///
+    /// The address of the accelerometer is normally `0x19`.
+    /// Requires the `embedded-hal` feature; see the `hal` module.
+    pub fn from_embedded_hal(
+        i2c: I2C,
+        address: u8,
+    ) -> Result<Accelerometer<crate::hal::HalDevice<I2C>>> {
+        Accelerometer::from_i2c_device(crate::hal::HalDevice::new(i2c, address))
+    }
+}
+
+
 impl<Dev> Accelerometer<Dev>
 where
-    Dev: I2CDevice,
+    Dev: I2CWriteRead,
     Error: From<Dev::Error>,
     Dev::Error: Send + 'static,
 {
@@ -118,6 +852,11 @@ where
     /// Prefer to use `Accelerometer::new`, unless you are using an
     /// implementation of `I2CDevice` that is not covered by this crate.
     ///
+    /// All three axes are enabled, with a data rate of 50 Hz.
+    /// This is fast enough for most motion sensing,
+    /// without the power draw of the higher rates.
+    /// Block data update is enabled; see `set_block_data_update`.
+    ///
     /// ```no_run
     /// # extern crate lsm303;
     /// # use lsm303::Accelerometer;
@@ -131,28 +870,30 @@ where
     /// # }
     /// ```
     pub fn from_i2c_device(mut device: Dev) -> Result<Accelerometer<Dev>> {
-        use registers::{CTRL_REG1_A, CtrlReg1A, CTRL_REG4_A, CtrlReg4A};
+        use crate::registers::{CTRL_REG1_A, CtrlReg1A, CTRL_REG4_A, CtrlReg4A};
 
-        // Set data rate to 10 Hz, enable all axes.
+        // Set data rate to 50 Hz, enable all axes.
         type R = CtrlReg1A;
-        let ctrl_reg1_a = R::ODR1 | R::Zen | R::Yen | R::Xen;
+        let ctrl_reg1_a = R::ODR2 | R::Zen | R::Yen | R::Xen;
         write_register!(device, CTRL_REG1_A, ctrl_reg1_a)?;
 
-        // Enable high resolution output mode.
+        // Enable high resolution output mode, and block data update.
         let mut ctrl_reg4_a = read_register!(device, CTRL_REG4_A, CtrlReg4A)?;
-        ctrl_reg4_a.insert(CtrlReg4A::HR);
+        ctrl_reg4_a.insert(CtrlReg4A::HR | CtrlReg4A::BDU);
         write_register!(device, CTRL_REG4_A, ctrl_reg4_a)?;
 
         // Default scale is +/- 2G
         let scale = Scale::Scale2G;
 
         // Default rate
-        let rate = Rate::Rate10Hz;
+        let rate = Rate::Rate50Hz;
 
         let accelerometer = Accelerometer {
             device,
             scale,
             rate,
+            mode: AccelMode::HighResolution,
+            enabled_axes: Vector3 { x: true, y: true, z: true },
         };
         Ok(accelerometer)
     }
@@ -171,70 +912,142 @@ where
     /// # }
     /// ```
     pub fn read_acceleration(&mut self) -> Result<AccelerationVector> {
-        use byteorder::{LittleEndian, ReadBytesExt};
         use dimensioned::f64prefixes::MILLI;
-        use std::io::Cursor;
-
-        let data = self.device.smbus_read_i2c_block_data(
-            registers::OUT_X_L_A | 0x80,
-            6,
-        )?;
 
-        if data.len() < 6 {
-            bail!(ErrorKind::NotEnoughData);
-        }
-
-        let mut cursor = Cursor::new(&data);
+        let Vector3 { x, y, z } = self.read_raw_acceleration()?;
 
-        // The scale of the measurement, in g's.
-        // Refer to Table 3; linear acceleration sensitivity is measured in mg/LSB.
-        //
         // `dimensioned` only defines the acceleration of free fall for
         // UCUM, so we have to convert to SI.
-        let scale = MILLI * ucum::G_ *
-            match self.scale {
-                Scale::Scale2G => 1.0,
-                Scale::Scale4G => 2.0,
-                Scale::Scale8G => 4.0,
-                // This one doesn't follow the pattern - is the datasheet correct?
-                Scale::Scale16G => 12.0,
-            };
+        let scale = MILLI * ucum::G_ * f64::from(self.scale.mg_per_lsb(self.mode));
         let scale: si::MeterPerSecond2<f64> = scale.into();
 
-        let x = (cursor.read_i16::<LittleEndian>()? >> 4) as f64 * scale;
-        let y = (cursor.read_i16::<LittleEndian>()? >> 4) as f64 * scale;
-        let z = (cursor.read_i16::<LittleEndian>()? >> 4) as f64 * scale;
+        let x = f64::from(x) * scale;
+        let y = f64::from(y) * scale;
+        let z = f64::from(z) * scale;
 
         let out = AccelerationVector { x, y, z };
         Ok(out)
     }
 
-    /// Set the scale of the acceleration measurement.
+    /// Read the accelerometer, returning the acceleration in g's.
+    ///
+    /// The raw output is scaled according to the current scale and resolution.
     ///
     /// ```no_run
-    /// # use lsm303::accelerometer::{Accelerometer, Scale};
+    /// # use lsm303::Accelerometer;
     /// # fn main() { test().unwrap(); }
     /// # fn test() -> lsm303::Result<()> {
     /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
-    /// sensor.set_scale(Scale::Scale4G)?;
+    /// let accel = sensor.read_acceleration_g()?;
+    /// println!("Acceleration: ({}, {}, {}) g",
+    ///     accel.x, accel.y, accel.z);
     /// # Ok(())
     /// # }
     /// ```
-    pub fn set_scale(&mut self, scale: Scale) -> Result<()> {
-        use registers::{CTRL_REG4_A, CtrlReg4A};
-        type R = CtrlReg4A;
+    pub fn read_acceleration_g(&mut self) -> Result<Vector3<f32>> {
+        let Vector3 { x, y, z } = self.read_raw_acceleration()?;
+        let scale = self.scale.mg_per_lsb(self.mode) / 1000.0;
 
-        let mut flags = read_register!(self.device, CTRL_REG4_A, CtrlReg4A)?;
-        flags.remove(R::FS1 | R::FS0);
-        let setting = match scale {
-            Scale::Scale2G => R::empty(),
-            Scale::Scale4G => R::FS0,
-            Scale::Scale8G => R::FS1,
-            Scale::Scale16G => R::FS1 | R::FS0,
-        };
-        flags.insert(setting);
+        let x = f32::from(x) * scale;
+        let y = f32::from(y) * scale;
+        let z = f32::from(z) * scale;
+
+        Ok(Vector3 { x, y, z })
+    }
+
+    /// Read the accelerometer, returning the raw output of each axis.
+    ///
+    /// The meaning of the values depends on the current scale.
+    /// This does not allocate, so it is suitable for tight sampling loops.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// let raw = sensor.read_raw_acceleration()?;
+    /// println!("Raw acceleration: ({}, {}, {})",
+    ///     raw.x, raw.y, raw.z);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_raw_acceleration(&mut self) -> Result<Vector3<i16>> {
+        let mut bytes = [0; 6];
+        self.read_acceleration_raw(&mut bytes)?;
+        Ok(self.decode_output(&bytes))
+    }
+
+    /// Read the output registers of the accelerometer, without decoding them.
+    ///
+    /// This is for forwarding the measurement, say over a radio link,
+    /// to be decoded elsewhere.
+    /// The bytes are the X, Y and Z axes, in that order, each as a little endian `i16`.
+    /// The output is left justified: shift each axis right by
+    /// 4 bits in high resolution mode, 6 in normal mode, or 8 in low power mode.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// let mut frame = [0; 6];
+    /// sensor.read_acceleration_raw(&mut frame)?;
+    ///
+    /// // On the receiving end, in high resolution mode:
+    /// let x = i16::from_le_bytes([frame[0], frame[1]]) >> 4;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_acceleration_raw(&mut self, out: &mut [u8; 6]) -> Result<()> {
+        // Unlike the magnetometer, the accelerometer only auto-increments
+        // when asked to, which lets all six registers be read in one transaction.
+        read_registers!(self.device, registers::block_read_addr(registers::OUT_X_L_A), out[..])
+    }
+
+    /// Decode one sample from the output registers.
+    fn decode_output(&self, bytes: &[u8]) -> Vector3<i16> {
+        // The output is left justified, with a width that depends on the mode.
+        let shift = self.mode.shift();
+        let x = i16::from_le_bytes([bytes[0], bytes[1]]) >> shift;
+        let y = i16::from_le_bytes([bytes[2], bytes[3]]) >> shift;
+        let z = i16::from_le_bytes([bytes[4], bytes[5]]) >> shift;
+
+        // A disabled axis holds its last measurement, which would be misleading.
+        let axes = self.enabled_axes;
+        Vector3 {
+            x: if axes.x { x } else { 0 },
+            y: if axes.y { y } else { 0 },
+            z: if axes.z { z } else { 0 },
+        }
+    }
+
+    /// Set the scale of the acceleration measurement.
+    ///
+    /// ```no_run
+    /// # use lsm303::accelerometer::{Accelerometer, Scale};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.set_scale(Scale::Scale4G)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_scale(&mut self, scale: Scale) -> Result<()> {
+        use crate::registers::{CTRL_REG4_A, CtrlReg4A};
+        type R = CtrlReg4A;
+
+        let mut flags = read_register!(self.device, CTRL_REG4_A, CtrlReg4A)?;
+        flags.remove(R::FS1 | R::FS0);
+        let setting = match scale {
+            Scale::Scale2G => R::empty(),
+            Scale::Scale4G => R::FS0,
+            Scale::Scale8G => R::FS1,
+            Scale::Scale16G => R::FS1 | R::FS0,
+        };
+        flags.insert(setting);
 
         write_register!(self.device, CTRL_REG4_A, flags)?;
+        log_info!("accelerometer scale set to {:?}", scale);
         self.scale = scale;
 
         Ok(())
@@ -242,29 +1055,675 @@ where
 
     /// Set the rate at which acceleration is measured.
     ///
+    /// `Rate1620Hz` and `Rate5376Hz` are only available in low-power mode,
+    /// and `Rate1344Hz` only outside it,
+    /// so selecting one of them switches the operating mode to suit:
+    /// to `AccelMode::LowPower` for the first two,
+    /// and from low-power to `AccelMode::Normal` for the last.
+    ///
     /// ```no_run
     /// # use lsm303::accelerometer::{Accelerometer, Rate};
     /// # fn main() { test().unwrap(); }
     /// # fn test() -> lsm303::Result<()> {
     /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
-    /// sensor.set_rate(Rate::Rate100Hz)?;
+    /// sensor.set_data_rate(Rate::Rate100Hz)?;
     /// # Ok(())
     /// # }
     /// ```
+    pub fn set_data_rate(&mut self, rate: Rate) -> Result<()> {
+        if !rate.supports(self.mode) {
+            let mode = if rate == Rate::Rate1344Hz {
+                AccelMode::Normal
+            } else {
+                AccelMode::LowPower
+            };
+            self.write_operating_mode(mode)?;
+        }
+
+        self.write_data_rate(rate)?;
+        log_info!("accelerometer data rate set to {:?}", rate);
+        self.rate = rate;
+
+        Ok(())
+    }
+
+    /// Set the rate at which acceleration is measured.
+    #[deprecated(note = "use `set_data_rate`")]
     pub fn set_rate(&mut self, rate: Rate) -> Result<()> {
-        use registers::{CTRL_REG1_A, CtrlReg1A};
+        self.set_data_rate(rate)
+    }
+
+    /// Get the current data rate of the accelerometer.
+    ///
+    /// ```no_run
+    /// # use lsm303::accelerometer::{Accelerometer, Rate};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// assert_eq!(sensor.data_rate(), Rate::Rate50Hz);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn data_rate(&self) -> Rate {
+        self.rate
+    }
+
+    /// The nominal time between measurements, at the current data rate.
+    ///
+    /// This is the time step for integrating the acceleration.
+    /// Returns `None` when powered down.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// let dt = sensor.sample_interval().unwrap().as_secs_f32();
+    /// let mut velocity = 0.0;
+    /// velocity += sensor.read_acceleration_g()?.x * 9.81 * dt;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn sample_interval(&self) -> Option<Duration> {
+        self.sample_interval_us().map(|us| Duration::from_micros(u64::from(us)))
+    }
+
+    /// The nominal time between measurements, in microseconds.
+    ///
+    /// This is `sample_interval`, for integer-only code.
+    pub fn sample_interval_us(&self) -> Option<u32> {
+        let hz = self.rate.hz();
+        if hz == 0.0 {
+            None
+        } else {
+            Some((1_000_000.0 / hz).round() as u32)
+        }
+    }
+
+    /// Stop measuring acceleration.
+    ///
+    /// The data rate is remembered, and restored by `power_up`.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.power_down()?;
+    /// // ...
+    /// sensor.power_up()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn power_down(&mut self) -> Result<()> {
+        self.write_data_rate(Rate::PowerDown)
+    }
+
+    /// Resume measuring acceleration at the most recently set data rate.
+    ///
+    /// If the data rate was set to `Rate::PowerDown`,
+    /// the default rate of 50 Hz is used instead.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.power_down()?;
+    /// sensor.power_up()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn power_up(&mut self) -> Result<()> {
+        if self.rate == Rate::PowerDown {
+            self.rate = Rate::Rate50Hz;
+        }
+        let rate = self.rate;
+        self.write_data_rate(rate)
+    }
+
+    /// Configure the INT1 interrupt generator, and route it to the INT1 pin.
+    ///
+    /// ```no_run
+    /// # use lsm303::accelerometer::{Accelerometer, InterruptConfig};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// let cfg = InterruptConfig::new().z_high(true).threshold(32).duration(2);
+    /// sensor.configure_interrupt1(cfg)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn configure_interrupt1(&mut self, cfg: InterruptConfig) -> Result<()> {
+        use crate::registers::{CTRL_REG3_A, CTRL_REG5_A, INT1_CFG_A, INT1_DURATION_A, INT1_THS_A};
+        use crate::registers::{CtrlReg3A, CtrlReg5A};
+
+        for &(register, value) in &[(INT1_THS_A, cfg.threshold), (INT1_DURATION_A, cfg.duration)] {
+            self.device.smbus_write_byte_data(register, value).chain_bus_err(|| {
+                ErrorKind::FailedToWriteRegister
+            })?;
+        }
+
+        let mut ctrl_reg5_a = read_register!(self.device, CTRL_REG5_A, CtrlReg5A)?;
+        ctrl_reg5_a.set(CtrlReg5A::LIR_INT1, cfg.latched);
+        write_register!(self.device, CTRL_REG5_A, ctrl_reg5_a)?;
+
+        let mut ctrl_reg3_a = read_register!(self.device, CTRL_REG3_A, CtrlReg3A)?;
+        ctrl_reg3_a.set(CtrlReg3A::I1_AOI1, !cfg.events.is_empty());
+        write_register!(self.device, CTRL_REG3_A, ctrl_reg3_a)?;
+
+        // Enable the events last, so that they use the new threshold.
+        let int1_cfg_a = cfg.events;
+        write_register!(self.device, INT1_CFG_A, int1_cfg_a)?;
+
+        Ok(())
+    }
+
+    /// Read which events raised the INT1 interrupt.
+    ///
+    /// This clears a latched interrupt.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// let source = sensor.read_interrupt1_source()?;
+    /// if source.active && source.z_high {
+    ///     println!("Shaken vertically");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_interrupt1_source(&mut self) -> Result<InterruptSource> {
+        use crate::registers::{INT1_SOURCE_A, IntSrcA};
+
+        let flags = read_register!(self.device, INT1_SOURCE_A, IntSrcA)?;
+        Ok(InterruptSource::from_bits(flags))
+    }
+
+    /// Configure INT1 to detect free fall.
+    ///
+    /// In free fall, every axis measures close to zero.
+    /// The interrupt is raised when all three axes are below the threshold,
+    /// in milligravities, for at least the duration, in milliseconds.
+    /// The values are converted using the current scale and data rate,
+    /// so set those first; values that are out of range are clamped.
+    /// Something like 350 mg for 30 ms is a reasonable starting point.
+    ///
+    /// The interrupt is latched, so that a short fall is not missed.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.configure_free_fall(350, 30)?;
+    /// loop {
+    ///     if sensor.free_fall_detected()? {
+    ///         println!("Dropped!");
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn configure_free_fall(&mut self, threshold_mg: u16, duration_ms: u16) -> Result<()> {
+        let threshold = to_7_bits(f32::from(threshold_mg) / self.scale.threshold_mg_per_lsb());
+        let duration = to_7_bits(f32::from(duration_ms) * self.rate.hz() / 1000.0);
+
+        let cfg = InterruptConfig::new()
+            .x_low(true)
+            .y_low(true)
+            .z_low(true)
+            .and_combination(true)
+            .threshold(threshold)
+            .duration(duration)
+            .latched(true);
+        self.configure_interrupt1(cfg)
+    }
+
+    /// Check whether free fall was detected since the last check.
+    ///
+    /// See `configure_free_fall`. This clears the latched interrupt.
+    pub fn free_fall_detected(&mut self) -> Result<bool> {
+        let source = self.read_interrupt1_source()?;
+        Ok(source.active)
+    }
+
+    /// Configure INT1 to recognize which face of the device is up.
+    ///
+    /// This uses the 6D position recognition of the chip.
+    /// An axis is taken to point up or down once it measures more than 0.75 g,
+    /// which at most one axis can do at a time,
+    /// so the position changes after tilting by about 41 degrees.
+    /// The threshold is converted using the current scale, so set that first.
+    /// See `read_orientation`.
+    pub fn configure_orientation_detection(&mut self) -> Result<()> {
+        let threshold = to_7_bits(ORIENTATION_THRESHOLD_MG / self.scale.threshold_mg_per_lsb());
+
+        let cfg = InterruptConfig::new()
+            .x_high(true)
+            .x_low(true)
+            .y_high(true)
+            .y_low(true)
+            .z_high(true)
+            .z_low(true)
+            .and_combination(true)
+            .six_direction(true)
+            .threshold(threshold);
+        self.configure_interrupt1(cfg)
+    }
+
+    /// Read which face of the device is up.
+    ///
+    /// See `configure_orientation_detection`.
+    /// Fails with `ErrorKind::OrientationUnknown` while the device is tilted
+    /// between positions, or moving.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # use lsm303::accelerometer::Orientation;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.configure_orientation_detection()?;
+    /// if sensor.read_orientation()? == Orientation::ZDown {
+    ///     println!("Upside down");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_orientation(&mut self) -> Result<Orientation> {
+        use crate::registers::{INT1_SOURCE_A, IntSrcA};
+
+        let flags = read_register!(self.device, INT1_SOURCE_A, IntSrcA)?;
+        match Orientation::from_bits(flags) {
+            Some(orientation) => Ok(orientation),
+            None => bail!(ErrorKind::OrientationUnknown),
+        }
+    }
+
+    /// Configure click detection, and route it to the INT1 pin.
+    ///
+    /// ```no_run
+    /// # use lsm303::accelerometer::{Accelerometer, ClickConfig};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// let cfg = ClickConfig::new().z_single(true).threshold(40).time_limit(10);
+    /// sensor.configure_click(cfg)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn configure_click(&mut self, cfg: ClickConfig) -> Result<()> {
+        use crate::registers::{CLICK_CFG_A, CLICK_THS_A, CTRL_REG3_A, TIME_LATENCY_A, TIME_LIMIT_A,
+                               TIME_WINDOW_A};
+        use crate::registers::CtrlReg3A;
+
+        let timing = [
+            (CLICK_THS_A, cfg.threshold),
+            (TIME_LIMIT_A, cfg.time_limit),
+            (TIME_LATENCY_A, cfg.time_latency),
+            (TIME_WINDOW_A, cfg.time_window),
+        ];
+        for &(register, value) in &timing {
+            self.device.smbus_write_byte_data(register, value).chain_bus_err(|| {
+                ErrorKind::FailedToWriteRegister
+            })?;
+        }
+
+        let mut ctrl_reg3_a = read_register!(self.device, CTRL_REG3_A, CtrlReg3A)?;
+        ctrl_reg3_a.set(CtrlReg3A::I1_CLICK, !cfg.events.is_empty());
+        write_register!(self.device, CTRL_REG3_A, ctrl_reg3_a)?;
+
+        // Enable the events last, so that they use the new timing.
+        let click_cfg_a = cfg.events;
+        write_register!(self.device, CLICK_CFG_A, click_cfg_a)?;
+
+        Ok(())
+    }
+
+    /// Read which click was detected.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// let click = sensor.read_click_source()?;
+    /// if click.active && click.double {
+    ///     println!("Double tap");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_click_source(&mut self) -> Result<ClickSource> {
+        use crate::registers::{CLICK_SRC_A, ClickSrcA};
+
+        let flags = read_register!(self.device, CLICK_SRC_A, ClickSrcA)?;
+        Ok(ClickSource::from_bits(flags))
+    }
+
+    /// Configure the high pass filter.
+    ///
+    /// In `HighPassMode::Reference`, the reference value is written first,
+    /// so that it is in place before the filter is switched on.
+    /// See `HighPassConfig` for an example.
+    pub fn configure_high_pass(&mut self, cfg: HighPassConfig) -> Result<()> {
+        use crate::registers::{CTRL_REG2_A, REFERENCE_A};
+
+        if cfg.mode == HighPassMode::Reference {
+            self.device.smbus_write_byte_data(REFERENCE_A, cfg.reference).chain_bus_err(|| {
+                ErrorKind::FailedToWriteRegister
+            })?;
+        }
+
+        let ctrl_reg2_a = cfg.mode.bits() | cfg.cutoff.bits() | cfg.paths;
+        write_register!(self.device, CTRL_REG2_A, ctrl_reg2_a)?;
+
+        Ok(())
+    }
+
+    /// Reset the high pass filter, so that the current acceleration reads as zero.
+    ///
+    /// In `HighPassMode::Normal`, reading REFERENCE_A resets the filter,
+    /// so this is useful after the sensor has been moved to a new resting position.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.reset_reference()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reset_reference(&mut self) -> Result<()> {
+        use crate::registers::REFERENCE_A;
+
+        self.device.smbus_read_byte_data(REFERENCE_A).chain_bus_err(|| {
+            ErrorKind::FailedToReadRegister
+        })?;
+        Ok(())
+    }
+
+    /// Set the operating mode, trading resolution for power.
+    ///
+    /// This also changes the sensitivity used by `read_acceleration`
+    /// and `read_acceleration_g`.
+    /// Fails with `ErrorKind::InvalidArgument` if the current data rate
+    /// is not available in the new mode; see `set_data_rate`.
+    ///
+    /// ```no_run
+    /// # use lsm303::accelerometer::{Accelerometer, AccelMode};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.set_operating_mode(AccelMode::LowPower)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_operating_mode(&mut self, mode: AccelMode) -> Result<()> {
+        if !self.rate.supports(mode) {
+            bail!(ErrorKind::InvalidArgument);
+        }
+        self.write_operating_mode(mode)
+    }
+
+    /// Write the mode bits, and cache the mode.
+    fn write_operating_mode(&mut self, mode: AccelMode) -> Result<()> {
+        use crate::registers::{CTRL_REG1_A, CTRL_REG4_A, CtrlReg1A, CtrlReg4A};
+
+        let mut ctrl_reg1_a = read_register!(self.device, CTRL_REG1_A, CtrlReg1A)?;
+        ctrl_reg1_a.set(CtrlReg1A::LPen, mode == AccelMode::LowPower);
+        write_register!(self.device, CTRL_REG1_A, ctrl_reg1_a)?;
+
+        let mut ctrl_reg4_a = read_register!(self.device, CTRL_REG4_A, CtrlReg4A)?;
+        ctrl_reg4_a.set(CtrlReg4A::HR, mode == AccelMode::HighResolution);
+        write_register!(self.device, CTRL_REG4_A, ctrl_reg4_a)?;
+        log_info!("accelerometer operating mode set to {:?}", mode);
+
+        self.mode = mode;
+
+        Ok(())
+    }
+
+    /// Get the current operating mode of the accelerometer.
+    ///
+    /// ```no_run
+    /// # use lsm303::accelerometer::{Accelerometer, AccelMode};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// assert_eq!(sensor.operating_mode(), AccelMode::HighResolution);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn operating_mode(&self) -> AccelMode {
+        self.mode
+    }
+
+    /// Set the mode of the FIFO.
+    ///
+    /// The FIFO is enabled for every mode except `FifoMode::Bypass`.
+    /// Changing modes through `Bypass` discards any stored samples.
+    ///
+    /// ```no_run
+    /// # use lsm303::accelerometer::{Accelerometer, FifoMode, Rate};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.set_data_rate(Rate::Rate400Hz)?;
+    /// sensor.set_fifo_mode(FifoMode::Stream)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_fifo_mode(&mut self, mode: FifoMode) -> Result<()> {
+        use crate::registers::{CTRL_REG5_A, FIFO_CTRL_REG_A, CtrlReg5A, FifoCtrlRegA};
+        type R = FifoCtrlRegA;
+
+        let mut ctrl_reg5_a = read_register!(self.device, CTRL_REG5_A, CtrlReg5A)?;
+        ctrl_reg5_a.set(CtrlReg5A::FIFO_EN, mode != FifoMode::Bypass);
+        write_register!(self.device, CTRL_REG5_A, ctrl_reg5_a)?;
+
+        let mut flags = read_register!(self.device, FIFO_CTRL_REG_A, FifoCtrlRegA)?;
+        flags.remove(R::FM1 | R::FM0);
+        flags.insert(mode.bits());
+        write_register!(self.device, FIFO_CTRL_REG_A, flags)?;
+
+        Ok(())
+    }
+
+    /// Set the FIFO level at which the watermark flag is raised.
+    ///
+    /// Only the low 5 bits are used.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.set_fifo_watermark(24)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_fifo_watermark(&mut self, level: u8) -> Result<()> {
+        use crate::registers::{FIFO_CTRL_REG_A, FifoCtrlRegA};
+        type R = FifoCtrlRegA;
+
+        let mut flags = read_register!(self.device, FIFO_CTRL_REG_A, FifoCtrlRegA)?;
+        flags.remove(R::FTH4 | R::FTH3 | R::FTH2 | R::FTH1 | R::FTH0);
+        flags.insert(FifoCtrlRegA::from_bits_truncate(level & 0x1F));
+        write_register!(self.device, FIFO_CTRL_REG_A, flags)?;
+
+        Ok(())
+    }
+
+    /// Read the level of the FIFO, along with the watermark and overrun flags.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// let status = sensor.fifo_status()?;
+    /// if status.overrun {
+    ///     println!("Some samples were lost");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fifo_status(&mut self) -> Result<FifoStatus> {
+        use crate::registers::{FIFO_SRC_REG_A, FifoSrcRegA};
+
+        let flags = read_register!(self.device, FIFO_SRC_REG_A, FifoSrcRegA)?;
+        Ok(FifoStatus::from_bits(flags))
+    }
+
+    /// Read the number of unread samples in the FIFO.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// println!("{} samples waiting", sensor.fifo_level()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fifo_level(&mut self) -> Result<u8> {
+        Ok(self.fifo_status()?.level)
+    }
+
+    /// Drain the FIFO, appending the raw samples to `out`.
+    ///
+    /// All of the available samples are read in a single burst.
+    /// Returns the number of samples that were read.
+    ///
+    /// ```no_run
+    /// # use lsm303::accelerometer::{Accelerometer, FifoMode};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.set_fifo_mode(FifoMode::Stream)?;
+    /// let mut samples = Vec::new();
+    /// loop {
+    ///     sensor.read_fifo(&mut samples)?;
+    ///     // ...
+    ///     samples.clear();
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_fifo(&mut self, out: &mut Vec<Vector3<i16>>) -> Result<usize> {
+        let count = usize::from(self.fifo_level()?);
+        if count == 0 {
+            return Ok(0);
+        }
+
+        // With the FIFO enabled, auto-increment wraps from OUT_Z_H_A back to OUT_X_L_A.
+        let mut bytes = [0; FIFO_DEPTH * 6];
+        let len = count * 6;
+        read_registers!(
+            self.device,
+            registers::block_read_addr(registers::OUT_X_L_A),
+            bytes[..len]
+        )?;
+
+        out.extend(bytes[..len].chunks(6).map(|sample| self.decode_output(sample)));
+        Ok(count)
+    }
+
+    /// Enable or disable block data update, which is enabled by default.
+    ///
+    /// With block data update, the output registers of an axis are not updated
+    /// until both of its bytes have been read.
+    /// Without it, a new measurement can land between reading the low byte
+    /// and the high byte, and the sample combines halves of two measurements.
+    /// This shows up as an occasional sample that is wildly wrong,
+    /// most often at high data rates.
+    /// Only disable it if every read is timed to avoid the update.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.set_block_data_update(false)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_block_data_update(&mut self, enabled: bool) -> Result<()> {
+        use crate::registers::{CTRL_REG4_A, CtrlReg4A};
+
+        let mut flags = read_register!(self.device, CTRL_REG4_A, CtrlReg4A)?;
+        flags.set(CtrlReg4A::BDU, enabled);
+        write_register!(self.device, CTRL_REG4_A, flags)?;
+
+        Ok(())
+    }
+
+    /// Enable or disable the measurement of each axis.
+    ///
+    /// Disabling the axes that are not needed saves power.
+    /// Every read of the acceleration reports zero for a disabled axis;
+    /// see `enabled_axes`.
+    ///
+    /// ```no_run
+    /// # use lsm303::Accelerometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Accelerometer::new("/dev/i2c-1")?;
+    /// sensor.set_enabled_axes(false, false, true)?;
+    /// let vibration = sensor.read_acceleration_g()?.z;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_enabled_axes(&mut self, x: bool, y: bool, z: bool) -> Result<()> {
+        use crate::registers::{CTRL_REG1_A, CtrlReg1A};
+        type R = CtrlReg1A;
+
+        let mut flags = read_register!(self.device, CTRL_REG1_A, CtrlReg1A)?;
+        flags.set(R::Xen, x);
+        flags.set(R::Yen, y);
+        flags.set(R::Zen, z);
+        write_register!(self.device, CTRL_REG1_A, flags)?;
+
+        self.enabled_axes = Vector3 { x, y, z };
+
+        Ok(())
+    }
+
+    /// Which axes are being measured.
+    ///
+    /// All of them are, until `set_enabled_axes` is called.
+    pub fn enabled_axes(&self) -> Vector3<bool> {
+        self.enabled_axes
+    }
+
+    /// Write the ODR bits, without changing the cached rate.
+    fn write_data_rate(&mut self, rate: Rate) -> Result<()> {
+        use crate::registers::{CTRL_REG1_A, CtrlReg1A};
         type R = CtrlReg1A;
 
         let mut flags = read_register!(self.device, CTRL_REG1_A, CtrlReg1A)?;
         flags.remove(R::ODR3 | R::ODR2 | R::ODR1 | R::ODR0);
 
         let setting = match rate {
-            _ => CtrlReg1A::empty(),
+            Rate::PowerDown => R::empty(),
+            Rate::Rate1Hz => R::ODR0,
+            Rate::Rate10Hz => R::ODR1,
+            Rate::Rate25Hz => R::ODR1 | R::ODR0,
+            Rate::Rate50Hz => R::ODR2,
+            Rate::Rate100Hz => R::ODR2 | R::ODR0,
+            Rate::Rate200Hz => R::ODR2 | R::ODR1,
+            Rate::Rate400Hz => R::ODR2 | R::ODR1 | R::ODR0,
+            Rate::Rate1620Hz => R::ODR3,
+            Rate::Rate1344Hz | Rate::Rate5376Hz => R::ODR3 | R::ODR0,
         };
         flags.insert(setting);
 
         write_register!(self.device, CTRL_REG1_A, flags)?;
-        self.rate = rate;
 
         Ok(())
     }
@@ -297,3 +1756,139 @@ where
         &mut self.device
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::{AccelMode, Accelerometer, Orientation, Rate};
+    use crate::registers::{CTRL_REG1_A, CTRL_REG4_A, INT1_SOURCE_A, OUT_X_L_A};
+    use crate::test_util::MockI2CDevice;
+    use crate::ErrorKind;
+
+    // The accelerometer requests auto-increment, so a read of the output crosses from X to Y to Z.
+    #[test]
+    fn output_read_auto_increments() {
+        let mut device = MockI2CDevice::new();
+        device.strict_auto_increment();
+        for (i, value) in [0x10, 0x01, 0x20, 0x02, 0x30, 0x03].iter().enumerate() {
+            device.set_register(OUT_X_L_A + i as u8, *value);
+        }
+
+        let mut sensor = Accelerometer::from_i2c_device(device).unwrap();
+        let raw = sensor.read_raw_acceleration().unwrap();
+        assert_eq!((raw.x, raw.y, raw.z), (0x0110 >> 4, 0x0220 >> 4, 0x0330 >> 4));
+    }
+
+    // The output is read without the SMBus block read, which allocates.
+    #[test]
+    fn output_read_avoids_smbus_block_read() {
+        let mut sensor = Accelerometer::from_i2c_device(MockI2CDevice::new()).unwrap();
+        sensor.read_raw_acceleration().unwrap();
+        assert_eq!(sensor.block_reads(), 0);
+    }
+
+    // Each position of 6D recognition is decoded from INT1_SRC_A, and anything else is unknown.
+    #[test]
+    fn orientation_decodes_each_face() {
+        let mut sensor = Accelerometer::from_i2c_device(MockI2CDevice::new()).unwrap();
+        let faces = [
+            (0x42, Orientation::XUp),
+            (0x41, Orientation::XDown),
+            (0x48, Orientation::YUp),
+            (0x44, Orientation::YDown),
+            (0x60, Orientation::ZUp),
+            (0x50, Orientation::ZDown),
+        ];
+        for &(bits, face) in &faces {
+            sensor.set_register(INT1_SOURCE_A, bits);
+            assert_eq!(sensor.read_orientation().unwrap(), face);
+        }
+
+        for &bits in &[0x00, 0x20, 0x40, 0x62] {
+            sensor.set_register(INT1_SOURCE_A, bits);
+            match *sensor.read_orientation().err().unwrap().kind() {
+                ErrorKind::OrientationUnknown => {}
+                ref kind => panic!("unexpected error: {}", kind),
+            }
+        }
+    }
+
+    // A disabled axis of the accelerometer reads as zero.
+    #[test]
+    fn disabled_axis_reads_zero() {
+        let mut device = MockI2CDevice::new();
+        for (i, value) in [0x10, 0x01, 0x20, 0x02, 0x30, 0x03].iter().enumerate() {
+            device.set_register(OUT_X_L_A + i as u8, *value);
+        }
+
+        let mut sensor = Accelerometer::from_i2c_device(device).unwrap();
+        sensor.set_enabled_axes(true, false, true).unwrap();
+        assert_eq!(sensor.register(CTRL_REG1_A) & 0x07, 0x05);
+        let raw = sensor.read_raw_acceleration().unwrap();
+        assert_eq!((raw.x, raw.y, raw.z), (0x0110 >> 4, 0, 0x0330 >> 4));
+        assert!(!sensor.enabled_axes().y);
+    }
+
+    // Block data update is enabled on initialization, and can be turned off.
+    #[test]
+    fn block_data_update_enabled_by_default() {
+        let mut sensor = Accelerometer::from_i2c_device(MockI2CDevice::new()).unwrap();
+        assert_eq!(sensor.register(CTRL_REG4_A) & 0x80, 0x80);
+        sensor.set_block_data_update(false).unwrap();
+        assert_eq!(sensor.register(CTRL_REG4_A) & 0x80, 0x00);
+    }
+
+    // The sample interval follows the data rate, and the variant.
+    #[test]
+    fn sample_interval_follows_data_rate() {
+        let mut sensor = Accelerometer::from_i2c_device(MockI2CDevice::new()).unwrap();
+        assert_eq!(sensor.sample_interval_us(), Some(20_000));
+        sensor.set_data_rate(Rate::PowerDown).unwrap();
+        assert_eq!(sensor.sample_interval(), None);
+    }
+
+    // The defaults of the settings are those of a newly initialized sensor.
+    #[test]
+    fn defaults_match_initialized_sensor() {
+        let sensor = Accelerometer::from_i2c_device(MockI2CDevice::new()).unwrap();
+        assert_eq!(sensor.data_rate(), Rate::default());
+        assert_eq!(sensor.operating_mode(), AccelMode::default());
+    }
+
+    // The fastest rates switch to the operating mode that provides them,
+    // and a mode without the current rate is rejected.
+    #[test]
+    fn fastest_rates_select_their_mode() {
+        let mut sensor = Accelerometer::from_i2c_device(MockI2CDevice::new()).unwrap();
+        let table = [
+            (Rate::Rate1620Hz, 0x8F, AccelMode::LowPower),
+            (Rate::Rate5376Hz, 0x9F, AccelMode::LowPower),
+            (Rate::Rate1344Hz, 0x97, AccelMode::Normal),
+            (Rate::Rate400Hz, 0x77, AccelMode::Normal),
+        ];
+        for &(rate, ctrl_reg1_a, mode) in &table {
+            sensor.set_data_rate(rate).unwrap();
+            assert_eq!(sensor.register(CTRL_REG1_A), ctrl_reg1_a, "{:?}", rate);
+            assert_eq!(sensor.operating_mode(), mode, "{:?}", rate);
+            assert_eq!(sensor.data_rate(), rate);
+        }
+        assert_eq!(sensor.register(CTRL_REG4_A) & 0x08, 0x00);
+
+        sensor.set_data_rate(Rate::Rate5376Hz).unwrap();
+        match *sensor.set_operating_mode(AccelMode::HighResolution).unwrap_err().kind() {
+            ErrorKind::InvalidArgument => {}
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+        assert_eq!(sensor.operating_mode(), AccelMode::LowPower);
+    }
+
+    // The old name of `set_data_rate` still works.
+    #[test]
+    #[allow(deprecated)]
+    fn set_rate_forwards_to_set_data_rate() {
+        let mut sensor = Accelerometer::from_i2c_device(MockI2CDevice::new()).unwrap();
+        sensor.set_rate(Rate::Rate100Hz).unwrap();
+        assert_eq!(sensor.data_rate(), Rate::Rate100Hz);
+        assert_eq!(sensor.register(CTRL_REG1_A) & 0xF0, 0x50);
+    }
+}
@@ -0,0 +1,228 @@
+//! Asynchronous reads from the magnetometer, using `embedded-hal-async`.
+//!
+//! The blocking sensors stall the caller for every transfer,
+//! which blocks every other task on a cooperative executor such as Embassy.
+//! `AsyncMagnetometer` yields while each transfer is in flight instead.
+//! It shares its configuration and register layout with `Magnetometer`,
+//! and is created with `MagnetometerBuilder::build_async`.
+//!
+//! Enable the `async` feature to use this module.
+//! It does not need `std`, so it builds for bare-metal targets
+//! with `default-features = false`.
+//!
+//! ```no_run
+//! # extern crate embedded_hal_async;
+//! # extern crate lsm303;
+//! # use embedded_hal_async::i2c::I2c;
+//! use lsm303::magnetometer::MagnetometerBuilder;
+//!
+//! // `i2c` is the async bus provided by your HAL crate.
+//! async fn run<I2C: I2c>(i2c: I2C) -> lsm303::Result<()> {
+//!     let mut sensor = MagnetometerBuilder::new().build_async(i2c, 0x1E).await?;
+//!     loop {
+//!         let field = sensor.read_magnetic_field_gauss().await?;
+//!         // ...
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+
+use crate::common::{AxisRemap, Vector3};
+use crate::errors::{ErrorKind, Result, ResultExt};
+#[cfg(feature = "std")]
+use crate::magnetometer::MagneticField;
+use crate::magnetometer::{self, Gain, OutputLayout, Variant};
+use crate::registers::MagRegister;
+use core::fmt::Debug;
+use embedded_hal_async::i2c::I2c;
+#[cfg(feature = "std")]
+use std::io;
+
+
+/// Interface to an LSM303 magnetometer on an async I2C bus.
+pub struct AsyncMagnetometer<I2C> {
+    i2c: I2C,
+    address: u8,
+    variant: Variant,
+    gain: Gain,
+    axis_remap: AxisRemap,
+    output_register: MagRegister,
+    output_layout: OutputLayout,
+}
+
+
+/// Convert an error from the bus, which is only known to implement `Debug`.
+#[cfg(feature = "std")]
+pub(crate) fn bus_error<E: Debug>(error: E) -> io::Error {
+    io::Error::other(format!("I2C bus error: {:?}", error))
+}
+
+
+/// Without `std`, the error from the bus is passed on, to be replaced by its context.
+#[cfg(not(feature = "std"))]
+pub(crate) fn bus_error<E: Debug>(error: E) -> E {
+    error
+}
+
+
+impl<I2C> AsyncMagnetometer<I2C>
+where
+    I2C: I2c,
+{
+    /// Wrap a bus whose magnetometer has already been configured.
+    ///
+    /// The output is read from the default registers of the variant.
+    pub(crate) fn new(
+        i2c: I2C,
+        address: u8,
+        variant: Variant,
+        gain: Gain,
+        axis_remap: AxisRemap,
+    ) -> AsyncMagnetometer<I2C> {
+        AsyncMagnetometer {
+            i2c,
+            address,
+            variant,
+            gain,
+            axis_remap,
+            output_register: variant.output_register(),
+            output_layout: variant.output_layout(),
+        }
+    }
+
+    /// Read the magnetometer, returning the magnetic field as a vector.
+    ///
+    /// The units come from `dimensioned`, which requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub async fn read_magnetic_field(&mut self) -> Result<MagneticField> {
+        let raw = self.read_raw_magnetic_field().await?;
+        let out = magnetometer::to_tesla(raw, self.variant.lsb_per_gauss(self.gain));
+        Ok(self.axis_remap.apply(out))
+    }
+
+    /// Read the magnetometer, returning the magnetic field in Gauss.
+    pub async fn read_magnetic_field_gauss(&mut self) -> Result<Vector3<f32>> {
+        let raw = self.read_raw_magnetic_field().await?;
+        let out = magnetometer::to_gauss(raw, self.variant.lsb_per_gauss(self.gain));
+        Ok(self.axis_remap.apply(out))
+    }
+
+    /// Read the magnetometer, returning the raw output of each axis.
+    ///
+    /// The axes are those of the chip, ignoring the axis remapping.
+    pub async fn read_raw_magnetic_field(&mut self) -> Result<Vector3<i16>> {
+        let mut bytes = [0; 6];
+        self.i2c
+            .write_read(self.address, &[self.output_register.addr()], &mut bytes)
+            .await
+            .map_err(bus_error)
+            .chain_err(|| ErrorKind::FailedToReadRegister)?;
+        Ok(self.output_layout.decode(bytes))
+    }
+
+    /// Check whether a new measurement is available.
+    pub async fn data_ready(&mut self) -> Result<bool> {
+        let mut status = [0];
+        self.i2c
+            .write_read(self.address, &[self.variant.status_register().addr()], &mut status)
+            .await
+            .map_err(bus_error)
+            .chain_err(|| ErrorKind::FailedToReadRegister)?;
+        Ok(self.variant.data_ready(status[0]))
+    }
+
+    /// Get the variant of the LSM303.
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Get the current gain of the magnetometer.
+    pub fn gain(&self) -> Gain {
+        self.gain
+    }
+
+    /// Read the magnetic field from other output registers.
+    ///
+    /// See `Magnetometer::set_output_layout`.
+    pub fn set_output_layout(&mut self, register: MagRegister, layout: OutputLayout) {
+        self.output_register = register;
+        self.output_layout = layout;
+    }
+
+    /// Get the first output register, and the layout of the output.
+    pub fn output_layout(&self) -> (MagRegister, OutputLayout) {
+        (self.output_register, self.output_layout)
+    }
+
+    /// Release the underlying bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncMagnetometer;
+    use crate::magnetometer::{Magnetometer, OutputLayout};
+    use crate::registers::{MagRegister, OUTX_L_REG_M};
+    use crate::test_util::MockI2CDevice;
+    use embedded_hal_async::i2c::{ErrorType, I2c, Operation, SevenBitAddress};
+    use i2cdev::core::I2CDevice;
+    use std::convert::Infallible;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    /// An async bus on the mock, whose transfers complete immediately.
+    struct AsyncMock(MockI2CDevice);
+
+    impl ErrorType for AsyncMock {
+        type Error = Infallible;
+    }
+
+    impl I2c for AsyncMock {
+        async fn transaction(
+            &mut self,
+            _address: SevenBitAddress,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Infallible> {
+            for operation in operations {
+                match operation {
+                    Operation::Write(data) => self.0.write(data).unwrap(),
+                    Operation::Read(buffer) => self.0.read(buffer).unwrap(),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Run a future whose transfers never wait.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut context = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    // Converting a configured magnetometer keeps its output registers, so a clone with another
+    // register map reads the same field on the async bus.
+    #[test]
+    fn into_async_carries_over_the_output_layout() {
+        let mut device = MockI2CDevice::magnetometer();
+        for (i, value) in [0x01, 0x02, 0x03, 0x04, 0x05, 0x06].iter().enumerate() {
+            device.set_register(OUTX_L_REG_M + i as u8, *value);
+        }
+
+        let mut sensor = Magnetometer::from_i2c_device(device.clone()).unwrap();
+        sensor.set_output_layout(MagRegister::OutxLReg, OutputLayout::XyzLittleEndian);
+        let mut sensor: AsyncMagnetometer<_> = sensor.into_async(AsyncMock(device), 0x1E);
+        assert_eq!(sensor.output_layout(), (MagRegister::OutxLReg, OutputLayout::XyzLittleEndian));
+
+        let raw = block_on(sensor.read_raw_magnetic_field()).unwrap();
+        assert_eq!((raw.x, raw.y, raw.z), (0x0201, 0x0403, 0x0605));
+    }
+}
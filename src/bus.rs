@@ -0,0 +1,380 @@
+//! Sharing one I2C bus between several devices.
+//!
+//! The accelerometer and magnetometer are on the same bus,
+//! but at different addresses.
+//! Rather than open the bus once for each sensor,
+//! a `BusProxy` can be created for each address,
+//! which points the shared bus at its device before every transaction.
+//!
+//! # Concurrency
+//!
+//! The bus is shared behind a `Mutex`, so the proxies can be moved to
+//! different threads. Each transaction holds the lock from setting the address
+//! until it completes, so transactions from different proxies never interleave.
+//! The sensors read registers with `I2CWriteRead::write_read`, which a proxy
+//! runs under a single lock, so another device cannot move the register pointer
+//! between the write of the register address and the read.
+//! A Linux bus can still be shared with other processes, which this does not guard against.
+//!
+//! # Other devices on the bus
+//!
+//! A `BusProxy` can address any device, not just the LSM303,
+//! and it implements `I2CDevice`, so drivers for the other sensors on the bus
+//! can be given proxies from the same `Arc<Mutex<_>>`.
+//! Each proxy sets its own address before every transaction,
+//! so the drivers cannot misdirect each other.
+//!
+//! The sensors only need an `I2CWriteRead` device whose error converts into `Error`,
+//! such as `io::Error`, so a bus manager from elsewhere can be used instead,
+//! by implementing `I2CDevice` and `I2CWriteRead` for its handles.
+//! With `embedded-hal`, a proxy from the `shared-bus` crate
+//! can be passed to `Magnetometer::from_embedded_hal`; see the `hal` module.
+
+use crate::registers::{CTRL_REG1_A, IRA_REG_M};
+use i2cdev::core::I2CDevice;
+use i2cdev::linux::{LinuxI2CDevice, LinuxI2CError};
+use std::sync::{Arc, Mutex};
+
+
+/// The 7 bit I2C address of the accelerometer.
+pub const ACCELEROMETER_ADDRESS: u16 = 0x32 >> 1;
+
+
+/// The 7 bit I2C address of the magnetometer.
+pub const MAGNETOMETER_ADDRESS: u16 = 0x3C >> 1;
+
+
+/// Probe the addresses of the accelerometer and magnetometer,
+/// returning those at which a device responded.
+///
+/// Each address is probed by reading one register.
+/// A response only means that something is at the address;
+/// constructing the sensor checks that it is an LSM303.
+/// The bus is left pointing at the last address probed.
+///
+/// ```no_run
+/// # extern crate lsm303;
+/// # extern crate i2cdev;
+/// # use i2cdev::linux::LinuxI2CDevice;
+/// # use lsm303::bus::{scan, MAGNETOMETER_ADDRESS};
+/// # fn main() { test().unwrap(); }
+/// # fn test() -> lsm303::Result<()> {
+/// let mut bus = LinuxI2CDevice::new("/dev/i2c-1", MAGNETOMETER_ADDRESS)?;
+/// if !scan(&mut bus).contains(&MAGNETOMETER_ADDRESS) {
+///     println!("No magnetometer found; check the wiring");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn scan<Bus>(bus: &mut Bus) -> Vec<u16>
+where
+    Bus: I2CBus,
+{
+    [(ACCELEROMETER_ADDRESS, CTRL_REG1_A), (MAGNETOMETER_ADDRESS, IRA_REG_M)]
+        .iter()
+        .filter(|&&(address, register)| {
+            bus.set_address(address)
+                .and_then(|_| bus.smbus_read_byte_data(register))
+                .is_ok()
+        })
+        .map(|&(address, _)| address)
+        .collect()
+}
+
+
+/// An I2C device that can write and then read in a single transfer.
+///
+/// The read follows the write with a repeated start, so nothing else can use
+/// the bus in between. The sensors read registers this way, writing the
+/// register address and then reading the values into a buffer.
+///
+/// The provided method is a plain write followed by a plain read,
+/// for devices that cannot do better; `impl I2CWriteRead for MyDevice {}`
+/// is enough to use the sensors with one.
+pub trait I2CWriteRead: I2CDevice {
+    /// Write `data`, and then fill `buffer` by reading.
+    fn write_read(&mut self, data: &[u8], buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.write(data)?;
+        self.read(buffer)
+    }
+}
+
+
+/// `i2cdev` does not expose the address of a `LinuxI2CDevice`,
+/// which a combined transfer needs, so this is a plain write and then a plain read.
+/// `FdI2CDevice` combines them.
+impl I2CWriteRead for LinuxI2CDevice {}
+
+
+/// An I2C bus that can address any device on it.
+pub trait I2CBus: I2CWriteRead {
+    /// Direct subsequent transactions to the device at this address.
+    fn set_address(&mut self, address: u16) -> Result<(), Self::Error>;
+}
+
+
+impl I2CBus for LinuxI2CDevice {
+    fn set_address(&mut self, address: u16) -> Result<(), LinuxI2CError> {
+        use std::os::unix::io::AsRawFd;
+
+        set_slave_address(self.as_raw_fd(), address).map_err(LinuxI2CError::from)
+    }
+}
+
+
+/// Direct the transfers on a Linux I2C file descriptor to the device at this address.
+pub(crate) fn set_slave_address(
+    fd: ::std::os::unix::io::RawFd,
+    address: u16,
+) -> ::std::io::Result<()> {
+    use libc;
+    use std::io;
+
+    // See linux/i2c-dev.h
+    const I2C_SLAVE: libc::c_ulong = 0x0703;
+
+    let result = unsafe { libc::ioctl(fd, I2C_SLAVE as _, libc::c_ulong::from(address)) };
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+
+/// One device on a shared I2C bus.
+pub struct BusProxy<Bus> {
+    bus: Arc<Mutex<Bus>>,
+    address: u16,
+}
+
+
+impl<Bus> BusProxy<Bus>
+where
+    Bus: I2CBus,
+{
+    /// Address a device on the shared bus.
+    ///
+    /// ```no_run
+    /// # extern crate lsm303;
+    /// # extern crate i2cdev;
+    /// # use i2cdev::linux::LinuxI2CDevice;
+    /// # use lsm303::bus::{BusProxy, ACCELEROMETER_ADDRESS, MAGNETOMETER_ADDRESS};
+    /// # use lsm303::{Accelerometer, Magnetometer};
+    /// # use std::sync::{Arc, Mutex};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let bus = Arc::new(Mutex::new(LinuxI2CDevice::new("/dev/i2c-1", MAGNETOMETER_ADDRESS)?));
+    /// let mut magnetometer =
+    ///     Magnetometer::from_i2c_device(BusProxy::new(bus.clone(), MAGNETOMETER_ADDRESS))?;
+    /// let mut accelerometer =
+    ///     Accelerometer::from_i2c_device(BusProxy::new(bus, ACCELEROMETER_ADDRESS))?;
+    ///
+    /// let handle = std::thread::spawn(move || magnetometer.read_raw_magnetic_field());
+    /// let accel = accelerometer.read_raw_acceleration()?;
+    /// let field = handle.join().unwrap()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new(bus: Arc<Mutex<Bus>>, address: u16) -> BusProxy<Bus> {
+        BusProxy { bus, address }
+    }
+
+    /// Run a transaction against this device.
+    fn with_bus<F, R>(&mut self, f: F) -> Result<R, Bus::Error>
+    where
+        F: FnOnce(&mut Bus) -> Result<R, Bus::Error>,
+    {
+        // A panic on another thread does not leave the bus itself inconsistent.
+        let mut bus = self.bus.lock().unwrap_or_else(|e| e.into_inner());
+        bus.set_address(self.address)?;
+        f(&mut bus)
+    }
+}
+
+
+impl<Bus> I2CWriteRead for BusProxy<Bus>
+where
+    Bus: I2CBus,
+{
+    fn write_read(&mut self, data: &[u8], buffer: &mut [u8]) -> Result<(), Bus::Error> {
+        self.with_bus(|bus| bus.write_read(data, buffer))
+    }
+}
+
+
+impl<Bus> I2CDevice for BusProxy<Bus>
+where
+    Bus: I2CBus,
+{
+    type Error = Bus::Error;
+
+    fn read(&mut self, data: &mut [u8]) -> Result<(), Bus::Error> {
+        self.with_bus(|bus| bus.read(data))
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Bus::Error> {
+        self.with_bus(|bus| bus.write(data))
+    }
+
+    fn smbus_write_quick(&mut self, bit: bool) -> Result<(), Bus::Error> {
+        self.with_bus(|bus| bus.smbus_write_quick(bit))
+    }
+
+    fn smbus_read_byte(&mut self) -> Result<u8, Bus::Error> {
+        self.with_bus(|bus| bus.smbus_read_byte())
+    }
+
+    fn smbus_write_byte(&mut self, value: u8) -> Result<(), Bus::Error> {
+        self.with_bus(|bus| bus.smbus_write_byte(value))
+    }
+
+    fn smbus_read_byte_data(&mut self, register: u8) -> Result<u8, Bus::Error> {
+        self.with_bus(|bus| bus.smbus_read_byte_data(register))
+    }
+
+    fn smbus_write_byte_data(&mut self, register: u8, value: u8) -> Result<(), Bus::Error> {
+        self.with_bus(|bus| bus.smbus_write_byte_data(register, value))
+    }
+
+    fn smbus_read_word_data(&mut self, register: u8) -> Result<u16, Bus::Error> {
+        self.with_bus(|bus| bus.smbus_read_word_data(register))
+    }
+
+    fn smbus_write_word_data(&mut self, register: u8, value: u16) -> Result<(), Bus::Error> {
+        self.with_bus(|bus| bus.smbus_write_word_data(register, value))
+    }
+
+    fn smbus_process_word(&mut self, register: u8, value: u16) -> Result<u16, Bus::Error> {
+        self.with_bus(|bus| bus.smbus_process_word(register, value))
+    }
+
+    fn smbus_read_block_data(&mut self, register: u8) -> Result<Vec<u8>, Bus::Error> {
+        self.with_bus(|bus| bus.smbus_read_block_data(register))
+    }
+
+    fn smbus_read_i2c_block_data(&mut self, register: u8, len: u8) -> Result<Vec<u8>, Bus::Error> {
+        self.with_bus(|bus| bus.smbus_read_i2c_block_data(register, len))
+    }
+
+    fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> Result<(), Bus::Error> {
+        self.with_bus(|bus| bus.smbus_write_block_data(register, values))
+    }
+
+    fn smbus_process_block(&mut self, register: u8, values: &[u8]) -> Result<(), Bus::Error> {
+        self.with_bus(|bus| bus.smbus_process_block(register, values))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{BusProxy, I2CWriteRead, ACCELEROMETER_ADDRESS, MAGNETOMETER_ADDRESS};
+    use crate::registers::{CTRL_REG1_A, MR_REG_M, OUT_X_H_M, OUT_X_L_M};
+    use crate::test_util::{MockI2CBus, MockI2CDevice};
+    use crate::{Accelerometer, Magnetometer};
+    use i2cdev::core::I2CDevice;
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+
+    // Through a shared bus, the register address is still followed by the read.
+    #[test]
+    fn register_address_is_followed_by_read() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(OUT_X_H_M, 0x01);
+        device.set_register(OUT_X_L_M, 0x2C);
+
+        let bus = Arc::new(Mutex::new(device));
+        let proxy = BusProxy::new(bus, MAGNETOMETER_ADDRESS);
+        let mut sensor = Magnetometer::from_i2c_device(proxy).unwrap();
+        assert_eq!(sensor.read_raw_magnetic_field().unwrap().x, 300);
+    }
+
+    // The sensors work through a bus manager from outside this crate, shared with the driver of
+    // another device.
+    #[test]
+    fn works_through_external_bus_manager() {
+        // A handle on a bus that is shared within one thread.
+        struct Proxy(Rc<RefCell<MockI2CDevice>>);
+
+        impl I2CDevice for Proxy {
+            type Error = io::Error;
+            fn read(&mut self, data: &mut [u8]) -> io::Result<()> {
+                self.0.borrow_mut().read(data)
+            }
+            fn write(&mut self, data: &[u8]) -> io::Result<()> {
+                self.0.borrow_mut().write(data)
+            }
+            fn smbus_write_quick(&mut self, bit: bool) -> io::Result<()> {
+                self.0.borrow_mut().smbus_write_quick(bit)
+            }
+            fn smbus_read_block_data(&mut self, register: u8) -> io::Result<Vec<u8>> {
+                self.0.borrow_mut().smbus_read_block_data(register)
+            }
+            fn smbus_read_i2c_block_data(&mut self, register: u8, len: u8) -> io::Result<Vec<u8>> {
+                self.0.borrow_mut().smbus_read_i2c_block_data(register, len)
+            }
+            fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> io::Result<()> {
+                self.0.borrow_mut().smbus_write_block_data(register, values)
+            }
+            fn smbus_process_block(&mut self, register: u8, values: &[u8]) -> io::Result<()> {
+                self.0.borrow_mut().smbus_process_block(register, values)
+            }
+            fn smbus_read_byte_data(&mut self, register: u8) -> io::Result<u8> {
+                self.0.borrow_mut().smbus_read_byte_data(register)
+            }
+            fn smbus_write_byte_data(&mut self, register: u8, value: u8) -> io::Result<()> {
+                self.0.borrow_mut().smbus_write_byte_data(register, value)
+            }
+        }
+
+        // The provided `write_read` is enough.
+        impl I2CWriteRead for Proxy {}
+
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(OUT_X_H_M, 0x01);
+        device.set_register(OUT_X_L_M, 0x2C);
+        let bus = Rc::new(RefCell::new(device));
+
+        let mut sensor = Magnetometer::from_i2c_device(Proxy(bus.clone())).unwrap();
+        let mut other = Proxy(bus);
+        assert_eq!(sensor.read_raw_magnetic_field().unwrap().x, 300);
+        other.smbus_read_byte_data(0x75).unwrap();
+        assert_eq!(sensor.read_raw_magnetic_field().unwrap().x, 300);
+    }
+
+    // Each proxy reaches only the device at its own address, and nothing acknowledges an address
+    // without a device.
+    #[test]
+    fn proxies_reach_their_own_devices() {
+        let mut magnetometer = MockI2CDevice::magnetometer();
+        magnetometer.set_register(OUT_X_H_M, 0x01);
+        magnetometer.set_register(OUT_X_L_M, 0x2C);
+        let mut bus = MockI2CBus::new();
+        bus.attach(MAGNETOMETER_ADDRESS, magnetometer);
+        bus.attach(ACCELEROMETER_ADDRESS, MockI2CDevice::new());
+        let bus = Arc::new(Mutex::new(bus));
+
+        let proxy = BusProxy::new(bus.clone(), MAGNETOMETER_ADDRESS);
+        let mut magnetometer = Magnetometer::from_i2c_device(proxy).unwrap();
+        let proxy = BusProxy::new(bus.clone(), ACCELEROMETER_ADDRESS);
+        let mut accelerometer = Accelerometer::from_i2c_device(proxy).unwrap();
+        assert_eq!(magnetometer.read_raw_magnetic_field().unwrap().x, 300);
+        accelerometer.read_raw_acceleration().unwrap();
+        assert_eq!(magnetometer.read_raw_magnetic_field().unwrap().x, 300);
+
+        {
+            let bus = bus.lock().unwrap();
+            let writes = bus.device(ACCELEROMETER_ADDRESS).writes();
+            assert!(writes.iter().all(|&(register, _)| register >= CTRL_REG1_A));
+            let writes = bus.device(MAGNETOMETER_ADDRESS).writes();
+            assert!(writes.iter().all(|&(register, _)| register <= MR_REG_M));
+        }
+
+        let mut nobody = BusProxy::new(bus, 0x50);
+        let error = nobody.smbus_read_byte_data(0x00).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(libc::ENXIO));
+    }
+}
@@ -0,0 +1,306 @@
+//! Interface to both sensors over a single I2C bus.
+
+use crate::accelerometer::Accelerometer;
+use crate::bus::{BusProxy, I2CBus, ACCELEROMETER_ADDRESS, MAGNETOMETER_ADDRESS};
+use crate::common::Vector3;
+use crate::compass::{self, Euler};
+use crate::errors::{Error, ErrorKind, Result, ResultExt};
+use i2cdev::linux::LinuxI2CDevice;
+use crate::magnetometer::{self, DataRate, Magnetometer};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+
+/// Interface to both the accelerometer and the magnetometer of an LSM303.
+///
+/// The bus is opened once, and shared between the two sensors.
+/// Both sensors are reached through `&mut self`, so one `Lsm303` only runs
+/// one transaction at a time; share it between threads behind a `Mutex`,
+/// or `split` it to give each sensor its own thread.
+/// See the `bus` module for how the shared bus keeps transactions apart.
+///
+/// ```
+/// # extern crate i2cdev;
+/// # extern crate lsm303;
+/// # use i2cdev::linux::LinuxI2CDevice;
+/// fn assert_send<T: Send>() {}
+/// assert_send::<lsm303::Lsm303<LinuxI2CDevice>>();
+/// ```
+pub struct Lsm303<Bus>
+where
+    Bus: I2CBus,
+{
+    accelerometer: Accelerometer<BusProxy<Bus>>,
+    magnetometer: Magnetometer<BusProxy<Bus>>,
+}
+
+
+/// Readings of both sensors, taken together.
+///
+/// See `Lsm303::spawn_sampler`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CombinedSnapshot {
+    /// The raw output of each axis of the accelerometer.
+    pub acceleration: Vector3<i16>,
+    /// The raw output of each axis of the magnetometer.
+    pub magnetic_field: Vector3<i16>,
+    /// When the readings were taken.
+    pub timestamp: Instant,
+}
+
+
+impl Lsm303<LinuxI2CDevice> {
+    /// Initialize both sensors for a Linux I2C bus.
+    ///
+    /// ```
+    /// # use lsm303::Lsm303;
+    /// let sensor = Lsm303::new("/dev/i2c-1");
+    /// ```
+    pub fn new<Path>(path: Path) -> Result<Lsm303<LinuxI2CDevice>>
+    where
+        Path: AsRef<::std::path::Path>,
+    {
+        let bus = LinuxI2CDevice::new(&path, ACCELEROMETER_ADDRESS).chain_err(|| {
+            ErrorKind::FailedToOpenDevice
+        })?;
+
+        Lsm303::from_bus(bus)
+    }
+}
+
+
+impl<Bus> Lsm303<Bus>
+where
+    Bus: I2CBus,
+    Error: From<Bus::Error>,
+    Bus::Error: Send + 'static,
+{
+    /// Initialize both sensors, given an open I2C bus.
+    ///
+    /// ```no_run
+    /// # extern crate lsm303;
+    /// # use lsm303::Lsm303;
+    /// # extern crate i2cdev;
+    /// # use i2cdev::linux::LinuxI2CDevice;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// // The address is replaced for each transaction.
+    /// let bus = LinuxI2CDevice::new("/dev/i2c-1", 0x32 >> 1)?;
+    /// let sensor = Lsm303::from_bus(bus)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_bus(bus: Bus) -> Result<Lsm303<Bus>> {
+        let bus = Arc::new(Mutex::new(bus));
+
+        let device = BusProxy::new(bus.clone(), ACCELEROMETER_ADDRESS);
+        let accelerometer = Accelerometer::from_i2c_device(device)?;
+
+        let device = BusProxy::new(bus, MAGNETOMETER_ADDRESS);
+        let magnetometer = Magnetometer::from_i2c_device(device)?;
+
+        let lsm303 = Lsm303 {
+            accelerometer,
+            magnetometer,
+        };
+        Ok(lsm303)
+    }
+
+    /// Access the accelerometer.
+    ///
+    /// ```no_run
+    /// # use lsm303::Lsm303;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Lsm303::new("/dev/i2c-1")?;
+    /// let accel = sensor.accelerometer().read_acceleration()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn accelerometer(&mut self) -> &mut Accelerometer<BusProxy<Bus>> {
+        &mut self.accelerometer
+    }
+
+    /// Access the magnetometer.
+    ///
+    /// ```no_run
+    /// # use lsm303::Lsm303;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Lsm303::new("/dev/i2c-1")?;
+    /// let field = sensor.magnetometer().read_magnetic_field()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn magnetometer(&mut self) -> &mut Magnetometer<BusProxy<Bus>> {
+        &mut self.magnetometer
+    }
+
+    /// Separate the sensors, which keep sharing the bus.
+    ///
+    /// ```no_run
+    /// # use lsm303::Lsm303;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let (mut accelerometer, mut magnetometer) = Lsm303::new("/dev/i2c-1")?.split();
+    /// let handle = std::thread::spawn(move || magnetometer.read_magnetic_field());
+    /// let accel = accelerometer.read_acceleration()?;
+    /// let field = handle.join().unwrap()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn split(self) -> (Accelerometer<BusProxy<Bus>>, Magnetometer<BusProxy<Bus>>) {
+        (self.accelerometer, self.magnetometer)
+    }
+
+    /// Read both sensors, returning the raw acceleration and magnetic field.
+    ///
+    /// ```no_run
+    /// # use lsm303::Lsm303;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Lsm303::new("/dev/i2c-1")?;
+    /// let (accel, field) = sensor.read_all()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_all(&mut self) -> Result<(Vector3<i16>, Vector3<i16>)> {
+        let accel = self.accelerometer.read_raw_acceleration()?;
+        let field = self.magnetometer.read_raw_magnetic_field()?;
+        Ok((accel, field))
+    }
+
+    /// Estimate the roll, pitch, and yaw of the board, in degrees.
+    ///
+    /// This reads both sensors once, and combines them with `compass::orientation`.
+    /// It is a static estimate, without filtering, so it is only accurate
+    /// when the board is still or moving slowly;
+    /// smooth the yaw with a `compass::CompassFilter` if it jitters.
+    ///
+    /// ```no_run
+    /// # use lsm303::Lsm303;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Lsm303::new("/dev/i2c-1")?;
+    /// let euler = sensor.estimate_orientation()?;
+    /// println!("Roll {}, pitch {}, yaw {}", euler.roll, euler.pitch, euler.yaw);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn estimate_orientation(&mut self) -> Result<Euler> {
+        let accel = self.accelerometer.read_acceleration_g()?;
+        let field = self.magnetometer.read_magnetic_field_gauss()?;
+        Ok(compass::orientation(field, accel))
+    }
+}
+
+
+impl<Bus> Lsm303<Bus>
+where
+    Bus: I2CBus + Send + 'static,
+    Error: From<Bus::Error>,
+    Bus::Error: Send + 'static,
+{
+    /// Move both sensors to a background thread, which reads them
+    /// at the given data rate of the magnetometer, and sends the readings over a channel.
+    ///
+    /// The data rate of the magnetometer is set first, and the thread keeps to its
+    /// `sample_interval`; the accelerometer keeps its own data rate,
+    /// which should be at least as fast.
+    /// As with `Magnetometer::spawn_sampler`, the thread stops once the receiver is dropped,
+    /// and the handle yields `Ok(())`, or the error if a read fails.
+    ///
+    /// ```no_run
+    /// # use lsm303::Lsm303;
+    /// # use lsm303::magnetometer::DataRate;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Lsm303::new("/dev/i2c-1")?;
+    /// let (handle, snapshots) = sensor.spawn_sampler(DataRate::Rate30Hz);
+    /// for snapshot in snapshots.iter().take(30) {
+    ///     println!("{} {}", snapshot.acceleration, snapshot.magnetic_field);
+    /// }
+    /// drop(snapshots);
+    /// handle.join().unwrap()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn spawn_sampler(
+        mut self,
+        rate: DataRate,
+    ) -> (JoinHandle<Result<()>>, Receiver<CombinedSnapshot>) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            self.magnetometer.set_data_rate(rate)?;
+            let period = self.magnetometer.sample_interval();
+            magnetometer::sample_periodically(period, &sender, || {
+                let timestamp = Instant::now();
+                let (acceleration, magnetic_field) = self.read_all()?;
+                Ok(CombinedSnapshot {
+                    acceleration,
+                    magnetic_field,
+                    timestamp,
+                })
+            })
+        });
+        (handle, receiver)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Lsm303;
+    use crate::bus::{ACCELEROMETER_ADDRESS, MAGNETOMETER_ADDRESS};
+    use crate::magnetometer::DataRate;
+    use crate::registers::{
+        OUT_X_H_M, OUT_X_L_M, OUT_Z_H_A, OUT_Z_H_M, OUT_Z_L_A, OUT_Z_L_M, SR_REG_M,
+    };
+    use crate::test_util::{MockI2CBus, MockI2CDevice};
+
+    /// A bus with both sensors, level and facing north.
+    fn level_bus() -> MockI2CBus {
+        let mut accelerometer = MockI2CDevice::new();
+        // 1 g on Z, at 1 mg per LSB, left justified by 4 bits.
+        accelerometer.set_register(OUT_Z_L_A, 0x80);
+        accelerometer.set_register(OUT_Z_H_A, 0x3E);
+
+        let mut magnetometer = MockI2CDevice::magnetometer();
+        // 0.2 G on X and -0.4 G on Z, at a gain of +/- 1.3 Gauss.
+        magnetometer.set_register(OUT_X_H_M, 0x00);
+        magnetometer.set_register(OUT_X_L_M, 0xDC);
+        magnetometer.set_register(OUT_Z_H_M, 0xFE);
+        magnetometer.set_register(OUT_Z_L_M, 0x78);
+        magnetometer.set_register(SR_REG_M, 0x01);
+
+        let mut bus = MockI2CBus::new();
+        bus.attach(ACCELEROMETER_ADDRESS, accelerometer);
+        bus.attach(MAGNETOMETER_ADDRESS, magnetometer);
+        bus
+    }
+
+    // The orientation combines both sensors; here the board is level, facing north.
+    #[test]
+    fn level_board_facing_north() {
+        let mut sensor = Lsm303::from_bus(level_bus()).unwrap();
+        let euler = sensor.estimate_orientation().unwrap();
+        assert!(euler.roll.abs() < 0.1);
+        assert!(euler.pitch.abs() < 0.1);
+        assert!(euler.yaw < 0.1 || euler.yaw > 359.9);
+    }
+
+    // The sampler reads both sensors, and stops once its receiver is dropped.
+    #[test]
+    fn sampler_streams_both_sensors() {
+        let sensor = Lsm303::from_bus(level_bus()).unwrap();
+        let (handle, snapshots) = sensor.spawn_sampler(DataRate::Rate220Hz);
+        let received: Vec<_> = snapshots.iter().take(2).collect();
+        assert_eq!(received[0].acceleration.z, 1000);
+        assert_eq!(received[0].magnetic_field.x, 220);
+        assert!(received[1].timestamp > received[0].timestamp);
+        drop(snapshots);
+        assert!(handle.join().unwrap().is_ok());
+    }
+}
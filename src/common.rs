@@ -1,9 +1,260 @@
 //! General types and functions.
 
+use crate::errors::{ErrorKind, Result};
+use core::fmt;
+use core::ops::Neg;
+
 /// A simple three dimensional vector.
+///
+/// With the `serde` feature, readings can be serialized directly.
+///
+/// ```
+/// # extern crate lsm303;
+/// # extern crate serde_json;
+/// # use lsm303::common::Vector3;
+/// # fn main() {
+/// # #[cfg(feature = "serde")] {
+/// let reading = Vector3 { x: 0.12f32, y: -0.03, z: 0.98 };
+/// let json = serde_json::to_string(&reading).unwrap();
+/// assert_eq!(json, r#"{"x":0.12,"y":-0.03,"z":0.98}"#);
+/// let back: Vector3<f32> = serde_json::from_str(&json).unwrap();
+/// assert_eq!(back, reading);
+/// # }
+/// # }
+/// ```
 #[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Vector3<T> {
     pub x: T,
     pub y: T,
     pub z: T,
 }
+
+
+/// Format as `(x, y, z)`.
+///
+/// Formatting options, such as the precision, apply to each component.
+///
+/// ```
+/// # use lsm303::common::Vector3;
+/// let v = Vector3 { x: 12i16, y: -3, z: 980 };
+/// assert_eq!(v.to_string(), "(12, -3, 980)");
+///
+/// let v = Vector3 { x: 0.123f32, y: -0.0349, z: 0.98 };
+/// assert_eq!(format!("{:.1}", v), "(0.1, -0.0, 1.0)");
+/// ```
+impl<T> fmt::Display for Vector3<T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("(")?;
+        self.x.fmt(f)?;
+        f.write_str(", ")?;
+        self.y.fmt(f)?;
+        f.write_str(", ")?;
+        self.z.fmt(f)?;
+        f.write_str(")")
+    }
+}
+
+
+/// These allocate, so they require the `std` feature.
+#[cfg(feature = "std")]
+impl Vector3<f32> {
+    /// Format a magnetic field in Gauss, to two decimal places.
+    ///
+    /// ```
+    /// # use lsm303::common::Vector3;
+    /// let field = Vector3 { x: 0.123f32, y: -0.0349, z: 0.98 };
+    /// assert_eq!(field.fmt_gauss(), "(0.12, -0.03, 0.98) G");
+    /// ```
+    pub fn fmt_gauss(&self) -> String {
+        format!("{:.2} G", self)
+    }
+
+    /// Format an acceleration in g's, to two decimal places.
+    ///
+    /// ```
+    /// # use lsm303::common::Vector3;
+    /// let accel = Vector3 { x: 0.0f32, y: 0.5, z: -1.0 };
+    /// assert_eq!(accel.fmt_g(), "(0.00, 0.50, -1.00) g");
+    /// ```
+    pub fn fmt_g(&self) -> String {
+        format!("{:.2} g", self)
+    }
+}
+
+
+/// Convert to an `(x, y, z)` tuple.
+///
+/// ```
+/// # use lsm303::common::Vector3;
+/// let v = Vector3 { x: 1, y: 2, z: 3 };
+/// let (x, y, z) = v.into();
+/// assert_eq!((x, y, z), (1, 2, 3));
+/// ```
+impl<T> From<Vector3<T>> for (T, T, T) {
+    fn from(v: Vector3<T>) -> (T, T, T) {
+        (v.x, v.y, v.z)
+    }
+}
+
+
+impl<T> Vector3<T> {
+    /// Apply a function to each component.
+    ///
+    /// ```
+    /// # use lsm303::common::Vector3;
+    /// let raw = Vector3 { x: 10i16, y: -20, z: 30 };
+    /// let scaled = raw.map(|v| f32::from(v) / 10.0);
+    /// assert_eq!(scaled, Vector3 { x: 1.0, y: -2.0, z: 3.0 });
+    /// ```
+    pub fn map<U, F>(self, f: F) -> Vector3<U>
+    where
+        F: Fn(T) -> U,
+    {
+        Vector3 {
+            x: f(self.x),
+            y: f(self.y),
+            z: f(self.z),
+        }
+    }
+}
+
+
+/// Iterate over the components, in the order `x`, `y`, `z`.
+///
+/// ```
+/// # use lsm303::common::Vector3;
+/// let v = Vector3 { x: 0.1f32, y: 0.2, z: 0.3 };
+/// let mut array = [0.0; 3];
+/// for (out, component) in array.iter_mut().zip(v) {
+///     *out = component;
+/// }
+/// assert_eq!(array, [0.1, 0.2, 0.3]);
+/// assert_eq!(v.into_iter().collect::<Vec<_>>(), vec![0.1, 0.2, 0.3]);
+/// ```
+impl<T> IntoIterator for Vector3<T> {
+    type Item = T;
+    type IntoIter = core::array::IntoIter<T, 3>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIterator::into_iter([self.x, self.y, self.z])
+    }
+}
+
+
+/// A physical axis of the chip, with a direction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Axis {
+    /// The positive X axis
+    PlusX,
+    /// The negative X axis
+    MinusX,
+    /// The positive Y axis
+    PlusY,
+    /// The negative Y axis
+    MinusY,
+    /// The positive Z axis
+    PlusZ,
+    /// The negative Z axis
+    MinusZ,
+}
+
+
+impl Axis {
+    /// The index of the physical axis, ignoring the direction.
+    fn index(&self) -> usize {
+        match *self {
+            Axis::PlusX | Axis::MinusX => 0,
+            Axis::PlusY | Axis::MinusY => 1,
+            Axis::PlusZ | Axis::MinusZ => 2,
+        }
+    }
+
+    /// Select this axis of a vector, negating it if necessary.
+    fn select<T>(&self, v: Vector3<T>) -> T
+    where
+        T: Neg<Output = T>,
+    {
+        match *self {
+            Axis::PlusX => v.x,
+            Axis::MinusX => -v.x,
+            Axis::PlusY => v.y,
+            Axis::MinusY => -v.y,
+            Axis::PlusZ => v.z,
+            Axis::MinusZ => -v.z,
+        }
+    }
+}
+
+
+/// A mapping from the axes of the chip to the axes of the board.
+///
+/// Boards often mount the chip rotated, or on the underside.
+/// Each logical axis is taken from one of the physical axes,
+/// optionally inverted.
+/// The default is the identity mapping.
+///
+/// ```
+/// # use lsm303::common::{Axis, AxisRemap, Vector3};
+/// // Swap X and Y, and invert Z.
+/// let remap = AxisRemap::new(Axis::PlusY, Axis::PlusX, Axis::MinusZ).unwrap();
+/// let v = remap.apply(Vector3 { x: 1, y: 2, z: 3 });
+/// assert_eq!(v, Vector3 { x: 2, y: 1, z: -3 });
+///
+/// // Each physical axis must be used exactly once.
+/// assert!(AxisRemap::new(Axis::PlusX, Axis::MinusX, Axis::PlusZ).is_err());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AxisRemap {
+    x: Axis,
+    y: Axis,
+    z: Axis,
+}
+
+
+impl Default for AxisRemap {
+    fn default() -> AxisRemap {
+        AxisRemap {
+            x: Axis::PlusX,
+            y: Axis::PlusY,
+            z: Axis::PlusZ,
+        }
+    }
+}
+
+
+impl AxisRemap {
+    /// Create a mapping, given the physical axis for each logical axis.
+    ///
+    /// Fails with `ErrorKind::InvalidAxisRemap`
+    /// if the axes are not a permutation of X, Y, and Z.
+    pub fn new(x: Axis, y: Axis, z: Axis) -> Result<AxisRemap> {
+        let mut used = [false; 3];
+        for axis in &[x, y, z] {
+            used[axis.index()] = true;
+        }
+        if used != [true; 3] {
+            bail!(ErrorKind::InvalidAxisRemap);
+        }
+
+        Ok(AxisRemap { x, y, z })
+    }
+
+    /// Map a vector from the axes of the chip to the axes of the board.
+    pub fn apply<T>(&self, v: Vector3<T>) -> Vector3<T>
+    where
+        T: Copy + Neg<Output = T>,
+    {
+        Vector3 {
+            x: self.x.select(v),
+            y: self.y.select(v),
+            z: self.z.select(v),
+        }
+    }
+}
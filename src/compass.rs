@@ -0,0 +1,275 @@
+//! Compass calculations based on magnetometer and accelerometer readings.
+//!
+//! These functions operate on readings rather than on the sensors,
+//! so they can be used with data from any source.
+
+use crate::common::Vector3;
+use crate::errors::{ErrorKind, Result};
+
+
+/// Compute the compass heading, compensating for the tilt of the board.
+///
+/// The accelerometer reading gives the direction of gravity,
+/// which is used to find the pitch and roll of the board.
+/// The magnetic field is then rotated into the horizontal plane.
+/// The heading is in degrees, in the range `[0, 360)`.
+///
+/// The units of each vector do not matter,
+/// as long as all three axes of a vector share them.
+///
+/// ```
+/// # use lsm303::common::Vector3;
+/// # use lsm303::compass::tilt_compensated_heading;
+/// // Level, facing north
+/// let mag = Vector3 { x: 0.2, y: 0.0, z: -0.4 };
+/// let accel = Vector3 { x: 0.0, y: 0.0, z: 1.0 };
+/// assert!(tilt_compensated_heading(mag, accel) < 0.5);
+///
+/// // Pitched 30 degrees nose down, facing north-east
+/// let mag = Vector3 { x: 0.3225, y: 0.1414, z: -0.2757 };
+/// let accel = Vector3 { x: -0.5, y: 0.0, z: 0.866 };
+/// assert!((tilt_compensated_heading(mag, accel) - 45.0).abs() < 0.5);
+///
+/// // Rolled 30 degrees, facing east
+/// let mag = Vector3 { x: 0.0, y: -0.0268, z: -0.4464 };
+/// let accel = Vector3 { x: 0.0, y: 0.5, z: 0.866 };
+/// assert!((tilt_compensated_heading(mag, accel) - 90.0).abs() < 0.5);
+/// ```
+pub fn tilt_compensated_heading(mag: Vector3<f32>, accel: Vector3<f32>) -> f32 {
+    orientation(mag, accel).yaw
+}
+
+
+/// The orientation of the board, as angles in degrees.
+///
+/// See `orientation`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Euler {
+    /// The rotation about the X axis, in `[-180, 180]`; positive when the Y axis points up.
+    pub roll: f32,
+    /// The rotation about the Y axis, in `[-90, 90]`; positive when the X axis points down.
+    pub pitch: f32,
+    /// The tilt compensated heading of the X axis, in `[0, 360)`.
+    pub yaw: f32,
+}
+
+
+/// Compute the roll, pitch, and yaw of the board.
+///
+/// The roll and pitch come from the direction of gravity, as measured by the accelerometer,
+/// and the yaw is the heading given by `tilt_compensated_heading`.
+/// This is a static estimate from a single pair of readings:
+/// any acceleration other than gravity tilts it,
+/// so it suits platforms that are still or moving slowly.
+/// Smooth the yaw with a `CompassFilter` for a steadier display.
+///
+/// ```
+/// # use lsm303::common::Vector3;
+/// # use lsm303::compass::orientation;
+/// // Level, facing north
+/// let mag = Vector3 { x: 0.2, y: 0.0, z: -0.4 };
+/// let euler = orientation(mag, Vector3 { x: 0.0, y: 0.0, z: 1.0 });
+/// assert!(euler.roll.abs() < 0.01 && euler.pitch.abs() < 0.01 && euler.yaw < 0.5);
+///
+/// // Pitched 30 degrees nose down, facing north-east
+/// let mag = Vector3 { x: 0.3225, y: 0.1414, z: -0.2757 };
+/// let euler = orientation(mag, Vector3 { x: -0.5, y: 0.0, z: 0.866 });
+/// assert!(euler.roll.abs() < 0.01);
+/// assert!((euler.pitch - 30.0).abs() < 0.1);
+/// assert!((euler.yaw - 45.0).abs() < 0.5);
+///
+/// // Pitched 30 degrees nose up
+/// let euler = orientation(mag, Vector3 { x: 0.5, y: 0.0, z: 0.866 });
+/// assert!((euler.pitch + 30.0).abs() < 0.1);
+///
+/// // Rolled 30 degrees, facing east
+/// let mag = Vector3 { x: 0.0, y: -0.0268, z: -0.4464 };
+/// let euler = orientation(mag, Vector3 { x: 0.0, y: 0.5, z: 0.866 });
+/// assert!((euler.roll - 30.0).abs() < 0.1);
+/// assert!(euler.pitch.abs() < 0.01);
+/// assert!((euler.yaw - 90.0).abs() < 0.5);
+/// ```
+pub fn orientation(mag: Vector3<f32>, accel: Vector3<f32>) -> Euler {
+    let roll = accel.y.atan2(accel.z);
+    let (sin_roll, cos_roll) = roll.sin_cos();
+    let pitch = (-accel.x).atan2(accel.y * sin_roll + accel.z * cos_roll);
+    let (sin_pitch, cos_pitch) = pitch.sin_cos();
+
+    // The components of the field in the horizontal plane.
+    let x = mag.x * cos_pitch + mag.y * sin_pitch * sin_roll + mag.z * sin_pitch * cos_roll;
+    let y = mag.y * cos_roll - mag.z * sin_roll;
+
+    Euler {
+        roll: roll.to_degrees(),
+        pitch: pitch.to_degrees(),
+        yaw: normalize_degrees(y.atan2(x).to_degrees()),
+    }
+}
+
+
+/// Compute the strength of a magnetic field, in the units of the reading.
+///
+/// The field of the Earth is about 0.25 to 0.65 Gauss,
+/// so a much stronger field suggests a magnet nearby.
+///
+/// ```
+/// # use lsm303::common::Vector3;
+/// # use lsm303::compass::magnitude;
+/// let field = Vector3 { x: 0.3, y: 0.0, z: -0.4 };
+/// assert!((magnitude(field) - 0.5).abs() < 1e-6);
+/// ```
+pub fn magnitude(reading: Vector3<f32>) -> f32 {
+    (reading.x * reading.x + reading.y * reading.y + reading.z * reading.z).sqrt()
+}
+
+
+/// Compute the inclination, or dip, of a magnetic field in degrees.
+///
+/// This is the angle of the field below the horizontal,
+/// in the range `[-90, 90]`; it is positive in the northern hemisphere,
+/// where the field points into the ground.
+/// This assumes that the board is held flat, with the Z axis up,
+/// as it is when the accelerometer reads +1 g on Z.
+///
+/// ```
+/// # use lsm303::common::Vector3;
+/// # use lsm303::compass::inclination;
+/// let field = Vector3 { x: 0.2, y: 0.0, z: -0.2 };
+/// assert!((inclination(field) - 45.0).abs() < 1e-3);
+///
+/// let field = Vector3 { x: 0.0, y: 0.3, z: 0.0 };
+/// assert_eq!(inclination(field), 0.0);
+/// ```
+pub fn inclination(reading: Vector3<f32>) -> f32 {
+    let horizontal = (reading.x * reading.x + reading.y * reading.y).sqrt();
+    (-reading.z).atan2(horizontal).to_degrees()
+}
+
+
+/// Correct a magnetic heading to true north, given the declination.
+///
+/// The declination is the angle from true north to magnetic north,
+/// in degrees, with east being positive.
+/// Any finite declination is accepted, and wrapped to a single turn;
+/// fails with `ErrorKind::InvalidArgument` if it is NaN or infinite.
+/// The result is in the range `[0, 360)`.
+///
+/// ```
+/// # use lsm303::compass::apply_declination;
+/// // West declination
+/// assert!((apply_declination(5.0, -12.8).unwrap() - 352.2).abs() < 1e-3);
+///
+/// // More than a full turn
+/// assert!((apply_declination(350.0, 370.0).unwrap() - 0.0).abs() < 1e-3);
+/// assert!((apply_declination(90.0, -725.0).unwrap() - 85.0).abs() < 1e-3);
+///
+/// assert!(apply_declination(90.0, std::f32::NAN).is_err());
+/// assert!(apply_declination(90.0, std::f32::INFINITY).is_err());
+/// ```
+pub fn apply_declination(heading: f32, declination: f32) -> Result<f32> {
+    if !declination.is_finite() {
+        bail!(ErrorKind::InvalidArgument);
+    }
+    Ok(normalize_degrees(heading + normalize_degrees(declination)))
+}
+
+
+/// Smooth a series of headings, for a steady compass display.
+///
+/// Each heading is averaged with the previous ones, as a unit vector,
+/// so that the wrap from 359° to 0° is handled correctly.
+/// The output only moves once the average has moved by more than
+/// the hysteresis, which hides the last degree or two of jitter.
+///
+/// The smoothing is the weight given to each new heading, from 0 to 1;
+/// 1 disables the smoothing, and smaller values respond more slowly.
+/// The hysteresis is in degrees; 0 disables it.
+///
+/// ```
+/// # use lsm303::compass::CompassFilter;
+/// // Either side of north averages to north, not south.
+/// let mut filter = CompassFilter::new(0.5, 0.0);
+/// filter.update(359.0);
+/// let heading = filter.update(1.0);
+/// assert!(heading < 0.01 || heading > 359.99);
+///
+/// // Small changes are ignored, and larger ones are followed.
+/// let mut filter = CompassFilter::new(1.0, 2.0);
+/// assert_eq!(filter.update(90.0), 90.0);
+/// assert_eq!(filter.update(91.5), 90.0);
+/// assert_eq!(filter.update(88.5), 90.0);
+/// assert_eq!(filter.update(93.0), 93.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompassFilter {
+    smoothing: f32,
+    hysteresis: f32,
+    average: Option<(f32, f32)>,
+    output: f32,
+}
+
+
+impl CompassFilter {
+    /// Create a filter, with a smoothing factor and a hysteresis in degrees.
+    ///
+    /// The smoothing is clamped to `[0, 1]`, and the hysteresis to at least 0.
+    pub fn new(smoothing: f32, hysteresis: f32) -> CompassFilter {
+        CompassFilter {
+            smoothing: smoothing.clamp(0.0, 1.0),
+            hysteresis: hysteresis.max(0.0),
+            average: None,
+            output: 0.0,
+        }
+    }
+
+    /// Add a heading in degrees, returning the stabilized heading.
+    ///
+    /// The result is in the range `[0, 360)`.
+    pub fn update(&mut self, heading: f32) -> f32 {
+        let (sin, cos) = heading.to_radians().sin_cos();
+        let (sin, cos) = match self.average {
+            Some((avg_sin, avg_cos)) => (
+                avg_sin + self.smoothing * (sin - avg_sin),
+                avg_cos + self.smoothing * (cos - avg_cos),
+            ),
+            None => {
+                self.output = normalize_degrees(heading);
+                (sin, cos)
+            }
+        };
+        self.average = Some((sin, cos));
+
+        let smoothed = normalize_degrees(sin.atan2(cos).to_degrees());
+        if angle_between(smoothed, self.output) > self.hysteresis {
+            self.output = smoothed;
+        }
+        self.output
+    }
+
+    /// Get the last stabilized heading, if there has been one.
+    pub fn heading(&self) -> Option<f32> {
+        self.average.map(|_| self.output)
+    }
+
+    /// Forget the previous headings.
+    pub fn reset(&mut self) {
+        self.average = None;
+    }
+}
+
+
+/// The smallest angle between two headings in degrees, in the range `[0, 180]`.
+fn angle_between(a: f32, b: f32) -> f32 {
+    let difference = normalize_degrees(a - b);
+    difference.min(360.0 - difference)
+}
+
+
+/// Wrap an angle in degrees into the range `[0, 360)`.
+pub(crate) fn normalize_degrees(angle: f32) -> f32 {
+    let angle = angle % 360.0;
+    let angle = if angle < 0.0 { angle + 360.0 } else { angle };
+    // Adding 360 to a tiny negative angle can round up to exactly 360.
+    if angle >= 360.0 { 0.0 } else { angle }
+}
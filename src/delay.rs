@@ -0,0 +1,28 @@
+//! Waiting for the sensor.
+//!
+//! The magnetometer has to wait for conversions, which take up to a third
+//! of a second at the slowest data rate. Rather than spin, it hands each wait
+//! to a `DelayProvider`, which can yield to the rest of the system.
+//! The default, `StdDelay`, puts the thread to sleep;
+//! with the `embedded-hal` feature, `hal::HalDelay` uses a HAL timer instead.
+
+use std::time::Duration;
+
+
+/// A way of waiting for a while.
+pub trait DelayProvider {
+    /// Wait for at least `duration`.
+    fn delay(&mut self, duration: Duration);
+}
+
+
+/// Wait with `std::thread::sleep`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StdDelay;
+
+
+impl DelayProvider for StdDelay {
+    fn delay(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
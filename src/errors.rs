@@ -1,26 +1,387 @@
 // This can probably be removed soon. See:
 // https://github.com/steveklabnik/rustdoc/issues/96
-#![allow(unused_doc_comment)]
+#![allow(unused_doc_comments)]
+// `error_chain` implements the deprecated `Error::description` and `Error::cause`.
+#![allow(deprecated)]
 
 //! The error type for this crate.
+//!
+//! With the `std` feature, errors are built with `error_chain`,
+//! and keep the error from the bus as their cause.
+//! Without it, an error is only its kind.
 
-error_chain!{
-    errors {
-        /// Error opening the I2C device
-        FailedToOpenDevice{}
+#[cfg(feature = "std")]
+use crate::retry::RetryPolicy;
+#[cfg(not(feature = "std"))]
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
 
-        /// An insufficient amount of data was read from the device.
-        NotEnoughData{}
 
-        /// An error occurred receiving information from the I2C slave.
-        FailedToReadRegister{}
+/// Define the error types from the kinds of error.
+///
+/// The kinds are written as in the `errors` block of `error_chain!`.
+macro_rules! define_errors {
+    ( $($kinds:tt)* ) => {
+        #[cfg(feature = "std")]
+        error_chain! {
+            errors { $($kinds)* }
 
-        /// An error occurred sending information to the I2C slave.
-        FailedToWriteRegister{}
+            foreign_links {
+                I2C(::i2cdev::linux::LinuxI2CError) #[doc = "An error from an I2C device."];
+                IO(::std::io::Error) #[doc = "An IO error."];
+            }
+        }
+
+        #[cfg(not(feature = "std"))]
+        define_error_kind! { $($kinds)* }
+    }
+}
+
+
+/// Define `ErrorKind` without `error_chain`, displayed as `error_chain!` would display it.
+#[cfg(not(feature = "std"))]
+macro_rules! define_error_kind {
+    ( $(
+        $(#[$meta:meta])*
+        $name:ident $(( $($field:ident: $ty:ty),* ))* {
+            $( description($description:expr) )*
+            $( display($($display:tt)*) )*
+        }
+    )* ) => {
+        /// The kind of an error.
+        #[derive(Debug)]
+        pub enum ErrorKind {
+            $( $(#[$meta])* $name $(( $($ty),* ))*, )*
+        }
+
+        impl fmt::Display for ErrorKind {
+            #[allow(unused_variables)]
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match *self {
+                    $(
+                        ErrorKind::$name $(( $(ref $field),* ))* => define_error_kind!(
+                            @display f, $name, [$($description)*], [$($($display)*)*]
+                        ),
+                    )*
+                }
+            }
+        }
+    };
+    (@display $f:ident, $name:ident, [$($description:expr)*], [$format:expr $(, $arg:expr)*]) => {
+        write!($f, $format $(, $arg)*)
+    };
+    (@display $f:ident, $name:ident, [$description:expr], []) => {
+        write!($f, "{}", $description)
+    };
+    (@display $f:ident, $name:ident, [], []) => {
+        write!($f, "{}", stringify!($name))
+    };
+}
+
+
+define_errors! {
+    /// Error opening the I2C device
+    FailedToOpenDevice{}
+
+    /// An insufficient amount of data was read from the device.
+    ///
+    /// The fields are the number of bytes expected, and the number read.
+    ///
+    /// ```
+    /// # use lsm303::ErrorKind;
+    /// let kind = ErrorKind::NotEnoughData(6, 3);
+    /// assert_eq!(kind.to_string(), "expected 6 bytes, got 3");
+    /// ```
+    NotEnoughData(expected: usize, got: usize) {
+        description("not enough data")
+        display("expected {} bytes, got {}", expected, got)
+    }
+
+    /// An error occurred receiving information from the I2C slave.
+    FailedToReadRegister{}
+
+    /// An error occurred sending information to the I2C slave.
+    FailedToWriteRegister{}
+
+    /// The sensor did not report new data within the expected time.
+    ConversionTimeout{}
+
+    /// No horizontal magnetic field was detected.
+    NoFieldDetected{}
+
+    /// The temperature sensor is disabled.
+    TemperatureDisabled{}
+
+    /// The operation is not supported by this variant of the LSM303.
+    UnsupportedByVariant{}
+
+    /// The axes of an `AxisRemap` are not a permutation of X, Y, and Z.
+    InvalidAxisRemap{}
+
+    /// The magnetic field is outside the range of the current gain.
+    FieldOverflow{}
+
+    /// A register did not hold the value just written to it.
+    VerifyFailed{}
+
+    /// The device at the address is not the expected sensor.
+    WrongDevice{}
+
+    /// The device did not respond within the timeout.
+    DeviceTimeout{}
+
+    /// A measurement was overwritten while it was being read.
+    DataOverrun{}
+
+    /// An argument was outside the range of valid values.
+    InvalidArgument{}
+
+    /// The requested field range is wider than any gain covers.
+    RangeUnsupported{}
+
+    /// The accelerometer is not resting in one of its six positions.
+    OrientationUnknown{}
+
+    /// A register holds a reserved setting.
+    UnknownBitPattern{}
+
+    /// The file is not an I2C bus.
+    NotAnI2CDevice{
+        description("not an I2C device")
+        display("the file is not an I2C bus; check that it is a /dev/i2c-* device")
+    }
+
+    /// No device acknowledged its address on the bus.
+    NoDeviceAtAddress{
+        description("no device at address")
+        display("no device acknowledged the I2C address; \
+                 check the wiring, and that the address is correct")
+    }
+
+    /// The I2C adapter does not support SMBus block transfers.
+    SmbusUnsupported{
+        description("SMBus block transfers not supported")
+        display("the I2C adapter does not support SMBus block transfers; \
+                 try `set_transfer_mode(TransferMode::Plain)`")
+    }
+
+    /// A register did not contain the expected value.
+    ///
+    /// The fields are the register address, the expected value, and the value found.
+    ///
+    /// ```
+    /// # use lsm303::ErrorKind;
+    /// let kind = ErrorKind::UnexpectedRegisterValue(0x0A, 0x48, 0xFF);
+    /// assert_eq!(kind.to_string(), "register 0x0A: expected 0x48, found 0xFF");
+    /// ```
+    UnexpectedRegisterValue(register: u8, expected: u8, found: u8) {
+        description("unexpected register value")
+        display("register 0x{:02X}: expected 0x{:02X}, found 0x{:02X}",
+                register, expected, found)
+    }
+}
+
+
+/// An error, without `std`.
+///
+/// As with `error_chain`, the kind is the first field, and is also returned by `kind`.
+#[cfg(not(feature = "std"))]
+#[derive(Debug)]
+pub struct Error(pub ErrorKind);
+
+
+#[cfg(not(feature = "std"))]
+impl Error {
+    /// Get the kind of the error.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.0
+    }
+}
+
+
+#[cfg(not(feature = "std"))]
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error(kind)
     }
+}
+
+
+#[cfg(not(feature = "std"))]
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+
+/// The result type for this crate.
+#[cfg(not(feature = "std"))]
+pub type Result<T> = core::result::Result<T, Error>;
+
+
+/// Context for errors, as in `error_chain`.
+///
+/// Without `std`, there is nowhere to keep the original error,
+/// so it is replaced by the given kind.
+#[cfg(not(feature = "std"))]
+pub trait ResultExt<T> {
+    /// Replace the error with the kind returned by `callback`.
+    fn chain_err<F, EK>(self, callback: F) -> Result<T>
+    where
+        F: FnOnce() -> EK,
+        EK: Into<ErrorKind>;
+}
+
+
+#[cfg(not(feature = "std"))]
+impl<T, E> ResultExt<T> for core::result::Result<T, E> {
+    fn chain_err<F, EK>(self, callback: F) -> Result<T>
+    where
+        F: FnOnce() -> EK,
+        EK: Into<ErrorKind>,
+    {
+        self.map_err(|_| Error(callback().into()))
+    }
+}
+
+
+/// Return early with an error, as `error_chain`'s `bail!` does.
+#[cfg(not(feature = "std"))]
+macro_rules! bail {
+    ($e:expr) => {
+        return Err($e.into())
+    };
+}
+
+
+/// Context for the errors of an I2C device.
+///
+/// This is `ResultExt::chain_err` for transfers on the bus,
+/// except that a transfer which no device acknowledged
+/// is reported as `ErrorKind::NoDeviceAtAddress` instead of the given kind.
+#[cfg(any(feature = "std", feature = "async"))]
+pub(crate) trait BusResultExt<T> {
+    /// Convert the error, adding the kind returned by `callback` as context.
+    fn chain_bus_err<F>(self, callback: F) -> Result<T>
+    where
+        F: FnOnce() -> ErrorKind;
+
+    /// As `chain_bus_err`, for SMBus transfers, which the adapter may not support.
+    ///
+    /// An unsupported transfer is reported as `ErrorKind::SmbusUnsupported`.
+    #[cfg(feature = "std")]
+    fn chain_smbus_err<F>(self, callback: F) -> Result<T>
+    where
+        F: FnOnce() -> ErrorKind;
+}
+
+
+#[cfg(feature = "std")]
+impl<T, E> BusResultExt<T> for ::std::result::Result<T, E>
+where
+    Error: From<E>,
+{
+    fn chain_bus_err<F>(self, callback: F) -> Result<T>
+    where
+        F: FnOnce() -> ErrorKind,
+    {
+        self.map_err(|e| {
+            let error = bus_error(Error::from(e));
+            let kind = if is_nack(&error) {
+                ErrorKind::NoDeviceAtAddress
+            } else {
+                callback()
+            };
+            Error::with_chain(error, kind)
+        })
+    }
+
+    fn chain_smbus_err<F>(self, callback: F) -> Result<T>
+    where
+        F: FnOnce() -> ErrorKind,
+    {
+        self.map_err(|e| {
+            let error = bus_error(Error::from(e));
+            let kind = if is_nack(&error) {
+                ErrorKind::NoDeviceAtAddress
+            } else if is_unsupported(&error) {
+                ErrorKind::SmbusUnsupported
+            } else {
+                callback()
+            };
+            Error::with_chain(error, kind)
+        })
+    }
+}
+
+
+/// Without `std`, the error from the bus does not tell a NACK apart,
+/// so every error is reported as the given kind.
+#[cfg(all(not(feature = "std"), feature = "async"))]
+impl<T, E> BusResultExt<T> for core::result::Result<T, E> {
+    fn chain_bus_err<F>(self, callback: F) -> Result<T>
+    where
+        F: FnOnce() -> ErrorKind,
+    {
+        self.chain_err(callback)
+    }
+}
+
+
+/// Convert an error from the Linux I2C layer into an IO error,
+/// which exposes the errno.
+#[cfg(feature = "std")]
+fn bus_error(error: Error) -> Error {
+    match error {
+        Error(ErrorKind::I2C(e), _) => Error::from(io::Error::from(e)),
+        error => error,
+    }
+}
+
+
+/// Check whether an error is the Linux errno for an unacknowledged address.
+///
+/// Depending on the bus driver, that is either ENXIO or EREMOTEIO.
+#[cfg(feature = "std")]
+fn is_nack(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::IO(ref e) => {
+            matches!(e.raw_os_error(), Some(libc::ENXIO) | Some(libc::EREMOTEIO))
+        }
+        _ => false,
+    }
+}
+
+
+/// Check whether an error is the Linux errno for an unsupported transfer.
+///
+/// That is EOPNOTSUPP, which Linux also defines as ENOTSUP.
+#[cfg(feature = "std")]
+fn is_unsupported(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::IO(ref e) => e.raw_os_error() == Some(libc::EOPNOTSUPP),
+        _ => false,
+    }
+}
+
 
-    foreign_links {
-        I2C(::i2cdev::linux::LinuxI2CError) #[doc = "An error from an I2C device."];
-        IO(::std::io::Error) #[doc = "An IO error."];
+/// Check whether a failed transfer may succeed if it is repeated.
+///
+/// That is a short read, or a bus error that `RetryPolicy::is_recoverable` accepts.
+/// A NACK, or a transfer that the adapter does not support, would only fail again.
+#[cfg(feature = "std")]
+pub(crate) fn is_transient(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::NotEnoughData(..) => true,
+        ErrorKind::NoDeviceAtAddress | ErrorKind::SmbusUnsupported => false,
+        ErrorKind::IO(ref e) => RetryPolicy::is_recoverable(e),
+        _ => error
+            .1
+            .next_error
+            .as_ref()
+            .and_then(|cause| cause.downcast_ref::<Error>())
+            .is_some_and(is_transient),
     }
 }
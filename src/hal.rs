@@ -0,0 +1,198 @@
+//! Support for `embedded-hal` I2C buses.
+//!
+//! The sensors are written against the `I2CDevice` trait from `i2cdev`.
+//! `HalDevice` implements that trait for any bus that implements the
+//! blocking `embedded-hal` I2C traits (`Write` and `WriteRead`),
+//! so the same register logic can be used with any HAL.
+//! Registers are read with `WriteRead`, in a single transfer.
+//!
+//! Enable the `embedded-hal` feature to use this module.
+//! It implies `std`; on bare-metal targets, use the `asynch` module instead.
+//!
+//! `HalDevice` takes ownership of the bus.
+//! To share the bus with other drivers, pass a proxy from a bus manager,
+//! such as `shared-bus`, to `Magnetometer::from_embedded_hal` instead;
+//! any proxy that implements the two traits works.
+//!
+//! ```no_run
+//! # extern crate embedded_hal;
+//! # extern crate lsm303;
+//! # use embedded_hal::blocking::i2c::{Write, WriteRead};
+//! # struct Bus;
+//! # impl Write for Bus {
+//! #     type Error = ();
+//! #     fn write(&mut self, _: u8, _: &[u8]) -> Result<(), ()> { Ok(()) }
+//! # }
+//! # impl WriteRead for Bus {
+//! #     type Error = ();
+//! #     fn write_read(&mut self, _: u8, _: &[u8], _: &mut [u8]) -> Result<(), ()> { Ok(()) }
+//! # }
+//! # fn main() { test().unwrap(); }
+//! # fn test() -> lsm303::Result<()> {
+//! # let i2c = Bus;
+//! use lsm303::Magnetometer;
+//!
+//! // `i2c` is the bus provided by your HAL crate.
+//! let mut sensor = Magnetometer::from_embedded_hal(i2c, 0x1E)?;
+//! let field = sensor.read_magnetic_field_gauss()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::bus::{I2CBus, I2CWriteRead};
+use crate::delay::DelayProvider;
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use i2cdev::core::I2CDevice;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::io;
+use std::time::Duration;
+
+
+/// An `I2CDevice` backed by an `embedded-hal` I2C bus.
+pub struct HalDevice<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+
+impl<I2C> HalDevice<I2C> {
+    /// Address a device on the bus, using its 7 bit address.
+    pub fn new(i2c: I2C, address: u8) -> HalDevice<I2C> {
+        HalDevice { i2c, address }
+    }
+
+    /// Release the underlying bus.
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+}
+
+
+/// A `DelayProvider` backed by an `embedded-hal` delay.
+///
+/// ```no_run
+/// # extern crate embedded_hal;
+/// # extern crate lsm303;
+/// # use embedded_hal::blocking::delay::DelayUs;
+/// # struct Timer;
+/// # impl DelayUs<u32> for Timer {
+/// #     fn delay_us(&mut self, _: u32) {}
+/// # }
+/// # fn main() { test().unwrap(); }
+/// # fn test() -> lsm303::Result<()> {
+/// # let timer = Timer;
+/// use lsm303::hal::HalDelay;
+/// use lsm303::Magnetometer;
+///
+/// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+/// // `timer` is a delay provided by your HAL crate.
+/// sensor.set_delay(HalDelay(timer));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HalDelay<D>(pub D);
+
+
+impl<D> DelayProvider for HalDelay<D>
+where
+    D: DelayUs<u32>,
+{
+    fn delay(&mut self, duration: Duration) {
+        let micros = u32::try_from(duration.as_micros()).unwrap_or(u32::MAX);
+        self.0.delay_us(micros);
+    }
+}
+
+
+/// Convert an error from the bus, which is only known to implement `Debug`.
+fn bus_error<E: Debug>(error: E) -> io::Error {
+    io::Error::other(format!("I2C bus error: {:?}", error))
+}
+
+
+/// The error for operations that cannot be expressed with `Write` and `WriteRead`.
+fn unsupported(operation: &str) -> io::Error {
+    io::Error::other(format!("{} is not supported by embedded-hal buses", operation))
+}
+
+
+impl<I2C, E> I2CDevice for HalDevice<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    type Error = io::Error;
+
+    fn read(&mut self, _data: &mut [u8]) -> io::Result<()> {
+        Err(unsupported("A read without a register address"))
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.i2c.write(self.address, data).map_err(bus_error)
+    }
+
+    fn smbus_write_quick(&mut self, _bit: bool) -> io::Result<()> {
+        Err(unsupported("SMBus quick write"))
+    }
+
+    fn smbus_read_byte_data(&mut self, register: u8) -> io::Result<u8> {
+        let mut data = [0];
+        self.i2c
+            .write_read(self.address, &[register], &mut data)
+            .map_err(bus_error)?;
+        Ok(data[0])
+    }
+
+    fn smbus_read_block_data(&mut self, _register: u8) -> io::Result<Vec<u8>> {
+        Err(unsupported("SMBus block read"))
+    }
+
+    fn smbus_read_i2c_block_data(&mut self, register: u8, len: u8) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; len as usize];
+        self.i2c
+            .write_read(self.address, &[register], &mut data)
+            .map_err(bus_error)?;
+        Ok(data)
+    }
+
+    fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> io::Result<()> {
+        let mut data = Vec::with_capacity(values.len() + 2);
+        data.push(register);
+        data.push(values.len() as u8);
+        data.extend_from_slice(values);
+        self.write(&data)
+    }
+
+    fn smbus_process_block(&mut self, _register: u8, _values: &[u8]) -> io::Result<()> {
+        Err(unsupported("SMBus block process call"))
+    }
+}
+
+
+impl<I2C, E> I2CWriteRead for HalDevice<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    fn write_read(&mut self, data: &[u8], buffer: &mut [u8]) -> io::Result<()> {
+        self.i2c.write_read(self.address, data, buffer).map_err(bus_error)
+    }
+}
+
+
+impl<I2C, E> I2CBus for HalDevice<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+    E: Debug,
+{
+    fn set_address(&mut self, address: u16) -> io::Result<()> {
+        if address > 0x7F {
+            return Err(io::Error::other(format!("0x{:X} is not a 7 bit address", address)));
+        }
+        self.address = address as u8;
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(missing_docs)]
+// error_chain expands recursively, once per error kind.
+#![recursion_limit = "256"]
 
 //! Interface to the LSM303 digital accelerometer and magnetometer.
 //!
@@ -26,33 +29,101 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! The blocking sensors need the `std` feature, which is enabled by default.
+//! Without it, the crate is `no_std`, and provides the register definitions,
+//! the magnetometer settings, and, with the `async` feature, `asynch::AsyncMagnetometer`.
 
 // External crates
 
 #[macro_use]
 extern crate bitflags;
 
-extern crate byteorder;
-
+#[cfg(feature = "std")]
 extern crate dimensioned;
 
+#[cfg(feature = "embedded-hal")]
+extern crate embedded_hal;
+
+#[cfg(feature = "async")]
+extern crate embedded_hal_async;
+
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate error_chain;
 
+#[cfg(feature = "std")]
 extern crate i2cdev;
 
-// Exports
+#[cfg(feature = "std")]
+extern crate libc;
 
-pub mod common;
+#[cfg(feature = "log")]
+extern crate log;
 
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+
+// Exports
+
+#[macro_use]
 mod errors;
 pub use errors::{Error, ErrorKind, Result, ResultExt};
 
+pub mod common;
+
 #[macro_use]
 pub mod registers;
 
+#[cfg(feature = "std")]
 pub mod accelerometer;
+#[cfg(feature = "std")]
 pub use accelerometer::Accelerometer;
 
 pub mod magnetometer;
+#[cfg(feature = "std")]
 pub use magnetometer::Magnetometer;
+
+#[cfg(feature = "std")]
+pub mod bus;
+#[cfg(feature = "std")]
+pub use bus::{ACCELEROMETER_ADDRESS, MAGNETOMETER_ADDRESS};
+
+#[cfg(feature = "std")]
+pub mod combined;
+#[cfg(feature = "std")]
+pub use combined::Lsm303;
+
+#[cfg(feature = "std")]
+pub mod compass;
+
+#[cfg(feature = "std")]
+pub mod delay;
+
+#[cfg(feature = "std")]
+pub mod linux;
+
+#[cfg(feature = "std")]
+pub mod lsm303d;
+#[cfg(feature = "std")]
+pub use lsm303d::Lsm303d;
+
+#[cfg(feature = "std")]
+pub mod retry;
+
+#[cfg(feature = "embedded-hal")]
+pub mod hal;
+
+#[cfg(feature = "async")]
+pub mod asynch;
+
+#[cfg(any(test, feature = "sim", feature = "test-util"))]
+#[macro_use]
+mod register_map;
+
+#[cfg(feature = "sim")]
+pub mod sim;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
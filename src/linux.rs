@@ -0,0 +1,210 @@
+//! Using an I2C bus that was opened by someone else.
+//!
+//! Service managers and sandboxes may hand a process an open file descriptor
+//! for the bus, rather than letting it open `/dev/i2c-1` itself.
+//! If the descriptor can be turned into a `LinuxI2CDevice` by its owner,
+//! pass that to `Magnetometer::from_i2c_device`, `Accelerometer::from_i2c_device`,
+//! or `Lsm303::from_bus`, as with any other bus.
+//! Otherwise, wrap the descriptor in a `FdI2CDevice`.
+//!
+//! ```no_run
+//! # extern crate lsm303;
+//! # use lsm303::linux::FdI2CDevice;
+//! # use lsm303::{Magnetometer, MAGNETOMETER_ADDRESS};
+//! # use std::os::unix::io::FromRawFd;
+//! # fn main() { test().unwrap(); }
+//! # fn test() -> lsm303::Result<()> {
+//! // The first descriptor passed by systemd socket activation.
+//! let file = unsafe { std::fs::File::from_raw_fd(3) };
+//! let device = FdI2CDevice::new(file, MAGNETOMETER_ADDRESS)?;
+//! let mut sensor = Magnetometer::from_i2c_device(device)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::bus::{set_slave_address, I2CBus, I2CWriteRead};
+use crate::errors::{ErrorKind, Result, ResultExt};
+use i2cdev::core::I2CDevice;
+use std::fs::File;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+
+/// An `I2CDevice` on an open Linux I2C file descriptor.
+///
+/// Transfers are plain reads and writes of the descriptor,
+/// so the adapter does not need to support SMBus;
+/// the SMBus operations that the sensors use are built from them.
+/// Registers are read with a combined `I2C_RDWR` transfer.
+#[derive(Debug)]
+pub struct FdI2CDevice {
+    file: File,
+    address: u16,
+}
+
+
+impl FdI2CDevice {
+    /// Wrap an open I2C bus, addressing the device at `address`.
+    ///
+    /// Fails with `ErrorKind::NotAnI2CDevice` if the file is not an I2C bus.
+    ///
+    /// ```
+    /// # use lsm303::linux::FdI2CDevice;
+    /// # use lsm303::ErrorKind;
+    /// let file = std::fs::File::open("/dev/null").unwrap();
+    /// match *FdI2CDevice::new(file, 0x1E).err().unwrap().kind() {
+    ///     ErrorKind::NotAnI2CDevice => {}
+    ///     ref kind => panic!("unexpected error: {}", kind),
+    /// }
+    /// ```
+    pub fn new(file: File, address: u16) -> Result<FdI2CDevice> {
+        check_functionality(file.as_raw_fd()).chain_err(|| ErrorKind::NotAnI2CDevice)?;
+        let mut device = FdI2CDevice { file, address };
+        device.set_address(address).chain_err(|| ErrorKind::FailedToOpenDevice)?;
+        Ok(device)
+    }
+
+    /// Take ownership of a raw file descriptor, and wrap it as `new` does.
+    ///
+    /// # Safety
+    ///
+    /// The descriptor must be open, and not owned by anything else;
+    /// it is closed when the device is dropped.
+    pub unsafe fn from_raw_fd(fd: RawFd, address: u16) -> Result<FdI2CDevice> {
+        FdI2CDevice::new(File::from_raw_fd(fd), address)
+    }
+}
+
+
+/// Ask the adapter behind a descriptor what it supports,
+/// which fails unless it is an I2C bus.
+fn check_functionality(fd: RawFd) -> io::Result<()> {
+    // See linux/i2c-dev.h
+    const I2C_FUNCS: libc::c_ulong = 0x0705;
+
+    let mut functionality: libc::c_ulong = 0;
+    let result = unsafe { libc::ioctl(fd, I2C_FUNCS as _, &mut functionality) };
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+
+/// Write and then read the device at `address`, with a repeated start in between.
+fn write_read(fd: RawFd, address: u16, data: &[u8], buffer: &mut [u8]) -> io::Result<()> {
+    // See linux/i2c.h and linux/i2c-dev.h
+    const I2C_RDWR: libc::c_ulong = 0x0707;
+    const I2C_M_RD: u16 = 0x0001;
+
+    #[repr(C)]
+    struct I2cMsg {
+        addr: u16,
+        flags: u16,
+        len: u16,
+        buf: *mut u8,
+    }
+
+    #[repr(C)]
+    struct I2cRdwrIoctlData {
+        msgs: *mut I2cMsg,
+        nmsgs: u32,
+    }
+
+    let too_long = || io::Error::from_raw_os_error(libc::EINVAL);
+    let mut messages = [
+        I2cMsg {
+            addr: address,
+            flags: 0,
+            len: u16::try_from(data.len()).map_err(|_| too_long())?,
+            // The kernel only reads from a message without I2C_M_RD.
+            buf: data.as_ptr() as *mut u8,
+        },
+        I2cMsg {
+            addr: address,
+            flags: I2C_M_RD,
+            len: u16::try_from(buffer.len()).map_err(|_| too_long())?,
+            buf: buffer.as_mut_ptr(),
+        },
+    ];
+    let mut transfer = I2cRdwrIoctlData {
+        msgs: messages.as_mut_ptr(),
+        nmsgs: messages.len() as u32,
+    };
+    let result = unsafe { libc::ioctl(fd, I2C_RDWR as _, &mut transfer) };
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+
+/// The error for operations that cannot be expressed with plain I2C transfers.
+fn unsupported(operation: &str) -> io::Error {
+    io::Error::other(format!("{} is not supported by FdI2CDevice", operation))
+}
+
+
+impl AsRawFd for FdI2CDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+
+impl I2CDevice for FdI2CDevice {
+    type Error = io::Error;
+
+    fn read(&mut self, data: &mut [u8]) -> io::Result<()> {
+        self.file.read_exact(data)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.write_all(data)
+    }
+
+    fn smbus_write_quick(&mut self, _bit: bool) -> io::Result<()> {
+        Err(unsupported("SMBus quick write"))
+    }
+
+    fn smbus_read_block_data(&mut self, _register: u8) -> io::Result<Vec<u8>> {
+        Err(unsupported("SMBus block read"))
+    }
+
+    fn smbus_read_i2c_block_data(&mut self, register: u8, len: u8) -> io::Result<Vec<u8>> {
+        let mut data = vec![0; usize::from(len)];
+        self.write_read(&[register], &mut data)?;
+        Ok(data)
+    }
+
+    fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> io::Result<()> {
+        let mut data = Vec::with_capacity(values.len() + 2);
+        data.push(register);
+        data.push(values.len() as u8);
+        data.extend_from_slice(values);
+        self.write(&data)
+    }
+
+    fn smbus_process_block(&mut self, _register: u8, _values: &[u8]) -> io::Result<()> {
+        Err(unsupported("SMBus block process call"))
+    }
+}
+
+
+impl I2CWriteRead for FdI2CDevice {
+    fn write_read(&mut self, data: &[u8], buffer: &mut [u8]) -> io::Result<()> {
+        write_read(self.file.as_raw_fd(), self.address, data, buffer)
+    }
+}
+
+
+impl I2CBus for FdI2CDevice {
+    fn set_address(&mut self, address: u16) -> io::Result<()> {
+        set_slave_address(self.file.as_raw_fd(), address)?;
+        self.address = address;
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+//! Interface to the LSM303D.
+//!
+//! Unlike the LSM303DLHC, the LSM303D has both sensors behind a single
+//! I2C address, with one register map. The address is `0x1D`,
+//! or `0x1E` if the SA0 pin is pulled low.
+//!
+//! ```no_run
+//! # fn main() { test().unwrap(); }
+//! # fn test() -> lsm303::Result<()> {
+//! let mut sensor = lsm303::Lsm303d::new("/dev/i2c-1")?;
+//! let accel = sensor.read_acceleration_g()?;
+//! let mag = sensor.read_magnetic_field_gauss()?;
+//! println!("Accel: {}  ||  Mag: {}", accel.fmt_g(), mag.fmt_gauss());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::accelerometer::AccelerationVector;
+use crate::bus::I2CWriteRead;
+use crate::common::Vector3;
+use crate::errors::{BusResultExt, Error, ErrorKind, Result, ResultExt};
+use crate::magnetometer::{self, MagneticField};
+use crate::registers;
+use dimensioned::{si, ucum};
+use i2cdev::core::I2CDevice;
+use i2cdev::linux::LinuxI2CDevice;
+use std::ops::{Deref, DerefMut};
+
+
+/// The I2C address of the LSM303D, with SA0 pulled high.
+pub const I2C_ADDRESS: u16 = 0x1D;
+
+
+/// The I2C address of the LSM303D, with SA0 pulled low.
+pub const I2C_ADDRESS_SA0_LOW: u16 = 0x1E;
+
+
+/// The contents of WHO_AM_I_D.
+const WHO_AM_I: u8 = 0x49;
+
+
+/// Interface to an LSM303D accelerometer and magnetometer.
+pub struct Lsm303d<Dev>
+where
+    Dev: I2CDevice,
+{
+    device: Dev,
+    accel_scale: AccelScale,
+    mag_scale: MagScale,
+}
+
+
+/// Settings for the scale of the acceleration measurement.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AccelScale {
+    /// +/- 2G
+    Scale2G,
+    /// +/- 4G
+    Scale4G,
+    /// +/- 6G
+    Scale6G,
+    /// +/- 8G
+    Scale8G,
+    /// +/- 16G
+    Scale16G,
+}
+
+
+/// Settings for the scale of the magnetic field measurement.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MagScale {
+    /// +/- 2 Gauss
+    Scale2Gauss,
+    /// +/- 4 Gauss
+    Scale4Gauss,
+    /// +/- 8 Gauss
+    Scale8Gauss,
+    /// +/- 12 Gauss
+    Scale12Gauss,
+}
+
+
+impl AccelScale {
+    /// The AFS bits of CTRL2_D for this scale.
+    fn bits(&self) -> registers::Ctrl2D {
+        use crate::registers::Ctrl2D as R;
+        match *self {
+            AccelScale::Scale2G => R::empty(),
+            AccelScale::Scale4G => /* -------- */ R::AFS0,
+            AccelScale::Scale6G => /* -- */ R::AFS1,
+            AccelScale::Scale8G => /* -- */ R::AFS1 | R::AFS0,
+            AccelScale::Scale16G => R::AFS2,
+        }
+    }
+
+    /// The sensitivity of the output, in mg/LSB.
+    ///
+    /// Refer to Table 3 of the LSM303D datasheet.
+    fn mg_per_lsb(&self) -> f32 {
+        match *self {
+            AccelScale::Scale2G => 0.061,
+            AccelScale::Scale4G => 0.122,
+            AccelScale::Scale6G => 0.183,
+            AccelScale::Scale8G => 0.244,
+            AccelScale::Scale16G => 0.732,
+        }
+    }
+}
+
+
+impl MagScale {
+    /// The MFS bits of CTRL6_D for this scale.
+    fn bits(&self) -> registers::Ctrl6D {
+        use crate::registers::Ctrl6D as R;
+        match *self {
+            MagScale::Scale2Gauss => R::empty(),
+            MagScale::Scale4Gauss => /* -------- */ R::MFS0,
+            MagScale::Scale8Gauss => /* -- */ R::MFS1,
+            MagScale::Scale12Gauss => R::MFS1 | R::MFS0,
+        }
+    }
+
+    /// The sensitivity of the output, in LSB/Gauss.
+    ///
+    /// Refer to Table 3 of the LSM303D datasheet,
+    /// which gives the inverse, in mgauss/LSB.
+    /// The sensitivity is the same for all three axes.
+    fn lsb_per_gauss(&self) -> (f32, f32) {
+        let mgauss_per_lsb = match *self {
+            MagScale::Scale2Gauss => 0.080,
+            MagScale::Scale4Gauss => 0.160,
+            MagScale::Scale8Gauss => 0.320,
+            MagScale::Scale12Gauss => 0.479,
+        };
+        let lsb_per_gauss = 1000.0 / mgauss_per_lsb;
+        (lsb_per_gauss, lsb_per_gauss)
+    }
+}
+
+
+impl Lsm303d<LinuxI2CDevice> {
+    /// Initialize the LSM303D for a Linux I2C device, at the default address.
+    ///
+    /// For a board with SA0 pulled low, use `from_i2c_device`
+    /// with `I2C_ADDRESS_SA0_LOW`.
+    ///
+    /// ```
+    /// # use lsm303::Lsm303d;
+    /// let sensor = Lsm303d::new("/dev/i2c-1");
+    /// ```
+    pub fn new<Path>(path: Path) -> Result<Lsm303d<LinuxI2CDevice>>
+    where
+        Path: AsRef<::std::path::Path>,
+    {
+        let device = LinuxI2CDevice::new(&path, I2C_ADDRESS).chain_err(|| {
+            ErrorKind::FailedToOpenDevice
+        })?;
+
+        Lsm303d::from_i2c_device(device)
+    }
+}
+
+
+impl<Dev> Lsm303d<Dev>
+where
+    Dev: I2CWriteRead,
+    Error: From<Dev::Error>,
+    Dev::Error: Send + 'static,
+{
+    /// Initialize the LSM303D, given an open I2C device.
+    ///
+    /// WHO_AM_I_D is checked before anything is written;
+    /// fails with `ErrorKind::WrongDevice` if it does not match.
+    ///
+    /// Both sensors are set to 50 Hz, with all axes enabled,
+    /// and the finest scale: +/- 2G and +/- 2 Gauss.
+    /// The magnetometer is measured in high resolution, continuously.
+    ///
+    /// ```no_run
+    /// # extern crate lsm303;
+    /// # use lsm303::lsm303d::{Lsm303d, I2C_ADDRESS_SA0_LOW};
+    /// # extern crate i2cdev;
+    /// # use i2cdev::linux::LinuxI2CDevice;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let device = LinuxI2CDevice::new("/dev/i2c-1", I2C_ADDRESS_SA0_LOW)?;
+    /// let sensor = Lsm303d::from_i2c_device(device)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_i2c_device(mut device: Dev) -> Result<Lsm303d<Dev>> {
+        use crate::registers::{CTRL1_D, CTRL2_D, CTRL5_D, CTRL6_D, CTRL7_D, WHO_AM_I_D};
+        use crate::registers::{Ctrl1D, Ctrl5D, Ctrl7D};
+
+        let found = device.smbus_read_byte_data(WHO_AM_I_D).chain_bus_err(|| {
+            ErrorKind::FailedToReadRegister
+        })?;
+        if found != WHO_AM_I {
+            let e: Error = ErrorKind::UnexpectedRegisterValue(WHO_AM_I_D, WHO_AM_I, found).into();
+            return Err(e).chain_err(|| ErrorKind::WrongDevice);
+        }
+
+        let accel_scale = AccelScale::Scale2G;
+        let mag_scale = MagScale::Scale2Gauss;
+
+        // Set the data rate to 50 Hz, and keep the high and low bytes of each axis
+        // from the same measurement.
+        type R1 = Ctrl1D;
+        let ctrl1 = R1::AODR2 | R1::AODR0 | R1::BDU | R1::AZEN | R1::AYEN | R1::AXEN;
+        write_register!(device, CTRL1_D, ctrl1)?;
+
+        let ctrl2 = accel_scale.bits();
+        write_register!(device, CTRL2_D, ctrl2)?;
+
+        // High resolution, at 50 Hz.
+        type R5 = Ctrl5D;
+        let ctrl5 = R5::M_RES1 | R5::M_RES0 | R5::M_ODR2;
+        write_register!(device, CTRL5_D, ctrl5)?;
+
+        let ctrl6 = mag_scale.bits();
+        write_register!(device, CTRL6_D, ctrl6)?;
+
+        // Continuous conversion, with the high pass filter bypassed.
+        let ctrl7 = Ctrl7D::empty();
+        write_register!(device, CTRL7_D, ctrl7)?;
+
+        let lsm303d = Lsm303d {
+            device,
+            accel_scale,
+            mag_scale,
+        };
+        Ok(lsm303d)
+    }
+
+    /// Read the accelerometer, returning a vector of accelerations.
+    ///
+    /// ```no_run
+    /// # use lsm303::Lsm303d;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Lsm303d::new("/dev/i2c-1")?;
+    /// let accel = sensor.read_acceleration()?;
+    /// println!("Acceleration: ({}, {}, {})",
+    ///     accel.x, accel.y, accel.z);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_acceleration(&mut self) -> Result<AccelerationVector> {
+        use dimensioned::f64prefixes::MILLI;
+
+        let Vector3 { x, y, z } = self.read_raw_acceleration()?;
+
+        // `dimensioned` only defines the acceleration of free fall for
+        // UCUM, so we have to convert to SI.
+        let scale = MILLI * ucum::G_ * f64::from(self.accel_scale.mg_per_lsb());
+        let scale: si::MeterPerSecond2<f64> = scale.into();
+
+        let x = f64::from(x) * scale;
+        let y = f64::from(y) * scale;
+        let z = f64::from(z) * scale;
+
+        Ok(AccelerationVector { x, y, z })
+    }
+
+    /// Read the accelerometer, returning the acceleration in g's.
+    pub fn read_acceleration_g(&mut self) -> Result<Vector3<f32>> {
+        let scale = self.accel_scale.mg_per_lsb() / 1000.0;
+        Ok(self.read_raw_acceleration()?.map(|v| f32::from(v) * scale))
+    }
+
+    /// Read the accelerometer, returning the raw output of each axis.
+    ///
+    /// The meaning of the values depends on the current scale.
+    pub fn read_raw_acceleration(&mut self) -> Result<Vector3<i16>> {
+        self.read_output(registers::OUT_X_L_A_D)
+    }
+
+    /// Read the magnetometer, returning the magnetic field as a vector.
+    pub fn read_magnetic_field(&mut self) -> Result<MagneticField> {
+        let raw = self.read_raw_magnetic_field()?;
+        Ok(magnetometer::to_tesla(raw, self.mag_scale.lsb_per_gauss()))
+    }
+
+    /// Read the magnetometer, returning the magnetic field in Gauss.
+    pub fn read_magnetic_field_gauss(&mut self) -> Result<Vector3<f32>> {
+        let raw = self.read_raw_magnetic_field()?;
+        Ok(magnetometer::to_gauss(raw, self.mag_scale.lsb_per_gauss()))
+    }
+
+    /// Read the magnetometer, returning the raw output of each axis.
+    ///
+    /// The meaning of the values depends on the current scale.
+    pub fn read_raw_magnetic_field(&mut self) -> Result<Vector3<i16>> {
+        self.read_output(registers::OUT_X_L_M_D)
+    }
+
+    /// Read the six output registers of a sensor, starting at `register`.
+    fn read_output(&mut self, register: u8) -> Result<Vector3<i16>> {
+        // Both sensors only auto-increment when asked to.
+        let mut bytes = [0; 6];
+        read_registers!(self.device, registers::block_read_addr(register), bytes)?;
+
+        // Unlike the LSM303DLHC magnetometer, both outputs are little endian,
+        // and ordered as X, Y, Z.
+        let x = i16::from_le_bytes([bytes[0], bytes[1]]);
+        let y = i16::from_le_bytes([bytes[2], bytes[3]]);
+        let z = i16::from_le_bytes([bytes[4], bytes[5]]);
+        Ok(Vector3 { x, y, z })
+    }
+
+    /// Set the scale of the acceleration measurement.
+    ///
+    /// ```no_run
+    /// # use lsm303::lsm303d::{AccelScale, Lsm303d};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Lsm303d::new("/dev/i2c-1")?;
+    /// sensor.set_accel_scale(AccelScale::Scale6G)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_accel_scale(&mut self, scale: AccelScale) -> Result<()> {
+        use crate::registers::{CTRL2_D, Ctrl2D};
+        type R = Ctrl2D;
+
+        let mut flags = read_register!(self.device, CTRL2_D, Ctrl2D)?;
+        flags.remove(R::AFS2 | R::AFS1 | R::AFS0);
+        flags.insert(scale.bits());
+
+        write_register!(self.device, CTRL2_D, flags)?;
+        self.accel_scale = scale;
+
+        Ok(())
+    }
+
+    /// Get the current scale of the acceleration measurement.
+    pub fn accel_scale(&self) -> AccelScale {
+        self.accel_scale
+    }
+
+    /// Set the scale of the magnetic field measurement.
+    ///
+    /// ```no_run
+    /// # use lsm303::lsm303d::{Lsm303d, MagScale};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Lsm303d::new("/dev/i2c-1")?;
+    /// sensor.set_mag_scale(MagScale::Scale4Gauss)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_mag_scale(&mut self, scale: MagScale) -> Result<()> {
+        use crate::registers::CTRL6_D;
+
+        // The other bits of CTRL6_D must be zero.
+        let flags = scale.bits();
+        write_register!(self.device, CTRL6_D, flags)?;
+        self.mag_scale = scale;
+
+        Ok(())
+    }
+
+    /// Get the current scale of the magnetic field measurement.
+    pub fn mag_scale(&self) -> MagScale {
+        self.mag_scale
+    }
+}
+
+
+/// Access the underlying `I2CDevice`.
+///
+/// Most of the methods require a mutable reference; `DerefMut` is implemented as well.
+impl<Dev> Deref for Lsm303d<Dev>
+where
+    Dev: I2CDevice,
+{
+    type Target = Dev;
+
+    fn deref(&self) -> &Dev {
+        &self.device
+    }
+}
+
+
+/// Access the underlying `I2CDevice`.
+///
+/// Refer to the LSM303D datasheet if you plan on accessing the device directly.
+impl<Dev> DerefMut for Lsm303d<Dev>
+where
+    Dev: I2CDevice,
+{
+    fn deref_mut(&mut self) -> &mut Dev {
+        &mut self.device
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::Lsm303d;
+    use crate::registers::{OUT_X_L_A_D, OUT_Z_H_M_D, WHO_AM_I_D};
+    use crate::test_util::MockI2CDevice;
+
+    // The LSM303D reads both sensors at the one address, with the auto-increment bit set.
+    #[test]
+    fn reads_both_sensors_with_auto_increment() {
+        let mut device = MockI2CDevice::new();
+        device.strict_auto_increment();
+        device.set_register(WHO_AM_I_D, 0x49);
+        device.set_register(OUT_X_L_A_D, 0x34);
+        device.set_register(OUT_X_L_A_D + 1, 0x12);
+        device.set_register(OUT_Z_H_M_D, 0xFF);
+
+        let mut sensor = Lsm303d::from_i2c_device(device).unwrap();
+        assert_eq!(sensor.read_raw_acceleration().unwrap().x, 0x1234);
+        assert_eq!(sensor.read_raw_magnetic_field().unwrap().z, -256);
+
+        assert!(Lsm303d::from_i2c_device(MockI2CDevice::magnetometer()).is_err());
+    }
+}
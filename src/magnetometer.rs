@@ -1,36 +1,717 @@
 //! Interface to the magnetometer.
 
-use common::Vector3;
+#[cfg(feature = "std")]
+use crate::bus::{I2CWriteRead, MAGNETOMETER_ADDRESS};
+use crate::common::{AxisRemap, Vector3};
+#[cfg(feature = "std")]
+use crate::compass::apply_declination;
+#[cfg(feature = "std")]
+use crate::delay::{DelayProvider, StdDelay};
+#[cfg(feature = "std")]
 use dimensioned::{si, ucum};
-use errors::{Error, ErrorKind, Result, ResultExt};
+use crate::errors::{Error, ErrorKind, Result};
+#[cfg(any(feature = "std", feature = "async"))]
+use crate::errors::BusResultExt;
+#[cfg(feature = "std")]
+use crate::errors::{is_transient, ResultExt};
+#[cfg(feature = "std")]
 use i2cdev::core::I2CDevice;
+#[cfg(feature = "std")]
 use i2cdev::linux::LinuxI2CDevice;
-use registers;
+use crate::registers;
+#[cfg(any(feature = "std", feature = "async"))]
+use crate::registers::MagRegister;
+#[cfg(feature = "async")]
+use crate::asynch::AsyncMagnetometer;
+use core::convert::TryFrom;
+#[cfg(any(feature = "std", feature = "async"))]
+use core::iter;
+#[cfg(feature = "std")]
+use std::collections::VecDeque;
+#[cfg(feature = "std")]
+use std::fmt;
+#[cfg(feature = "std")]
 use std::ops::{Deref, DerefMut};
+#[cfg(feature = "std")]
+use std::sync::mpsc::{self, Receiver, Sender};
+#[cfg(feature = "std")]
+use std::thread::{self, JoinHandle};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
 
-/// The I2C address of the magnetometer.
-const I2C_ADDRESS: u16 = 0x3C >> 1;
+/// The nominal sensitivity of the thermometer, in LSB/°C.
+const TEMPERATURE_LSB_PER_DEG: f32 = 8.0;
+
+
+/// The nominal temperature at which the thermometer reads zero, in °C.
+const TEMPERATURE_REFERENCE: f32 = 25.0;
+
+
+/// The default drift in sensitivity of the magnetometer with temperature, as a fraction
+/// per °C.
+///
+/// The LSM303DLHC datasheet (DocID018771, Table 3) specifies no change of the magnetic
+/// sensitivity with temperature, only of the accelerometer's, so there is no nominal value;
+/// the default applies no correction.
+#[cfg(feature = "std")]
+const DEFAULT_TEMP_COEFFICIENT: f32 = 0.0;
+
+
+/// The number of microtesla in one Gauss.
+#[cfg(feature = "std")]
+const MICROTESLA_PER_GAUSS: f32 = 100.0;
+
+
+/// The identity matrix, which applies no soft iron correction.
+#[cfg(feature = "std")]
+const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+
+/// The output of an axis when the field is outside the range of the gain.
+///
+/// The datasheet notes, in the description of the output registers,
+/// that an overflow of the ADC is reported as -4096 (0xF000).
+pub(crate) const OVERFLOW: i16 = -4096;
+
+
+/// The largest raw output of an axis, before it overflows.
+#[cfg(feature = "std")]
+const FULL_SCALE: f32 = 2047.0;
+
+
+/// The contents of WHO_AM_I_M on the LSM303AGR.
+#[cfg(feature = "std")]
+const AGR_WHO_AM_I: u8 = 0x40;
+
+
+/// The fixed sensitivity of the LSM303AGR, in LSB/Gauss.
+///
+/// The datasheet specifies 1.5 mG/LSB for all axes.
+#[cfg(any(feature = "std", feature = "async"))]
+const AGR_LSB_PER_GAUSS: f32 = 1000.0 / 1.5;
+
+
+/// The number of times a failed read of the output is retried, by default.
+#[cfg(feature = "std")]
+const DEFAULT_READ_RETRIES: u8 = 3;
+
+
+/// The number of measurements compared by `is_responsive`, by default.
+#[cfg(feature = "std")]
+const DEFAULT_RESPONSIVE_SAMPLES: usize = 5;
+
+
+/// The number of measurements averaged on each side of the self test.
+#[cfg(feature = "std")]
+const SELF_TEST_SAMPLES: i32 = 50;
+
+
+/// The allowed change of each axis during the self test, in LSB.
+///
+/// See Table 3 of the LSM303AGR datasheet.
+#[cfg(feature = "std")]
+const SELF_TEST_RANGE: (i32, i32) = (15, 500);
+
+
+/// The number of times to poll for data ready before giving up.
+///
+/// The device is polled ten times per measurement period,
+/// so this allows for ten periods.
+#[cfg(feature = "std")]
+const CONVERSION_RETRIES: u32 = 100;
 
 
 /// Interface to an LSM303 digital magnetometer.
+///
+/// **Dropping a `Magnetometer` puts the sensor to sleep**, so that it stops
+/// drawing current. Use `into_inner` to take the device back
+/// while leaving it measuring.
+#[cfg(feature = "std")]
 pub struct Magnetometer<Dev>
 where
     Dev: I2CDevice,
 {
-    device: Dev,
+    device: SleepOnDrop<Dev>,
+    variant: Variant,
     gain: Gain,
+    data_rate: DataRate,
+    mode: Mode,
+    temperature_enabled: bool,
+    hard_iron_offset: Vector3<i16>,
+    soft_iron_matrix: [[f32; 3]; 3],
+    axis_remap: AxisRemap,
+    read_retries: u8,
+    verify_writes: bool,
+    temp_coefficient: f32,
+    temp_calibration: TempCalibration,
+    temperature_window: usize,
+    temperature_samples: VecDeque<f32>,
+    transfer_mode: TransferMode,
+    output_register: MagRegister,
+    output_layout: OutputLayout,
+    last_reading_flags: ReadingFlags,
+    responsive_samples: usize,
+    responsive_interval: Duration,
+    delay: Box<dyn DelayProvider + Send>,
 }
 
 
 /// The output type of the magnetometer.
+#[cfg(feature = "std")]
 pub type MagneticField = Vector3<si::Tesla<f64>>;
 
 
+/// Readings of the magnetometer and thermometer, taken together.
+///
+/// See `Magnetometer::snapshot`.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Snapshot {
+    /// The raw output of each axis.
+    pub magnetic_field: Vector3<i16>,
+    /// The raw output of the thermometer.
+    pub temperature: i16,
+    /// When the readings were taken.
+    pub timestamp: Instant,
+}
+
+
+/// The configuration of the magnetometer, as stored in its registers.
+///
+/// See `Magnetometer::read_config` and `Magnetometer::apply_config`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MagnetometerConfig {
+    /// The gain, from CRB_REG_M.
+    pub gain: Gain,
+    /// The data rate, from CRA_REG_M.
+    pub data_rate: DataRate,
+    /// The measurement mode, from MR_REG_M.
+    pub mode: Mode,
+    /// Whether the thermometer is enabled, from CRA_REG_M.
+    pub temperature_enabled: bool,
+}
+
+
+/// The configuration set by `Magnetometer::from_i2c_device`:
+/// a gain of +/- 1.3 Gauss, 15 Hz, continuous mode, and the thermometer enabled.
+///
+/// This is not the power-on reset of the chip, which starts asleep
+/// with the thermometer disabled; see `Magnetometer::reset`.
+impl Default for MagnetometerConfig {
+    fn default() -> MagnetometerConfig {
+        MagnetometerConfig {
+            gain: Gain::default(),
+            data_rate: DataRate::default(),
+            mode: Mode::default(),
+            temperature_enabled: true,
+        }
+    }
+}
+
+
+/// The conversion of the raw thermometer output to degrees Celsius.
+///
+/// The default is the nominal calibration of the LSM303DLHC.
+/// See `Magnetometer::set_temp_calibration`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TempCalibration {
+    /// The sensitivity, in LSB/°C.
+    pub lsb_per_deg: f32,
+    /// The temperature at which the thermometer reads zero, in °C.
+    pub ref_offset_c: f32,
+}
+
+
+impl Default for TempCalibration {
+    fn default() -> TempCalibration {
+        TempCalibration {
+            lsb_per_deg: TEMPERATURE_LSB_PER_DEG,
+            ref_offset_c: TEMPERATURE_REFERENCE,
+        }
+    }
+}
+
+
+impl TempCalibration {
+    /// Convert the raw output to degrees Celsius.
+    ///
+    /// ```
+    /// # use lsm303::magnetometer::TempCalibration;
+    /// let calibration = TempCalibration::default();
+    /// assert_eq!(calibration.celsius(-128), 9.0);
+    /// ```
+    pub fn celsius(&self, raw: i16) -> f32 {
+        f32::from(raw) / self.lsb_per_deg + self.ref_offset_c
+    }
+}
+
+
+/// The outcome of the self test, axis by axis.
+///
+/// See `Magnetometer::self_test_detailed`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SelfTestReport {
+    /// The absolute change of each axis when the self test field was applied, in LSB.
+    pub delta: Vector3<i32>,
+    /// Whether each axis changed by between `min` and `max`.
+    pub axis_passed: Vector3<bool>,
+    /// The smallest change allowed on each axis, in LSB.
+    pub min: i32,
+    /// The largest change allowed on each axis, in LSB.
+    pub max: i32,
+}
+
+
+impl SelfTestReport {
+    /// Check the change of each axis against the allowed range.
+    #[cfg(feature = "std")]
+    fn new(delta: Vector3<i32>, (min, max): (i32, i32)) -> SelfTestReport {
+        SelfTestReport {
+            delta,
+            axis_passed: delta.map(|d| min <= d && d <= max),
+            min,
+            max,
+        }
+    }
+
+    /// Whether every axis passed.
+    ///
+    /// ```
+    /// # use lsm303::common::Vector3;
+    /// # use lsm303::magnetometer::SelfTestReport;
+    /// let report = SelfTestReport {
+    ///     delta: Vector3 { x: 200, y: 210, z: 12 },
+    ///     axis_passed: Vector3 { x: true, y: true, z: false },
+    ///     min: 15,
+    ///     max: 500,
+    /// };
+    /// assert!(!report.passed());
+    /// ```
+    pub fn passed(&self) -> bool {
+        self.axis_passed.x && self.axis_passed.y && self.axis_passed.z
+    }
+}
+
+
+bitflags! {
+    /// The corrections applied to the last reading of the magnetometer.
+    ///
+    /// See `Magnetometer::last_reading_flags`.
+    /// A correction is only reported if it changed the reading,
+    /// so a zero hard iron offset or an identity soft iron matrix leaves its flag clear.
+    #[derive(Default)]
+    pub struct ReadingFlags: u8 {
+        /// The hard iron offset was subtracted.
+        const HARD_IRON = 1 << 0;
+        /// The soft iron matrix was applied.
+        const SOFT_IRON = 1 << 1;
+        /// The drift in sensitivity with temperature was corrected.
+        const TEMPERATURE = 1 << 2;
+        /// The gain was changed to suit the field.
+        const AUTO_RANGE = 1 << 3;
+    }
+}
+
+
+/// The variants of the LSM303, which differ in their magnetometer.
+///
+/// The LSM303DLHC and the LSM303AGR share an I2C address,
+/// but have incompatible register maps.
+/// The AGR has a fixed sensitivity of 1.5 mG/LSB with no gain setting,
+/// and its magnetometer has no thermometer.
+/// The default variant is `Dlhc`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Variant {
+    /// LSM303DLHC
+    #[default]
+    Dlhc,
+    /// LSM303AGR
+    Agr,
+}
+
+
+/// The order of the axes in the output registers, and the order of their bytes.
+///
+/// See `Magnetometer::set_output_layout`.
+///
+/// ```
+/// # use lsm303::magnetometer::OutputLayout;
+/// let bytes = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+/// let v = OutputLayout::XzyBigEndian.decode(bytes);
+/// assert_eq!((v.x, v.y, v.z), (0x0102, 0x0506, 0x0304));
+/// let v = OutputLayout::XyzLittleEndian.decode(bytes);
+/// assert_eq!((v.x, v.y, v.z), (0x0201, 0x0403, 0x0605));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OutputLayout {
+    /// X, Z and then Y, each big endian, as on the LSM303DLHC.
+    XzyBigEndian,
+    /// X, Y and then Z, each little endian, as on the LSM303AGR.
+    XyzLittleEndian,
+}
+
+
+impl OutputLayout {
+    /// Decode the output registers into the raw output of each axis.
+    pub fn decode(&self, bytes: [u8; 6]) -> Vector3<i16> {
+        match *self {
+            OutputLayout::XzyBigEndian => {
+                // Yes indeed, the registers are ordered as X, Z, Y
+                let x = i16::from_be_bytes([bytes[0], bytes[1]]);
+                let z = i16::from_be_bytes([bytes[2], bytes[3]]);
+                let y = i16::from_be_bytes([bytes[4], bytes[5]]);
+                Vector3 { x, y, z }
+            }
+            OutputLayout::XyzLittleEndian => {
+                let x = i16::from_le_bytes([bytes[0], bytes[1]]);
+                let y = i16::from_le_bytes([bytes[2], bytes[3]]);
+                let z = i16::from_le_bytes([bytes[4], bytes[5]]);
+                Vector3 { x, y, z }
+            }
+        }
+    }
+}
+
+
+impl Variant {
+    /// The first output register.
+    #[cfg(any(feature = "std", feature = "async"))]
+    pub(crate) fn output_register(&self) -> MagRegister {
+        match *self {
+            Variant::Dlhc => MagRegister::OutXH,
+            Variant::Agr => MagRegister::OutxLReg,
+        }
+    }
+
+    /// The layout of the output registers.
+    #[cfg(any(feature = "std", feature = "async"))]
+    pub(crate) fn output_layout(&self) -> OutputLayout {
+        match *self {
+            Variant::Dlhc => OutputLayout::XzyBigEndian,
+            Variant::Agr => OutputLayout::XyzLittleEndian,
+        }
+    }
+
+    /// The status register.
+    #[cfg(any(feature = "std", feature = "async"))]
+    pub(crate) fn status_register(&self) -> MagRegister {
+        match *self {
+            Variant::Dlhc => MagRegister::SrReg,
+            Variant::Agr => MagRegister::StatusReg,
+        }
+    }
+
+    /// The identification registers, and their expected contents.
+    #[cfg(feature = "std")]
+    fn identity(&self) -> &'static [(MagRegister, u8)] {
+        use crate::registers::MagRegister::{IraReg, IrbReg, IrcReg, WhoAmI};
+        match *self {
+            Variant::Dlhc => &[(IraReg, b'H'), (IrbReg, b'4'), (IrcReg, b'3')],
+            Variant::Agr => &[(WhoAmI, AGR_WHO_AM_I)],
+        }
+    }
+
+    /// Check the status register for a new measurement.
+    #[cfg(any(feature = "std", feature = "async"))]
+    pub(crate) fn data_ready(&self, status: u8) -> bool {
+        use crate::registers::{SrRegM, StatusRegM};
+        match *self {
+            Variant::Dlhc => SrRegM::from_bits_truncate(status).contains(SrRegM::DRDY),
+            Variant::Agr => StatusRegM::from_bits_truncate(status).contains(StatusRegM::Zyxda),
+        }
+    }
+
+    /// The sensitivity of the XY and Z axes at the given gain, in LSB/Gauss.
+    ///
+    /// The gain is ignored by the AGR.
+    #[cfg(any(feature = "std", feature = "async"))]
+    pub(crate) fn lsb_per_gauss(&self, gain: Gain) -> (f32, f32) {
+        match *self {
+            Variant::Dlhc => gain.lsb_per_gauss(),
+            Variant::Agr => (AGR_LSB_PER_GAUSS, AGR_LSB_PER_GAUSS),
+        }
+    }
+
+    /// The milligauss per LSB of the XY and Z axes at the given gain,
+    /// each as a numerator and a denominator.
+    ///
+    /// The gain is ignored by the AGR.
+    #[cfg(feature = "std")]
+    fn milligauss_per_lsb(&self, gain: Gain) -> ((i32, i32), (i32, i32)) {
+        match *self {
+            Variant::Dlhc => {
+                let (xy, z) = gain.lsb_per_gauss_int();
+                ((1000, i32::from(xy)), (1000, i32::from(z)))
+            }
+            // 1.5 mG/LSB
+            Variant::Agr => ((3, 2), (3, 2)),
+        }
+    }
+}
+
+
+/// Check the identification registers of a device.
+///
+/// Fails with `ErrorKind::UnexpectedRegisterValue` on the first mismatch.
+#[cfg(feature = "std")]
+fn check_identity<Dev>(device: &mut Dev, variant: Variant) -> Result<()>
+where
+    Dev: I2CDevice,
+    Error: From<Dev::Error>,
+    Dev::Error: Send + 'static,
+{
+    for &(register, expected) in variant.identity() {
+        let found = read_register(device, register)?;
+        if found != expected {
+            bail!(ErrorKind::UnexpectedRegisterValue(register.addr(), expected, found));
+        }
+    }
+    Ok(())
+}
+
+
+/// Read a single register.
+#[cfg(feature = "std")]
+fn read_register<Dev>(device: &mut Dev, register: MagRegister) -> Result<u8>
+where
+    Dev: I2CDevice,
+    Error: From<Dev::Error>,
+    Dev::Error: Send + 'static,
+{
+    let value = device
+        .smbus_read_byte_data(register.addr())
+        .chain_bus_err(|| ErrorKind::FailedToReadRegister)?;
+    log_debug!("read 0x{:02X} from register 0x{:02X}", value, register.addr());
+    Ok(value)
+}
+
+
+/// Write a single register.
+#[cfg(feature = "std")]
+fn write_register<Dev>(device: &mut Dev, register: MagRegister, value: u8) -> Result<()>
+where
+    Dev: I2CDevice,
+    Error: From<Dev::Error>,
+    Dev::Error: Send + 'static,
+{
+    log_debug!("write 0x{:02X} to register 0x{:02X}", value, register.addr());
+    device
+        .smbus_write_byte_data(register.addr(), value)
+        .chain_bus_err(|| ErrorKind::FailedToWriteRegister)
+}
+
+
+/// Read consecutive registers, starting at `base`, into a buffer.
+#[cfg(feature = "std")]
+fn read_registers<Dev>(device: &mut Dev, base: MagRegister, buffer: &mut [u8]) -> Result<()>
+where
+    Dev: I2CWriteRead,
+    Error: From<Dev::Error>,
+    Dev::Error: Send + 'static,
+{
+    read_registers!(device, base.addr(), *buffer)
+}
+
+
+/// The most consecutive registers that are written in one transaction.
+///
+/// This covers CRA_REG_M, CRB_REG_M and MR_REG_M.
+#[cfg(any(feature = "std", feature = "async"))]
+const MAX_BATCH: usize = 3;
+
+
+/// Write consecutive registers, starting at `base`, in a single transaction.
+///
+/// Relies on the register address auto-incrementing, as it does on the magnetometer.
+/// At most `MAX_BATCH` values can be written.
+#[cfg(feature = "std")]
+fn write_registers<Dev>(device: &mut Dev, base: MagRegister, values: &[u8]) -> Result<()>
+where
+    Dev: I2CDevice,
+    Error: From<Dev::Error>,
+    Dev::Error: Send + 'static,
+{
+    log_debug!("write {:02X?} to registers from 0x{:02X}", values, base.addr());
+    let mut data = [0; MAX_BATCH + 1];
+    data[0] = base.addr();
+    data[1..=values.len()].copy_from_slice(values);
+
+    device.write(&data[..=values.len()]).chain_bus_err(|| ErrorKind::FailedToWriteRegister)
+}
+
+
+/// Group writes to consecutive registers, so that each group takes a single transaction.
+///
+/// Each group is its first register, the values, and how many of them are used.
+#[cfg(any(feature = "std", feature = "async"))]
+fn batch_writes<I>(writes: I) -> impl Iterator<Item = (MagRegister, [u8; MAX_BATCH], usize)>
+where
+    I: IntoIterator<Item = (MagRegister, u8)>,
+{
+    let mut writes = writes.into_iter().peekable();
+    iter::from_fn(move || {
+        let (base, value) = writes.next()?;
+        let mut values = [value, 0, 0];
+        let mut len = 1;
+        while len < MAX_BATCH {
+            match writes.peek() {
+                Some(&(register, value)) if register.addr() == base.addr() + len as u8 => {
+                    values[len] = value;
+                    len += 1;
+                    writes.next();
+                }
+                _ => break,
+            }
+        }
+        Some((base, values, len))
+    })
+}
+
+
+/// The sensitivity of the XY and Z axes of the LSM303DLHC at the given gain, in LSB/Gauss.
+///
+/// These are the values that the scaled reads use,
+/// from Table 3 of the datasheet.
+///
+/// ```
+/// # use lsm303::magnetometer::{sensitivity, Gain};
+/// let datasheet = [
+///     (Gain::Gain_1_3, (1100., 980.)),
+///     (Gain::Gain_1_9, (855., 760.)),
+///     (Gain::Gain_2_5, (670., 600.)),
+///     (Gain::Gain_4_0, (450., 400.)),
+///     (Gain::Gain_4_7, (400., 355.)),
+///     (Gain::Gain_5_6, (330., 295.)),
+///     (Gain::Gain_8_1, (230., 205.)),
+/// ];
+/// for &(gain, expected) in datasheet.iter() {
+///     assert_eq!(sensitivity(gain), expected);
+/// }
+/// ```
+pub fn sensitivity(gain: Gain) -> (f32, f32) {
+    gain.lsb_per_gauss()
+}
+
+
+/// Open the magnetometer on a Linux I2C bus, take a single measurement, and close it again.
+///
+/// This is `Magnetometer::new` followed by `read_magnetic_field_single`,
+/// for scripts and diagnostics that only need one reading.
+/// The magnetometer is left asleep.
+///
+/// ```no_run
+/// # use lsm303::magnetometer::one_shot_read;
+/// # fn main() { test().unwrap(); }
+/// # fn test() -> lsm303::Result<()> {
+/// let raw = one_shot_read("/dev/i2c-1")?;
+/// println!("Raw magnetic field: ({}, {}, {})", raw.x, raw.y, raw.z);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "std")]
+pub fn one_shot_read<P>(path: P) -> Result<Vector3<i16>>
+where
+    P: AsRef<::std::path::Path>,
+{
+    Magnetometer::new(path)?.read_magnetic_field_single()
+}
+
+
+/// Scale the raw output to Gauss, given the sensitivity of the XY and Z axes.
+#[cfg(any(feature = "std", feature = "async"))]
+pub(crate) fn to_gauss(raw: Vector3<i16>, (scale_xy, scale_z): (f32, f32)) -> Vector3<f32> {
+    Vector3 {
+        x: f32::from(raw.x) / scale_xy,
+        y: f32::from(raw.y) / scale_xy,
+        z: f32::from(raw.z) / scale_z,
+    }
+}
+
+
+/// Scale the raw output to Tesla, given the sensitivity of the XY and Z axes.
+#[cfg(feature = "std")]
+pub(crate) fn to_tesla(raw: Vector3<i16>, (scale_xy, scale_z): (f32, f32)) -> MagneticField {
+    let scale_xy: si::Tesla<f64> = (ucum::GS / f64::from(scale_xy)).into();
+    let scale_z: si::Tesla<f64> = (ucum::GS / f64::from(scale_z)).into();
+
+    MagneticField {
+        x: f64::from(raw.x) * scale_xy,
+        y: f64::from(raw.y) * scale_xy,
+        z: f64::from(raw.z) * scale_z,
+    }
+}
+
+
+/// Check whether any axis of a raw reading has overflowed.
+///
+/// The LSM303DLHC reports -4096 on an axis when the field
+/// is too strong for the current gain; the reading is then meaningless,
+/// and a coarser gain should be selected.
+///
+/// ```
+/// # use lsm303::common::Vector3;
+/// # use lsm303::magnetometer::is_saturated;
+/// assert!(!is_saturated(Vector3 { x: 120, y: -340, z: 2047 }));
+/// assert!(is_saturated(Vector3 { x: 120, y: -4096, z: 2047 }));
+/// ```
+pub fn is_saturated(reading: Vector3<i16>) -> bool {
+    reading.x == OVERFLOW || reading.y == OVERFLOW || reading.z == OVERFLOW
+}
+
+
+/// Decode TEMP_OUT_H_M and TEMP_OUT_L_M into a signed 12 bit value.
+///
+/// The value is two's complement, left justified in the 16 bit register,
+/// so the top bit of the high byte is the sign.
+#[cfg(feature = "std")]
+pub(crate) fn decode_temperature(high: u8, low: u8) -> i16 {
+    let raw = (u16::from(high) << 4) | (u16::from(low) >> 4);
+    // Move bit 11 into the sign bit, and shift back to extend it.
+    ((raw << 4) as i16) >> 4
+}
+
+
+/// The units for `Magnetometer::read_magnetic_field_in`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum MagneticUnit {
+    /// The raw output, which depends on the gain
+    Raw,
+    /// Gauss
+    Gauss,
+    /// Microtesla, where 1 G = 100 µT
+    MicroTesla,
+}
+
+
+/// How multi-byte registers are read from the bus.
+///
+/// See `Magnetometer::set_transfer_mode`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TransferMode {
+    /// The SMBus block read ioctl
+    #[default]
+    Smbus,
+    /// A write of the register address and a read, with `I2CWriteRead::write_read`
+    Plain,
+}
+
+
 /// The allowed settings for the gain on the magnetometer.
+///
+/// The default gain is +/- 1.3 Gauss.
 #[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Gain {
     /// +/- 1.3 Gauss
+    #[default]
     Gain_1_3,
     /// +/- 1.9 Gauss
     Gain_1_9,
@@ -40,182 +721,3044 @@ pub enum Gain {
     Gain_4_0,
     /// +/- 4.7 Gauss
     Gain_4_7,
-    /// +/- 5,6 Gauss
+    /// +/- 5.6 Gauss
     Gain_5_6,
     /// +/- 8.1 Gauss
     Gain_8_1,
 }
 
 
-impl Magnetometer<LinuxI2CDevice> {
-    /// Initialize the magnetometer for a Linux I2C device.
+/// Available values for the data rate of the magnetometer.
+///
+/// See Table 72 of the LSM303 datasheet.
+/// The default rate is `15 Hz`.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DataRate {
+    /// 0.75 Hz
+    Rate0_75Hz,
+    /// 1.5 Hz
+    Rate1_5Hz,
+    /// 3 Hz
+    Rate3Hz,
+    /// 7.5 Hz
+    Rate7_5Hz,
+    /// 15 Hz
+    #[default]
+    Rate15Hz,
+    /// 30 Hz
+    Rate30Hz,
+    /// 75 Hz
+    Rate75Hz,
+    /// 220 Hz
+    Rate220Hz,
+}
+
+
+/// The data rates of the LSM303AGR magnetometer.
+///
+/// See Table 27 of the LSM303AGR datasheet.
+/// `Magnetometer::set_data_rate` takes a `DataRate` on either variant;
+/// on the AGR, the nearest of these rates is used, as `From<DataRate>` chooses it.
+/// The default rate is `10 Hz`.
+///
+/// ```
+/// # use lsm303::magnetometer::{AgrDataRate, DataRate};
+/// assert_eq!(AgrDataRate::from(DataRate::Rate15Hz), AgrDataRate::Rate10Hz);
+/// assert_eq!(AgrDataRate::from(DataRate::Rate220Hz), AgrDataRate::Rate100Hz);
+/// assert_eq!(DataRate::from(AgrDataRate::Rate20Hz), DataRate::Rate30Hz);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum AgrDataRate {
+    /// 10 Hz
+    #[default]
+    Rate10Hz,
+    /// 20 Hz
+    Rate20Hz,
+    /// 50 Hz
+    Rate50Hz,
+    /// 100 Hz
+    Rate100Hz,
+}
+
+
+/// The measurement modes of the magnetometer.
+///
+/// See Table 78 of the LSM303 datasheet.
+/// The default mode is `Continuous`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Mode {
+    /// Measure continuously, at the configured data rate.
+    #[default]
+    Continuous,
+    /// Take a single measurement, and then go to sleep.
+    SingleConversion,
+    /// Do not take measurements.
+    Sleep,
+}
+
+
+impl Gain {
+    /// The GN bits of CRB_REG_M for this gain.
+    #[cfg(any(feature = "std", feature = "async"))]
+    pub(crate) fn bits(&self) -> registers::CrbRegM {
+        use crate::registers::CrbRegM as R;
+        match *self {
+            Gain::Gain_1_3 => /* --  |  ---- */ R::GN0,
+            Gain::Gain_1_9 => /* -- */ R::GN1,
+            Gain::Gain_2_5 => /* -- */ R::GN1 | R::GN0,
+            Gain::Gain_4_0 => R::GN2,
+            Gain::Gain_4_7 => R::GN2 | /* -- */ R::GN0,
+            Gain::Gain_5_6 => R::GN2 | R::GN1,
+            Gain::Gain_8_1 => R::GN2 | R::GN1 | R::GN0,
+        }
+    }
+
+    /// Decode the GN bits of CRB_REG_M, ignoring the other bits.
+    ///
+    /// All zeros is not a valid setting, and gives `None`.
+    pub(crate) fn from_bits(flags: registers::CrbRegM) -> Option<Gain> {
+        use crate::registers::CrbRegM as R;
+        match (flags.contains(R::GN2), flags.contains(R::GN1), flags.contains(R::GN0)) {
+            (false, false, false) => None,
+            (false, false, true) => Some(Gain::Gain_1_3),
+            (false, true, false) => Some(Gain::Gain_1_9),
+            (false, true, true) => Some(Gain::Gain_2_5),
+            (true, false, false) => Some(Gain::Gain_4_0),
+            (true, false, true) => Some(Gain::Gain_4_7),
+            (true, true, false) => Some(Gain::Gain_5_6),
+            (true, true, true) => Some(Gain::Gain_8_1),
+        }
+    }
+
+    /// The sensitivity of the XY and Z axes, in LSB/Gauss.
+    ///
+    /// Refer to Table 3 or Table 75 of the datasheet.
+    /// Note that the Z axis has a different sensitivity at every gain.
+    fn lsb_per_gauss(&self) -> (f32, f32) {
+        let (xy, z) = self.lsb_per_gauss_int();
+        (f32::from(xy), f32::from(z))
+    }
+
+    /// The sensitivity of the XY and Z axes, in LSB/Gauss, as integers.
+    ///
+    /// This is the table behind `lsb_per_gauss`, for integer-only scaling.
+    fn lsb_per_gauss_int(&self) -> (u16, u16) {
+        match *self {
+            Gain::Gain_1_3 => (1100, 980),
+            Gain::Gain_1_9 => (855, 760),
+            Gain::Gain_2_5 => (670, 600),
+            Gain::Gain_4_0 => (450, 400),
+            Gain::Gain_4_7 => (400, 355),
+            Gain::Gain_5_6 => (330, 295),
+            Gain::Gain_8_1 => (230, 205),
+        }
+    }
+}
+
+
+impl Gain {
+    /// All of the gains, from finest to coarsest.
+    const LADDER: [Gain; 7] = [
+        Gain::Gain_1_3,
+        Gain::Gain_1_9,
+        Gain::Gain_2_5,
+        Gain::Gain_4_0,
+        Gain::Gain_4_7,
+        Gain::Gain_5_6,
+        Gain::Gain_8_1,
+    ];
+
+    /// The largest field that can be measured at this gain, in Gauss.
     ///
     /// ```
-    /// # use lsm303::Magnetometer;
-    /// let sensor = Magnetometer::new("/dev/i2c-1");
+    /// # use lsm303::magnetometer::Gain;
+    /// assert_eq!(Gain::Gain_4_7.range_gauss(), 4.7);
     /// ```
-    pub fn new<Path>(path: Path) -> Result<Magnetometer<LinuxI2CDevice>>
-    where
+    pub fn range_gauss(&self) -> f32 {
+        match *self {
+            Gain::Gain_1_3 => 1.3,
+            Gain::Gain_1_9 => 1.9,
+            Gain::Gain_2_5 => 2.5,
+            Gain::Gain_4_0 => 4.0,
+            Gain::Gain_4_7 => 4.7,
+            Gain::Gain_5_6 => 5.6,
+            Gain::Gain_8_1 => 8.1,
+        }
+    }
+
+    /// The next gain with a wider range, if there is one.
+    #[cfg(feature = "std")]
+    fn coarser(&self) -> Option<Gain> {
+        let i = Gain::LADDER.iter().position(|g| g == self)?;
+        Gain::LADDER.get(i + 1).cloned()
+    }
+
+    /// The next gain with a finer resolution, if there is one.
+    #[cfg(feature = "std")]
+    fn finer(&self) -> Option<Gain> {
+        let i = Gain::LADDER.iter().position(|g| g == self)?;
+        i.checked_sub(1).map(|i| Gain::LADDER[i])
+    }
+}
+
+
+impl DataRate {
+    /// The DO bits of CRA_REG_M for this data rate.
+    #[cfg(any(feature = "std", feature = "async"))]
+    fn bits(&self) -> registers::CraRegM {
+        use crate::registers::CraRegM as R;
+        match *self {
+            DataRate::Rate0_75Hz => R::empty(),
+            DataRate::Rate1_5Hz => /* --------- */ R::DO0,
+            DataRate::Rate3Hz => /* ---- */ R::DO1,
+            DataRate::Rate7_5Hz => /* -- */ R::DO1 | R::DO0,
+            DataRate::Rate15Hz => R::DO2,
+            DataRate::Rate30Hz => R::DO2 | /* -- */ R::DO0,
+            DataRate::Rate75Hz => R::DO2 | R::DO1,
+            DataRate::Rate220Hz => R::DO2 | R::DO1 | R::DO0,
+        }
+    }
+
+    /// The time between measurements, in microseconds.
+    #[cfg(feature = "std")]
+    fn period_us(&self) -> u32 {
+        match *self {
+            DataRate::Rate0_75Hz => 1_333_333,
+            DataRate::Rate1_5Hz => 666_667,
+            DataRate::Rate3Hz => 333_333,
+            DataRate::Rate7_5Hz => 133_333,
+            DataRate::Rate15Hz => 66_667,
+            DataRate::Rate30Hz => 33_333,
+            DataRate::Rate75Hz => 13_333,
+            DataRate::Rate220Hz => 4_545,
+        }
+    }
+
+    /// The ODR bits of CFG_REG_A_M on the LSM303AGR, for the nearest rate it supports.
+    #[cfg(any(feature = "std", feature = "async"))]
+    fn agr_bits(&self) -> registers::CfgRegAM {
+        AgrDataRate::from(*self).bits()
+    }
+
+    /// Decode the DO bits of CRA_REG_M, ignoring the other bits.
+    fn from_bits(flags: registers::CraRegM) -> DataRate {
+        use crate::registers::CraRegM as R;
+        match (flags.contains(R::DO2), flags.contains(R::DO1), flags.contains(R::DO0)) {
+            (false, false, false) => DataRate::Rate0_75Hz,
+            (false, false, true) => DataRate::Rate1_5Hz,
+            (false, true, false) => DataRate::Rate3Hz,
+            (false, true, true) => DataRate::Rate7_5Hz,
+            (true, false, false) => DataRate::Rate15Hz,
+            (true, false, true) => DataRate::Rate30Hz,
+            (true, true, false) => DataRate::Rate75Hz,
+            (true, true, true) => DataRate::Rate220Hz,
+        }
+    }
+}
+
+
+impl AgrDataRate {
+    /// The ODR bits of CFG_REG_A_M for this data rate.
+    #[cfg(any(feature = "std", feature = "async"))]
+    fn bits(&self) -> registers::CfgRegAM {
+        use crate::registers::CfgRegAM as R;
+        match *self {
+            AgrDataRate::Rate10Hz => R::empty(),
+            AgrDataRate::Rate20Hz => /* -- */ R::ODR0,
+            AgrDataRate::Rate50Hz => R::ODR1,
+            AgrDataRate::Rate100Hz => R::ODR1 | R::ODR0,
+        }
+    }
+
+    /// Decode the ODR bits of CFG_REG_A_M, ignoring the other bits.
+    #[cfg(feature = "std")]
+    fn from_bits(flags: registers::CfgRegAM) -> AgrDataRate {
+        use crate::registers::CfgRegAM as R;
+        match (flags.contains(R::ODR1), flags.contains(R::ODR0)) {
+            (false, false) => AgrDataRate::Rate10Hz,
+            (false, true) => AgrDataRate::Rate20Hz,
+            (true, false) => AgrDataRate::Rate50Hz,
+            (true, true) => AgrDataRate::Rate100Hz,
+        }
+    }
+
+    /// The time between measurements, in microseconds.
+    #[cfg(feature = "std")]
+    fn period_us(&self) -> u32 {
+        match *self {
+            AgrDataRate::Rate10Hz => 100_000,
+            AgrDataRate::Rate20Hz => 50_000,
+            AgrDataRate::Rate50Hz => 20_000,
+            AgrDataRate::Rate100Hz => 10_000,
+        }
+    }
+}
+
+
+/// Choose the nearest rate of the LSM303AGR.
+///
+/// Every rate up to 15 Hz becomes 10 Hz.
+impl From<DataRate> for AgrDataRate {
+    fn from(rate: DataRate) -> AgrDataRate {
+        match rate {
+            DataRate::Rate0_75Hz |
+            DataRate::Rate1_5Hz |
+            DataRate::Rate3Hz |
+            DataRate::Rate7_5Hz |
+            DataRate::Rate15Hz => AgrDataRate::Rate10Hz,
+            DataRate::Rate30Hz => AgrDataRate::Rate20Hz,
+            DataRate::Rate75Hz => AgrDataRate::Rate50Hz,
+            DataRate::Rate220Hz => AgrDataRate::Rate100Hz,
+        }
+    }
+}
+
+
+/// The `DataRate` that selects this rate of the LSM303AGR.
+///
+/// This is the rate that `Magnetometer::data_rate` reports on the AGR.
+impl From<AgrDataRate> for DataRate {
+    fn from(rate: AgrDataRate) -> DataRate {
+        match rate {
+            AgrDataRate::Rate10Hz => DataRate::Rate15Hz,
+            AgrDataRate::Rate20Hz => DataRate::Rate30Hz,
+            AgrDataRate::Rate50Hz => DataRate::Rate75Hz,
+            AgrDataRate::Rate100Hz => DataRate::Rate220Hz,
+        }
+    }
+}
+
+
+impl Mode {
+    /// The MD bits of MR_REG_M for this mode.
+    #[cfg(any(feature = "std", feature = "async"))]
+    fn bits(&self) -> registers::MrRegM {
+        use crate::registers::MrRegM as R;
+        match *self {
+            Mode::Continuous => R::empty(),
+            Mode::SingleConversion => R::MD0,
+            Mode::Sleep => R::MD1 | R::MD0,
+        }
+    }
+
+    /// Decode the MD bits of MR_REG_M, ignoring the other bits.
+    ///
+    /// Both settings with MD1 set are sleep mode.
+    fn from_bits(flags: registers::MrRegM) -> Mode {
+        use crate::registers::MrRegM as R;
+        match (flags.contains(R::MD1), flags.contains(R::MD0)) {
+            (false, false) => Mode::Continuous,
+            (false, true) => Mode::SingleConversion,
+            (true, _) => Mode::Sleep,
+        }
+    }
+
+    /// The MD bits of CFG_REG_A_M on the LSM303AGR.
+    #[cfg(any(feature = "std", feature = "async"))]
+    fn agr_bits(&self) -> registers::CfgRegAM {
+        use crate::registers::CfgRegAM as R;
+        match *self {
+            Mode::Continuous => R::empty(),
+            Mode::SingleConversion => R::MD0,
+            Mode::Sleep => R::MD1 | R::MD0,
+        }
+    }
+
+    /// Decode the MD bits of CFG_REG_A_M on the LSM303AGR, ignoring the other bits.
+    #[cfg(feature = "std")]
+    fn from_agr_bits(flags: registers::CfgRegAM) -> Mode {
+        use crate::registers::CfgRegAM as R;
+        match (flags.contains(R::MD1), flags.contains(R::MD0)) {
+            (false, false) => Mode::Continuous,
+            (false, true) => Mode::SingleConversion,
+            (true, _) => Mode::Sleep,
+        }
+    }
+}
+
+
+/// Decode the GN bits of the contents of CRB_REG_M, ignoring the other bits.
+///
+/// Fails with `ErrorKind::UnknownBitPattern` for the reserved setting, with no bits set.
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// # use lsm303::magnetometer::Gain;
+/// # use lsm303::ErrorKind;
+/// let gains = [
+///     (0x20, Gain::Gain_1_3),
+///     (0x40, Gain::Gain_1_9),
+///     (0x60, Gain::Gain_2_5),
+///     (0x80, Gain::Gain_4_0),
+///     (0xA0, Gain::Gain_4_7),
+///     (0xC0, Gain::Gain_5_6),
+///     (0xE0, Gain::Gain_8_1),
+/// ];
+/// for &(bits, gain) in &gains {
+///     assert_eq!(Gain::try_from(bits).unwrap(), gain);
+///     assert_eq!(Gain::try_from(bits | 0x1F).unwrap(), gain);
+/// }
+/// match *Gain::try_from(0x00).unwrap_err().kind() {
+///     ErrorKind::UnknownBitPattern => {}
+///     ref kind => panic!("unexpected error: {}", kind),
+/// }
+/// ```
+impl TryFrom<u8> for Gain {
+    type Error = Error;
+
+    fn try_from(crb_reg_m: u8) -> Result<Gain> {
+        match Gain::from_bits(registers::CrbRegM::from_bits_truncate(crb_reg_m)) {
+            Some(gain) => Ok(gain),
+            None => bail!(ErrorKind::UnknownBitPattern),
+        }
+    }
+}
+
+
+/// Decode the DO bits of the contents of CRA_REG_M, ignoring the other bits.
+///
+/// Every setting of the DO bits is valid on the LSM303DLHC,
+/// so this never fails with `ErrorKind::UnknownBitPattern`.
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// # use lsm303::magnetometer::DataRate;
+/// let rates = [
+///     (0x00, DataRate::Rate0_75Hz),
+///     (0x04, DataRate::Rate1_5Hz),
+///     (0x08, DataRate::Rate3Hz),
+///     (0x0C, DataRate::Rate7_5Hz),
+///     (0x10, DataRate::Rate15Hz),
+///     (0x14, DataRate::Rate30Hz),
+///     (0x18, DataRate::Rate75Hz),
+///     (0x1C, DataRate::Rate220Hz),
+/// ];
+/// for &(bits, rate) in &rates {
+///     assert_eq!(DataRate::try_from(bits).unwrap(), rate);
+///     assert_eq!(DataRate::try_from(bits | 0x80).unwrap(), rate);
+/// }
+/// ```
+impl TryFrom<u8> for DataRate {
+    type Error = Error;
+
+    fn try_from(cra_reg_m: u8) -> Result<DataRate> {
+        Ok(DataRate::from_bits(registers::CraRegM::from_bits_truncate(cra_reg_m)))
+    }
+}
+
+
+/// Decode the MD bits of the contents of MR_REG_M, ignoring the other bits.
+///
+/// Both settings with MD1 set are sleep mode, according to Table 79 of the datasheet,
+/// so this never fails with `ErrorKind::UnknownBitPattern`.
+///
+/// ```
+/// # use std::convert::TryFrom;
+/// # use lsm303::magnetometer::Mode;
+/// let modes = [
+///     (0x00, Mode::Continuous),
+///     (0x01, Mode::SingleConversion),
+///     (0x02, Mode::Sleep),
+///     (0x03, Mode::Sleep),
+/// ];
+/// for &(bits, mode) in &modes {
+///     assert_eq!(Mode::try_from(bits).unwrap(), mode);
+///     assert_eq!(Mode::try_from(bits | 0xFC).unwrap(), mode);
+/// }
+/// ```
+impl TryFrom<u8> for Mode {
+    type Error = Error;
+
+    fn try_from(mr_reg_m: u8) -> Result<Mode> {
+        Ok(Mode::from_bits(registers::MrRegM::from_bits_truncate(mr_reg_m)))
+    }
+}
+
+
+/// Configure the magnetometer before it takes its first measurement.
+///
+/// Each register is written once, rather than once per setting.
+/// The defaults match `Magnetometer::from_i2c_device`:
+/// continuous mode, 15 Hz, thermometer enabled, and a gain of +/- 1.3 Gauss.
+///
+/// For an LSM303AGR, select `Variant::Agr`.
+/// The gain and thermometer settings are then ignored.
+///
+/// ```no_run
+/// # extern crate lsm303;
+/// # use lsm303::magnetometer::{DataRate, Gain, MagnetometerBuilder};
+/// # extern crate i2cdev;
+/// # use i2cdev::linux::LinuxI2CDevice;
+/// # fn main() { test().unwrap(); }
+/// # fn test() -> lsm303::Result<()> {
+/// let device = LinuxI2CDevice::new("/dev/i2c-1", 0x3C >> 1)?;
+/// let sensor = MagnetometerBuilder::new()
+///     .gain(Gain::Gain_4_0)
+///     .data_rate(DataRate::Rate75Hz)
+///     .temperature_enabled(false)
+///     .build(device)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct MagnetometerBuilder {
+    variant: Variant,
+    gain: Gain,
+    data_rate: DataRate,
+    temperature_enabled: bool,
+    mode: Mode,
+    axis_remap: AxisRemap,
+}
+
+
+impl Default for MagnetometerBuilder {
+    fn default() -> MagnetometerBuilder {
+        let config = MagnetometerConfig::default();
+        MagnetometerBuilder {
+            variant: Variant::Dlhc,
+            gain: config.gain,
+            data_rate: config.data_rate,
+            temperature_enabled: config.temperature_enabled,
+            mode: config.mode,
+            axis_remap: AxisRemap::default(),
+        }
+    }
+}
+
+
+impl MagnetometerBuilder {
+    /// Start with the default configuration.
+    pub fn new() -> MagnetometerBuilder {
+        MagnetometerBuilder::default()
+    }
+
+    /// Select the variant of the LSM303.
+    pub fn variant(mut self, variant: Variant) -> MagnetometerBuilder {
+        self.variant = variant;
+        self
+    }
+
+    /// Set the gain.
+    pub fn gain(mut self, gain: Gain) -> MagnetometerBuilder {
+        self.gain = gain;
+        self
+    }
+
+    /// Select the finest gain whose range covers fields of up to `range` Gauss.
+    ///
+    /// Fails with `ErrorKind::RangeUnsupported` if the range is wider than
+    /// the coarsest gain, of +/- 8.1 Gauss,
+    /// and with `ErrorKind::InvalidArgument` if it is negative or NaN.
+    ///
+    /// ```
+    /// # use lsm303::magnetometer::MagnetometerBuilder;
+    /// # use lsm303::ErrorKind;
+    /// assert!(MagnetometerBuilder::new().target_range_gauss(3.0).is_ok());
+    /// match *MagnetometerBuilder::new().target_range_gauss(10.0).unwrap_err().kind() {
+    ///     ErrorKind::RangeUnsupported => {}
+    ///     ref kind => panic!("unexpected error: {}", kind),
+    /// }
+    /// ```
+    pub fn target_range_gauss(mut self, range: f32) -> Result<MagnetometerBuilder> {
+        if range.is_nan() || range < 0.0 {
+            bail!(ErrorKind::InvalidArgument);
+        }
+        self.gain = match Gain::LADDER.iter().find(|gain| gain.range_gauss() >= range) {
+            Some(&gain) => gain,
+            None => bail!(ErrorKind::RangeUnsupported),
+        };
+        Ok(self)
+    }
+
+    /// Set the data rate.
+    pub fn data_rate(mut self, data_rate: DataRate) -> MagnetometerBuilder {
+        self.data_rate = data_rate;
+        self
+    }
+
+    /// Enable or disable the thermometer.
+    pub fn temperature_enabled(mut self, enabled: bool) -> MagnetometerBuilder {
+        self.temperature_enabled = enabled;
+        self
+    }
+
+    /// Set the measurement mode.
+    pub fn mode(mut self, mode: Mode) -> MagnetometerBuilder {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the mapping from the axes of the chip to the axes of the board.
+    pub fn axis_remap(mut self, axis_remap: AxisRemap) -> MagnetometerBuilder {
+        self.axis_remap = axis_remap;
+        self
+    }
+
+    /// Initialize the magnetometer with this configuration, on an async I2C bus.
+    ///
+    /// Unlike `build`, this does not check the identity of the device.
+    /// Requires the `async` feature; see the `asynch` module.
+    #[cfg(feature = "async")]
+    pub async fn build_async<I2C>(self, mut i2c: I2C, address: u8) -> Result<AsyncMagnetometer<I2C>>
+    where
+        I2C: ::embedded_hal_async::i2c::I2c,
+    {
+        for (base, values, len) in batch_writes(self.register_writes()) {
+            let mut data = [0; MAX_BATCH + 1];
+            data[0] = base.addr();
+            data[1..=len].copy_from_slice(&values[..len]);
+            i2c.write(address, &data[..=len])
+                .await
+                .map_err(crate::asynch::bus_error)
+                .chain_bus_err(|| ErrorKind::FailedToWriteRegister)?;
+        }
+
+        let magnetometer =
+            AsyncMagnetometer::new(i2c, address, self.variant, self.gain, self.axis_remap);
+        Ok(magnetometer)
+    }
+
+    /// The register writes that apply this configuration, in order.
+    #[cfg(any(feature = "std", feature = "async"))]
+    fn register_writes(&self) -> impl Iterator<Item = (MagRegister, u8)> {
+        use crate::registers::{CfgRegAM, CfgRegCM, CraRegM};
+        use crate::registers::MagRegister::{CfgRegA, CfgRegC, CraReg, CrbReg, MrReg};
+
+        let writes = match self.variant {
+            Variant::Dlhc => {
+                let mut cra_reg_m = self.data_rate.bits();
+                cra_reg_m.set(CraRegM::TEMP_EN, self.temperature_enabled);
+
+                // The mode is set last, so that measurements use the new configuration.
+                [
+                    Some((CraReg, cra_reg_m.bits())),
+                    Some((CrbReg, self.gain.bits().bits())),
+                    Some((MrReg, self.mode.bits().bits())),
+                ]
+            }
+            Variant::Agr => {
+                // The datasheet recommends always enabling temperature compensation.
+                let cfg_reg_a_m = CfgRegAM::COMP_TEMP_EN | self.data_rate.agr_bits() |
+                    self.mode.agr_bits();
+
+                // Keep the high and low bytes of each axis from the same measurement.
+                [Some((CfgRegC, CfgRegCM::BDU.bits())), Some((CfgRegA, cfg_reg_a_m.bits())), None]
+            }
+        };
+        IntoIterator::into_iter(writes).flatten()
+    }
+
+    /// Initialize the magnetometer with this configuration.
+    ///
+    /// The identification registers are checked before anything is written;
+    /// fails with `ErrorKind::WrongDevice` if they do not match the variant,
+    /// which usually means that the address is wrong.
+    #[cfg(feature = "std")]
+    pub fn build<Dev>(self, mut device: Dev) -> Result<Magnetometer<Dev>>
+    where
+        Dev: I2CWriteRead,
+        Error: From<Dev::Error>,
+        Dev::Error: Send + 'static,
+    {
+        if let Err(e) = check_identity(&mut device, self.variant) {
+            if let ErrorKind::UnexpectedRegisterValue(..) = *e.kind() {
+                return Err(e).chain_err(|| ErrorKind::WrongDevice);
+            }
+            return Err(e);
+        }
+
+        self.build_unchecked(device)
+    }
+
+    /// Initialize the magnetometer with this configuration, without checking its identity.
+    ///
+    /// This is for clones of the LSM303 that do not have
+    /// the same identification registers.
+    #[cfg(feature = "std")]
+    pub fn build_unchecked<Dev>(self, mut device: Dev) -> Result<Magnetometer<Dev>>
+    where
+        Dev: I2CWriteRead,
+        Error: From<Dev::Error>,
+        Dev::Error: Send + 'static,
+    {
+        for (base, values, len) in batch_writes(self.register_writes()) {
+            write_registers(&mut device, base, &values[..len])?;
+        }
+
+        let magnetometer = Magnetometer {
+            device: SleepOnDrop::new(device, self.variant),
+            variant: self.variant,
+            gain: self.gain,
+            data_rate: match self.variant {
+                Variant::Dlhc => self.data_rate,
+                Variant::Agr => DataRate::from(AgrDataRate::from(self.data_rate)),
+            },
+            mode: self.mode,
+            temperature_enabled: self.temperature_enabled && self.variant == Variant::Dlhc,
+            hard_iron_offset: Vector3 { x: 0, y: 0, z: 0 },
+            soft_iron_matrix: IDENTITY,
+            axis_remap: self.axis_remap,
+            read_retries: DEFAULT_READ_RETRIES,
+            transfer_mode: TransferMode::default(),
+            output_register: self.variant.output_register(),
+            output_layout: self.variant.output_layout(),
+            last_reading_flags: ReadingFlags::empty(),
+            responsive_samples: DEFAULT_RESPONSIVE_SAMPLES,
+            responsive_interval: Duration::from_millis(0),
+            delay: Box::new(StdDelay),
+            verify_writes: true,
+            temp_coefficient: DEFAULT_TEMP_COEFFICIENT,
+            temp_calibration: TempCalibration::default(),
+            temperature_window: 0,
+            temperature_samples: VecDeque::new(),
+        };
+        Ok(magnetometer)
+    }
+}
+
+
+#[cfg(feature = "std")]
+impl Magnetometer<LinuxI2CDevice> {
+    /// Initialize the magnetometer for a Linux I2C device.
+    ///
+    /// ```
+    /// # use lsm303::Magnetometer;
+    /// let sensor = Magnetometer::new("/dev/i2c-1");
+    /// ```
+    pub fn new<Path>(path: Path) -> Result<Magnetometer<LinuxI2CDevice>>
+    where
         Path: AsRef<::std::path::Path>,
     {
-        let device = LinuxI2CDevice::new(&path, I2C_ADDRESS).chain_err(|| {
+        Magnetometer::new_with_address(path, MAGNETOMETER_ADDRESS)
+    }
+
+    /// Initialize the magnetometer for a Linux I2C device, at a non-standard address.
+    ///
+    /// This is for boards behind an address translator,
+    /// or anything else that moves the magnetometer from its usual `0x1E`.
+    /// The address is the 7 bit address, without the read/write bit;
+    /// fails with `ErrorKind::InvalidArgument` if it is larger than `0x7F`.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Magnetometer::new_with_address("/dev/i2c-1", 0x1F)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// The address is checked before the device is opened.
+    ///
+    /// ```
+    /// # use lsm303::{ErrorKind, Magnetometer};
+    /// match Magnetometer::new_with_address("/dev/i2c-1", 0x80) {
+    ///     Err(ref e) if matches!(*e.kind(), ErrorKind::InvalidArgument) => {}
+    ///     _ => panic!("expected InvalidArgument"),
+    /// }
+    /// ```
+    pub fn new_with_address<Path>(path: Path, address: u16) -> Result<Magnetometer<LinuxI2CDevice>>
+    where
+        Path: AsRef<::std::path::Path>,
+    {
+        if address > 0x7F {
+            bail!(ErrorKind::InvalidArgument);
+        }
+
+        let device = LinuxI2CDevice::new(&path, address).chain_err(|| {
             ErrorKind::FailedToOpenDevice
         })?;
 
-        Magnetometer::from_i2c_device(device)
+        Magnetometer::from_i2c_device(device)
+    }
+
+    /// Initialize the magnetometer for a Linux I2C device, with a time limit.
+    ///
+    /// If the bus exists but the device is wedged, `new` can block indefinitely.
+    /// This fails with `ErrorKind::DeviceTimeout` instead,
+    /// if initialization does not complete in time.
+    /// The initialization runs on a separate thread,
+    /// which is abandoned if it times out.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use std::time::Duration;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Magnetometer::new_with_timeout("/dev/i2c-1", Duration::from_secs(1))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_with_timeout<Path>(
+        path: Path,
+        timeout: Duration,
+    ) -> Result<Magnetometer<LinuxI2CDevice>>
+    where
+        Path: AsRef<::std::path::Path>,
+    {
+        use std::sync::mpsc;
+        use std::thread;
+
+        let path = path.as_ref().to_path_buf();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // The receiver is gone if this took too long; there is nobody to tell.
+            let _ = sender.send(Magnetometer::new(path));
+        });
+
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(_) => bail!(ErrorKind::DeviceTimeout),
+        }
+    }
+}
+
+
+#[cfg(feature = "embedded-hal")]
+impl<I2C, E> Magnetometer<crate::hal::HalDevice<I2C>>
+where
+    I2C: ::embedded_hal::blocking::i2c::Write<Error = E>
+        + ::embedded_hal::blocking::i2c::WriteRead<Error = E>,
+    E: ::std::fmt::Debug,
+{
+    /// Initialize the magnetometer on an `embedded-hal` I2C bus.
+    ///
+    /// The address of the magnetometer is normally `0x1E`.
+    /// Requires the `embedded-hal` feature; see the `hal` module.
+    pub fn from_embedded_hal(
+        i2c: I2C,
+        address: u8,
+    ) -> Result<Magnetometer<crate::hal::HalDevice<I2C>>> {
+        Magnetometer::from_i2c_device(crate::hal::HalDevice::new(i2c, address))
+    }
+}
+
+
+#[cfg(feature = "std")]
+impl<Dev> Magnetometer<Dev>
+where
+    Dev: I2CWriteRead,
+    Error: From<Dev::Error>,
+    Dev::Error: Send + 'static,
+{
+    /// Initialize the magnetometer, given an open I2C device.
+    ///
+    /// The opening of the device is platform specific,
+    /// but initialization of the sensor is not.
+    /// Prefer to use `Magnetometer::new`, unless you are using an
+    /// implementation of `I2CDevice` that is not covered by this crate.
+    ///
+    /// This uses the default configuration of `MagnetometerBuilder`.
+    /// Fails with `ErrorKind::WrongDevice` if the device does not identify
+    /// as an LSM303DLHC; see `from_i2c_device_unchecked`.
+    ///
+    /// ```no_run
+    /// # extern crate lsm303;
+    /// # use lsm303::Magnetometer;
+    /// # extern crate i2cdev;
+    /// # use i2cdev::linux::LinuxI2CDevice;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let device = LinuxI2CDevice::new("/dev/i2c-1", 0x3C >> 1)?;
+    /// let sensor = Magnetometer::from_i2c_device(device)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_i2c_device(device: Dev) -> Result<Magnetometer<Dev>> {
+        MagnetometerBuilder::new().build(device)
+    }
+
+
+    /// Initialize the magnetometer, given an open I2C device, without checking its identity.
+    ///
+    /// This is for clones of the LSM303 that do not have
+    /// the same identification registers.
+    ///
+    /// ```no_run
+    /// # extern crate lsm303;
+    /// # use lsm303::Magnetometer;
+    /// # extern crate i2cdev;
+    /// # use i2cdev::linux::LinuxI2CDevice;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let device = LinuxI2CDevice::new("/dev/i2c-1", 0x3C >> 1)?;
+    /// let sensor = Magnetometer::from_i2c_device_unchecked(device)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_i2c_device_unchecked(device: Dev) -> Result<Magnetometer<Dev>> {
+        MagnetometerBuilder::new().build_unchecked(device)
+    }
+
+
+    /// Restore the magnetometer to its power-on defaults.
+    ///
+    /// CRA_REG_M, CRB_REG_M, and MR_REG_M are rewritten to their reset values:
+    /// 15 Hz, thermometer disabled, a gain of +/- 1.3 Gauss, and sleep mode.
+    /// On the LSM303AGR, CFG_REG_A_M through CFG_REG_C_M are rewritten instead,
+    /// which leaves it idle at 10 Hz.
+    /// Note that this differs from the configuration set by `from_i2c_device`;
+    /// call `wake` to resume measurements.
+    /// Calibration settings are not affected.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.reset()?;
+    /// sensor.wake()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reset(&mut self) -> Result<()> {
+        use crate::registers::CfgRegCM;
+
+        if self.variant == Variant::Agr {
+            let cfg_reg_a_m = DataRate::Rate15Hz.agr_bits() | Mode::Sleep.agr_bits();
+            self.write_flags(MagRegister::CfgRegA, cfg_reg_a_m.bits())?;
+            self.write_flags(MagRegister::CfgRegB, 0)?;
+            let cfg_reg_c_m = CfgRegCM::empty();
+            self.write_flags(MagRegister::CfgRegC, cfg_reg_c_m.bits())?;
+            self.data_rate = DataRate::Rate15Hz;
+            self.gain = Gain::Gain_1_3;
+            self.mode = Mode::Sleep;
+            return Ok(());
+        }
+
+        let cra_reg_m = DataRate::Rate15Hz.bits();
+        self.write_flags(MagRegister::CraReg, cra_reg_m.bits())?;
+        self.data_rate = DataRate::Rate15Hz;
+        self.temperature_enabled = false;
+
+        let crb_reg_m = Gain::Gain_1_3.bits();
+        self.write_flags(MagRegister::CrbReg, crb_reg_m.bits())?;
+        self.gain = Gain::Gain_1_3;
+
+        let mr_reg_m = Mode::Sleep.bits();
+        self.write_flags(MagRegister::MrReg, mr_reg_m.bits())?;
+        self.mode = Mode::Sleep;
+
+        Ok(())
+    }
+
+
+    /// Check that the device is an LSM303 magnetometer.
+    ///
+    /// This reads the identification registers,
+    /// which always contain the string `"H43"`,
+    /// or `0x40` in WHO_AM_I_M on the LSM303AGR.
+    /// Returns `false` if they do not match,
+    /// which suggests that some other device is at this address.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// if !sensor.identify()? {
+    ///     println!("This does not look like an LSM303");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn identify(&mut self) -> Result<bool> {
+        match self.verify_identity() {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                if let ErrorKind::UnexpectedRegisterValue(..) = *e.kind() {
+                    return Ok(false);
+                }
+                Err(e)
+            }
+        }
+    }
+
+
+    /// Check that the device is an LSM303 magnetometer, failing if it is not.
+    ///
+    /// This is like `identify`, but fails with
+    /// `ErrorKind::UnexpectedRegisterValue` on the first mismatch,
+    /// reporting the register and what it contained.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// if let Err(e) = sensor.verify_identity() {
+    ///     // For example, "register 0x0A: expected 0x48, found 0xFF"
+    ///     println!("{}", e);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn verify_identity(&mut self) -> Result<()> {
+        check_identity(&mut *self.device, self.variant)
+    }
+
+
+    /// Read a register, and convert the value with `from_bits`.
+    fn read_flags<F>(&mut self, register: MagRegister, from_bits: fn(u8) -> F) -> Result<F> {
+        read_register(&mut *self.device, register).map(from_bits)
+    }
+
+
+    /// Write a value to a register.
+    fn write_flags(&mut self, register: MagRegister, bits: u8) -> Result<()> {
+        write_register(&mut *self.device, register, bits)
+    }
+
+
+    /// Read consecutive registers, starting at `base`, into a buffer.
+    fn read_block(&mut self, base: MagRegister, buffer: &mut [u8]) -> Result<()> {
+        read_registers(&mut *self.device, base, buffer)
+    }
+
+
+    /// Check that a register contains the expected value.
+    fn verify_register(&mut self, register: MagRegister, expected: u8) -> Result<()> {
+        let found = read_register(&mut *self.device, register)?;
+        if found != expected {
+            bail!(ErrorKind::UnexpectedRegisterValue(register.addr(), expected, found));
+        }
+        Ok(())
+    }
+
+
+    /// Read the magnetometer, returning the magnetic field as a vector.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let field = sensor.read_magnetic_field()?;
+    /// println!("Magnetic field: ({}, {}, {})",
+    ///     field.x, field.y, field.z);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field(&mut self) -> Result<MagneticField> {
+        let raw = self.read_raw_magnetic_field()?;
+        let out = to_tesla(raw, self.lsb_per_gauss());
+        Ok(self.axis_remap.apply(out))
+    }
+
+
+    /// Read the magnetometer, making sure that all three axes are from the same measurement.
+    ///
+    /// `read_magnetic_field` reads the output in a single transfer,
+    /// but when sampling close to the data rate, a new measurement
+    /// may still arrive partway through.
+    /// This waits for a new measurement, reads it,
+    /// and then checks the status register again.
+    /// Reading the output clears the data ready flag,
+    /// so if it is already set again, the next measurement landed during the read,
+    /// and the read is repeated, up to `read_retries` times.
+    /// Fails with `ErrorKind::DataOverrun` if every attempt was interrupted,
+    /// or with `ErrorKind::ConversionTimeout` if no measurement arrives.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let field = sensor.read_magnetic_field_synced()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field_synced(&mut self) -> Result<MagneticField> {
+        for _ in 0..=self.read_retries {
+            self.wait_for_data()?;
+            let raw = self.read_raw_magnetic_field()?;
+            if !self.data_ready()? {
+                let out = to_tesla(raw, self.lsb_per_gauss());
+                return Ok(self.axis_remap.apply(out));
+            }
+        }
+        bail!(ErrorKind::DataOverrun);
+    }
+
+
+    /// Read the magnetometer, returning the magnetic field in Gauss.
+    ///
+    /// The raw output is scaled according to the current gain.
+    /// Fails with `ErrorKind::FieldOverflow` if any axis of the LSM303DLHC
+    /// is saturated, in which case a coarser gain is needed; see `is_saturated`.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let field = sensor.read_magnetic_field_gauss()?;
+    /// println!("Magnetic field: ({}, {}, {}) G",
+    ///     field.x, field.y, field.z);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field_gauss(&mut self) -> Result<Vector3<f32>> {
+        self.read_magnetic_field_in(MagneticUnit::Gauss)
+    }
+
+
+    /// Read the magnetometer, returning the magnetic field in the given units.
+    ///
+    /// Unlike `read_raw_magnetic_field`, the axis remapping is applied
+    /// even to `MagneticUnit::Raw`.
+    /// With any other unit, this fails with `ErrorKind::FieldOverflow`
+    /// if any axis of the LSM303DLHC is saturated, as `read_magnetic_field_gauss` does.
+    ///
+    /// ```no_run
+    /// # use lsm303::magnetometer::{MagneticUnit, Magnetometer};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let field = sensor.read_magnetic_field_in(MagneticUnit::MicroTesla)?;
+    /// println!("Magnetic field: {:.1} µT", field);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field_in(&mut self, unit: MagneticUnit) -> Result<Vector3<f32>> {
+        let raw = self.read_raw_magnetic_field()?;
+        if unit != MagneticUnit::Raw && self.variant == Variant::Dlhc && is_saturated(raw) {
+            bail!(ErrorKind::FieldOverflow);
+        }
+
+        let out = match unit {
+            MagneticUnit::Raw => raw.map(f32::from),
+            MagneticUnit::Gauss => to_gauss(raw, self.lsb_per_gauss()),
+            MagneticUnit::MicroTesla => {
+                to_gauss(raw, self.lsb_per_gauss()).map(|gauss| gauss * MICROTESLA_PER_GAUSS)
+            }
+        };
+        Ok(self.axis_remap.apply(out))
+    }
+
+
+    /// Read the magnetometer, returning the magnetic field in milligauss.
+    ///
+    /// This uses only integer arithmetic, for targets without a floating point unit.
+    /// Each axis is multiplied and divided by integers from the same table
+    /// as `read_magnetic_field_gauss`; the division rounds toward zero,
+    /// so the result is less than 1 mG closer to zero than the exact value.
+    /// The axis remapping is applied, but not the soft iron correction.
+    /// Fails with `ErrorKind::FieldOverflow` if any axis of the LSM303DLHC is saturated.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let field = sensor.read_magnetic_field_milligauss()?;
+    /// println!("Magnetic field: ({}, {}, {}) mG", field.x, field.y, field.z);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field_milligauss(&mut self) -> Result<Vector3<i32>> {
+        let raw = self.read_raw_magnetic_field()?;
+        if self.variant == Variant::Dlhc && is_saturated(raw) {
+            bail!(ErrorKind::FieldOverflow);
+        }
+
+        let ((num_xy, den_xy), (num_z, den_z)) = self.variant.milligauss_per_lsb(self.gain);
+        let out = Vector3 {
+            x: i32::from(raw.x) * num_xy / den_xy,
+            y: i32::from(raw.y) * num_xy / den_xy,
+            z: i32::from(raw.z) * num_z / den_z,
+        };
+        Ok(self.axis_remap.apply(out))
+    }
+
+
+    /// Read the magnetometer, in Gauss, correcting for the drift in sensitivity with temperature.
+    ///
+    /// The field and the temperature are read together, and the field is divided by
+    /// `1 + coefficient * (temperature - reference)`; see `set_temp_coefficient`.
+    /// The reference is the `ref_offset_c` of `temp_calibration`, 25°C by default.
+    /// This assumes that the drift is linear, and that the thermometer is accurate;
+    /// as noted in `read_temperature_celsius`, it is not calibrated,
+    /// so an offset in its reading shifts the correction as well.
+    /// Fails with `ErrorKind::TemperatureDisabled` if the thermometer is off,
+    /// and as `read_magnetic_field_gauss` does.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// // -0.03 %/°C
+    /// sensor.set_temp_coefficient(-0.0003);
+    /// let field = sensor.read_magnetic_field_temp_compensated()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field_temp_compensated(&mut self) -> Result<Vector3<f32>> {
+        let field = self.read_magnetic_field_gauss()?;
+        let temp = self.read_temperature_celsius()?;
+
+        let reference = self.temp_calibration.ref_offset_c;
+        let scale = 1.0 + self.temp_coefficient * (temp - reference);
+        self.last_reading_flags.set(ReadingFlags::TEMPERATURE, self.temp_coefficient != 0.0);
+        Ok(field.map(|v| v / scale))
+    }
+
+
+    /// Set the drift in sensitivity with temperature, as a fraction per °C.
+    ///
+    /// For example, a sensitivity that drops by 0.03% for each degree
+    /// above the reference temperature is `-0.0003`.
+    /// The LSM303DLHC datasheet (DocID018771, Table 3) gives no nominal coefficient
+    /// for the magnetometer, so the default is 0, which applies no correction;
+    /// to find it, read a steady field while the sensor warms up.
+    /// This is used by `read_magnetic_field_temp_compensated`.
+    pub fn set_temp_coefficient(&mut self, coefficient: f32) {
+        self.temp_coefficient = coefficient;
+    }
+
+
+    /// Get the drift in sensitivity with temperature, as a fraction per °C.
+    pub fn temp_coefficient(&self) -> f32 {
+        self.temp_coefficient
+    }
+
+
+    /// Read the magnetometer, correcting for hard and soft iron distortion.
+    ///
+    /// The hard iron offset is subtracted from the raw output of each axis,
+    /// and the result is multiplied by the soft iron matrix.
+    /// See `set_hard_iron_offset`, `calibrate_hard_iron`, and `set_soft_iron_matrix`.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.calibrate_hard_iron(500, |_, _| true)?;
+    /// let field = sensor.read_magnetic_field_calibrated()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field_calibrated(&mut self) -> Result<Vector3<f32>> {
+        let raw = self.read_raw_magnetic_field()?;
+        let offset = self.hard_iron_offset;
+
+        let v = [
+            f32::from(raw.x) - f32::from(offset.x),
+            f32::from(raw.y) - f32::from(offset.y),
+            f32::from(raw.z) - f32::from(offset.z),
+        ];
+        let m = &self.soft_iron_matrix;
+        let row = |i: usize| m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2];
+
+        let out = Vector3 { x: row(0), y: row(1), z: row(2) };
+        let hard_iron = offset != Vector3 { x: 0, y: 0, z: 0 };
+        self.last_reading_flags.set(ReadingFlags::HARD_IRON, hard_iron);
+        self.last_reading_flags.set(ReadingFlags::SOFT_IRON, *m != IDENTITY);
+        Ok(self.axis_remap.apply(out))
+    }
+
+
+    /// Read the magnetometer, adjusting the gain to suit the field.
+    ///
+    /// If any axis is saturated, the gain is stepped to the next coarser range
+    /// and the field is measured again, until it fits.
+    /// If the field would comfortably fit in a finer range,
+    /// the gain is stepped down instead, to regain resolution.
+    /// Fails with `ErrorKind::FieldOverflow` if the field
+    /// is too strong even for the coarsest gain.
+    /// The final gain is available from `gain`.
+    ///
+    /// The LSM303AGR has a fixed range, so it is simply read.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let field = sensor.read_magnetic_field_autorange()?;
+    /// println!("{} at {:?}", field.fmt_gauss(), sensor.gain());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field_autorange(&mut self) -> Result<Vector3<f32>> {
+        if self.variant == Variant::Agr {
+            return self.read_magnetic_field_gauss();
+        }
+
+        let initial_gain = self.gain;
+        // Once the gain has been raised, lowering it again could oscillate.
+        let mut raised = false;
+        loop {
+            let raw = self.read_raw_magnetic_field()?;
+
+            let next = if is_saturated(raw) {
+                raised = true;
+                match self.gain.coarser() {
+                    Some(gain) => gain,
+                    None => bail!(ErrorKind::FieldOverflow),
+                }
+            } else {
+                match self.gain.finer() {
+                    Some(gain) if !raised && self.fits_with_headroom(raw, gain) => gain,
+                    _ => {
+                        let changed = self.gain != initial_gain;
+                        self.last_reading_flags.set(ReadingFlags::AUTO_RANGE, changed);
+                        return Ok(self.axis_remap.apply(to_gauss(raw, self.lsb_per_gauss())));
+                    }
+                }
+            };
+
+            self.set_gain(next)?;
+            // The measurement in progress may still use the old gain, so skip it.
+            self.wait_for_data()?;
+            self.read_raw_magnetic_field()?;
+            self.wait_for_data()?;
+        }
+    }
+
+
+    /// Check whether a reading would use at most half the range of another gain.
+    fn fits_with_headroom(&self, raw: Vector3<i16>, gain: Gain) -> bool {
+        let (xy, z) = self.gain.lsb_per_gauss();
+        let (new_xy, new_z) = gain.lsb_per_gauss();
+        let fits = |value: i16, ratio: f32| f32::from(value).abs() * ratio < FULL_SCALE / 2.0;
+
+        fits(raw.x, new_xy / xy) && fits(raw.y, new_xy / xy) && fits(raw.z, new_z / z)
+    }
+
+
+    /// Get the corrections that were applied to the last reading.
+    ///
+    /// Every read of the output clears the flags, and the corrected reads set them:
+    /// `read_magnetic_field_calibrated` sets `HARD_IRON` and `SOFT_IRON`,
+    /// `read_magnetic_field_temp_compensated` sets `TEMPERATURE`,
+    /// and `read_magnetic_field_autorange` sets `AUTO_RANGE` if it changed the gain.
+    /// Store them with each reading in a log, so that corrected and uncorrected data are not mixed.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::magnetometer::ReadingFlags;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let field = sensor.read_magnetic_field_calibrated()?;
+    /// if !sensor.last_reading_flags().contains(ReadingFlags::HARD_IRON) {
+    ///     println!("Not calibrated yet");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn last_reading_flags(&self) -> ReadingFlags {
+        self.last_reading_flags
+    }
+
+
+    /// Set the hard iron offset, in raw units at the current gain.
+    ///
+    /// Hard iron distortion is caused by magnetized material near the sensor,
+    /// which adds a constant bias to every measurement.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::common::Vector3;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_hard_iron_offset(Vector3 { x: 12, y: -40, z: 3 });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_hard_iron_offset(&mut self, offset: Vector3<i16>) {
+        self.hard_iron_offset = offset;
+    }
+
+
+    /// Get the hard iron offset.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let offset = sensor.hard_iron_offset();
+    /// assert_eq!((offset.x, offset.y, offset.z), (0, 0, 0));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn hard_iron_offset(&self) -> Vector3<i16> {
+        self.hard_iron_offset
+    }
+
+
+    /// Set the soft iron correction matrix.
+    ///
+    /// Soft iron distortion is caused by material near the sensor
+    /// that bends the field, stretching and skewing the measurements.
+    /// The matrix is typically found by fitting an ellipsoid to
+    /// measurements taken in many orientations.
+    /// It is applied after the hard iron offset is subtracted.
+    /// The default is the identity matrix, which has no effect.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_soft_iron_matrix([
+    ///     [1.02, 0.01, 0.00],
+    ///     [0.01, 0.97, 0.02],
+    ///     [0.00, 0.02, 1.01],
+    /// ]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_soft_iron_matrix(&mut self, m: [[f32; 3]; 3]) {
+        self.soft_iron_matrix = m;
+    }
+
+
+    /// Get the soft iron correction matrix.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// assert_eq!(sensor.soft_iron_matrix()[0], [1.0, 0.0, 0.0]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn soft_iron_matrix(&self) -> [[f32; 3]; 3] {
+        self.soft_iron_matrix
+    }
+
+
+    /// Set the mapping from the axes of the chip to the axes of the board.
+    ///
+    /// This is applied to `read_magnetic_field`, `read_magnetic_field_gauss`,
+    /// `read_magnetic_field_calibrated`, and the heading,
+    /// but not to the raw output, so calibration is unaffected.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::common::{Axis, AxisRemap};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// // The chip is mounted upside down.
+    /// let remap = AxisRemap::new(Axis::PlusX, Axis::MinusY, Axis::MinusZ)?;
+    /// sensor.set_axis_remap(remap);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_axis_remap(&mut self, axis_remap: AxisRemap) {
+        self.axis_remap = axis_remap;
+    }
+
+
+    /// Get the mapping from the axes of the chip to the axes of the board.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::common::AxisRemap;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// assert_eq!(sensor.axis_remap(), AxisRemap::default());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn axis_remap(&self) -> AxisRemap {
+        self.axis_remap
+    }
+
+
+    /// Measure and store the hard iron offset.
+    ///
+    /// This takes the given number of measurements,
+    /// and sets the offset to the midpoint of the range of each axis.
+    /// While this runs, the board must be rotated through every orientation,
+    /// so that each axis sees both the highest and lowest possible field.
+    ///
+    /// `on_sample` is called after each measurement, with the number taken so far
+    /// and the raw measurement, to show progress.
+    /// Returning `false` from it stops the calibration early,
+    /// with the offset taken from the measurements so far.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// println!("Rotate the board in all directions...");
+    /// let offset = sensor.calibrate_hard_iron(500, |taken, _| {
+    ///     print!("\r{}/500", taken);
+    ///     true
+    /// })?;
+    /// println!("Hard iron offset: ({}, {}, {})", offset.x, offset.y, offset.z);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn calibrate_hard_iron<F>(
+        &mut self,
+        samples: usize,
+        mut on_sample: F,
+    ) -> Result<Vector3<i16>>
+    where
+        F: FnMut(usize, Vector3<i16>) -> bool,
+    {
+        use std::cmp::{max, min};
+
+        // Without any samples, there is nothing to correct.
+        if samples == 0 {
+            return Ok(self.hard_iron_offset);
+        }
+
+        let mut low = Vector3 { x: i16::MAX, y: i16::MAX, z: i16::MAX };
+        let mut high = Vector3 { x: i16::MIN, y: i16::MIN, z: i16::MIN };
+        for taken in 1..=samples {
+            self.wait_for_data()?;
+            let raw = self.read_raw_magnetic_field()?;
+            low = Vector3 { x: min(low.x, raw.x), y: min(low.y, raw.y), z: min(low.z, raw.z) };
+            high = Vector3 { x: max(high.x, raw.x), y: max(high.y, raw.y), z: max(high.z, raw.z) };
+            if !on_sample(taken, raw) {
+                break;
+            }
+        }
+
+        let midpoint = |low: i16, high: i16| ((i32::from(low) + i32::from(high)) / 2) as i16;
+        let offset = Vector3 {
+            x: midpoint(low.x, high.x),
+            y: midpoint(low.y, high.y),
+            z: midpoint(low.z, high.z),
+        };
+        self.hard_iron_offset = offset;
+
+        Ok(offset)
+    }
+
+
+    /// Check that the magnetometer has not latched up.
+    ///
+    /// A latched up sensor keeps reporting new data,
+    /// but every measurement is identical to the last.
+    /// This takes several new measurements, and returns `false`
+    /// if they are all bit for bit the same; noise makes that vanishingly
+    /// unlikely for a working sensor. Fails with `ErrorKind::ConversionTimeout`
+    /// if the sensor stops reporting new data altogether.
+    /// See `set_responsive_check` for the number of measurements and their spacing.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// if !sensor.is_responsive()? {
+    ///     println!("The magnetometer is stuck; power cycle it");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_responsive(&mut self) -> Result<bool> {
+        let mut first = None;
+        for i in 0..self.responsive_samples.max(2) {
+            if i > 0 {
+                self.delay.delay(self.responsive_interval);
+            }
+            self.wait_for_data()?;
+            let raw = self.read_raw_magnetic_field()?;
+            match first {
+                None => first = Some(raw),
+                Some(first) if first != raw => return Ok(true),
+                Some(_) => {}
+            }
+        }
+        Ok(false)
+    }
+
+
+    /// Set how `is_responsive` checks for a latched up sensor.
+    ///
+    /// It compares `samples` measurements, at least two,
+    /// waiting `interval` between them on top of the wait for new data.
+    /// The default is 5 measurements, with no extra wait.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use std::time::Duration;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_responsive_check(10, Duration::from_millis(50));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_responsive_check(&mut self, samples: usize, interval: Duration) {
+        self.responsive_samples = samples;
+        self.responsive_interval = interval;
+    }
+
+
+    /// Check that the magnetometer is working, using its self test.
+    ///
+    /// The self test applies a known field to the sensor.
+    /// Measurements are averaged with and without it,
+    /// and the change of each axis is checked against the datasheet.
+    /// Returns `false` if any axis is out of range;
+    /// see `self_test_detailed` for the change of each axis.
+    /// Fails with `ErrorKind::UnexpectedRegisterValue`
+    /// if the self test could not be enabled.
+    /// The previous configuration is restored afterwards.
+    /// This takes a bit over a second, during which the board should be still.
+    ///
+    /// Only the LSM303AGR documents a self test;
+    /// fails with `ErrorKind::UnsupportedByVariant` on the LSM303DLHC.
+    ///
+    /// ```no_run
+    /// # extern crate lsm303;
+    /// # use lsm303::magnetometer::{MagnetometerBuilder, Variant};
+    /// # extern crate i2cdev;
+    /// # use i2cdev::linux::LinuxI2CDevice;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let device = LinuxI2CDevice::new("/dev/i2c-1", 0x3C >> 1)?;
+    /// let mut sensor = MagnetometerBuilder::new()
+    ///     .variant(Variant::Agr)
+    ///     .build(device)?;
+    /// if !sensor.self_test()? {
+    ///     println!("The magnetometer is out of spec");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn self_test(&mut self) -> Result<bool> {
+        Ok(self.self_test_detailed()?.passed())
+    }
+
+
+    /// Run the self test, returning the change of each axis and whether it is in range.
+    ///
+    /// This is `self_test` with the measurements kept,
+    /// so that marginal axes can be spotted and tracked.
+    ///
+    /// ```no_run
+    /// # extern crate lsm303;
+    /// # use lsm303::magnetometer::{MagnetometerBuilder, Variant};
+    /// # extern crate i2cdev;
+    /// # use i2cdev::linux::LinuxI2CDevice;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let device = LinuxI2CDevice::new("/dev/i2c-1", 0x3C >> 1)?;
+    /// let mut sensor = MagnetometerBuilder::new()
+    ///     .variant(Variant::Agr)
+    ///     .build(device)?;
+    /// let report = sensor.self_test_detailed()?;
+    /// println!("Deltas: ({}, {}, {}), allowed {} to {}",
+    ///          report.delta.x, report.delta.y, report.delta.z,
+    ///          report.min, report.max);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn self_test_detailed(&mut self) -> Result<SelfTestReport> {
+        use crate::registers::{CfgRegAM, CfgRegCM};
+
+        if self.variant != Variant::Agr {
+            bail!(ErrorKind::UnsupportedByVariant);
+        }
+
+        let cfg_reg_a_m = self.read_flags(MagRegister::CfgRegA, CfgRegAM::from_bits_truncate)?;
+        let cfg_reg_c_m = self.read_flags(MagRegister::CfgRegC, CfgRegCM::from_bits_truncate)?;
+        let data_rate = self.data_rate;
+
+        // Measure continuously at 100 Hz, as the datasheet procedure requires.
+        self.data_rate = DataRate::Rate220Hz;
+        let result = self.run_self_test();
+
+        // Restore the configuration, whether or not the test succeeded.
+        self.data_rate = data_rate;
+        self.write_flags(MagRegister::CfgRegC, cfg_reg_c_m.bits())?;
+        self.write_flags(MagRegister::CfgRegA, cfg_reg_a_m.bits())?;
+
+        result
+    }
+
+
+    /// Take the self test measurements, leaving the configuration modified.
+    fn run_self_test(&mut self) -> Result<SelfTestReport> {
+        use crate::registers::{CfgRegAM, CfgRegCM};
+
+        let cfg_reg_a_m = CfgRegAM::COMP_TEMP_EN | self.data_rate.agr_bits() |
+            Mode::Continuous.agr_bits();
+        self.write_flags(MagRegister::CfgRegA, cfg_reg_a_m.bits())?;
+        let cfg_reg_c_m = CfgRegCM::BDU;
+        self.write_flags(MagRegister::CfgRegC, cfg_reg_c_m.bits())?;
+        self.delay.delay(Duration::from_millis(20));
+        let without = self.average_raw_magnetic_field()?;
+
+        let cfg_reg_c_m = CfgRegCM::BDU | CfgRegCM::Self_test;
+        self.write_flags(MagRegister::CfgRegC, cfg_reg_c_m.bits())?;
+        // A device that ignores the write would otherwise just fail the range check.
+        self.verify_register(MagRegister::CfgRegC, cfg_reg_c_m.bits())?;
+        self.delay.delay(Duration::from_millis(60));
+        let with = self.average_raw_magnetic_field()?;
+
+        let delta = Vector3 {
+            x: (with.x - without.x).abs(),
+            y: (with.y - without.y).abs(),
+            z: (with.z - without.z).abs(),
+        };
+        Ok(SelfTestReport::new(delta, SELF_TEST_RANGE))
+    }
+
+
+    /// Average the raw output over `SELF_TEST_SAMPLES` new measurements.
+    ///
+    /// The first measurement is discarded, since it may predate a change of configuration.
+    fn average_raw_magnetic_field(&mut self) -> Result<Vector3<i32>> {
+        self.wait_for_data()?;
+        self.read_raw_magnetic_field()?;
+
+        let mut sum = Vector3 { x: 0, y: 0, z: 0 };
+        for _ in 0..SELF_TEST_SAMPLES {
+            self.wait_for_data()?;
+            let raw = self.read_raw_magnetic_field()?;
+            sum.x += i32::from(raw.x);
+            sum.y += i32::from(raw.y);
+            sum.z += i32::from(raw.z);
+        }
+
+        Ok(sum.map(|total| total / SELF_TEST_SAMPLES))
+    }
+
+
+    /// Read the magnetometer, returning the raw output of each axis.
+    ///
+    /// The meaning of the values depends on the current gain.
+    /// The axes are those of the chip, ignoring `set_axis_remap`.
+    /// This does not allocate, so it is suitable for tight sampling loops.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let raw = sensor.read_raw_magnetic_field()?;
+    /// println!("Raw magnetic field: ({}, {}, {})",
+    ///     raw.x, raw.y, raw.z);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_raw_magnetic_field(&mut self) -> Result<Vector3<i16>> {
+        let mut bytes = [0; 6];
+        self.read_magnetic_field_raw(&mut bytes)?;
+        Ok(self.output_layout.decode(bytes))
+    }
+
+
+    /// Read the raw output of each axis, together with the gain it was measured at.
+    ///
+    /// When the gain changes between readings, this is what is needed to scale each one.
+    /// The gain is the cached setting, so this costs no more than `read_raw_magnetic_field`.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::magnetometer::sensitivity;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let (raw, gain) = sensor.read_magnetic_field_tagged()?;
+    /// let (scale_xy, _) = sensitivity(gain);
+    /// println!("X: {} G", f32::from(raw.x) / scale_xy);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field_tagged(&mut self) -> Result<(Vector3<i16>, Gain)> {
+        let raw = self.read_raw_magnetic_field()?;
+        Ok((raw, self.gain))
+    }
+
+
+    /// Read the output registers of the magnetometer, without decoding them.
+    ///
+    /// This is for forwarding the measurement, say over a radio link,
+    /// to be decoded elsewhere.
+    /// On the LSM303DLHC, the bytes are the X, Z and Y axes, in that order,
+    /// each as a big endian `i16`.
+    /// On the LSM303AGR, they are the X, Y and Z axes, each little endian.
+    /// `OutputLayout::decode` decodes either; see `output_layout`.
+    /// Like `read_raw_magnetic_field`, this is retried on failure, and does not allocate.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let mut frame = [0; 6];
+    /// sensor.read_magnetic_field_raw(&mut frame)?;
+    ///
+    /// // On the receiving end:
+    /// let x = i16::from_be_bytes([frame[0], frame[1]]);
+    /// let z = i16::from_be_bytes([frame[2], frame[3]]);
+    /// let y = i16::from_be_bytes([frame[4], frame[5]]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field_raw(&mut self, out: &mut [u8; 6]) -> Result<()> {
+        let register = self.output_register;
+        self.last_reading_flags = ReadingFlags::empty();
+
+        let mut attempts = 0;
+        loop {
+            match self.read_block(register, &mut out[..]) {
+                Ok(()) => return Ok(()),
+                Err(ref e) if attempts < self.read_retries && is_transient(e) => attempts += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+
+    /// Read the magnetic field from other output registers, for clones with another register map.
+    ///
+    /// `register` is the first of the six output registers,
+    /// and `layout` is the order of the axes in them.
+    /// By default, these are those of the variant:
+    /// `OutXH` and `XzyBigEndian` on the LSM303DLHC,
+    /// and `OutxLReg` and `XyzLittleEndian` on the LSM303AGR.
+    /// Only the reads of the magnetic field are affected.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::magnetometer::OutputLayout;
+    /// # use lsm303::registers::MagRegister;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_output_layout(MagRegister::OutxLReg, OutputLayout::XyzLittleEndian);
+    /// let raw = sensor.read_raw_magnetic_field()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_output_layout(&mut self, register: MagRegister, layout: OutputLayout) {
+        self.output_register = register;
+        self.output_layout = layout;
+    }
+
+
+    /// Get the first output register, and the layout of the output.
+    pub fn output_layout(&self) -> (MagRegister, OutputLayout) {
+        (self.output_register, self.output_layout)
+    }
+
+
+    /// Set how many times a failed read of the output is retried.
+    ///
+    /// Noisy buses occasionally drop or truncate a transfer.
+    /// Each read of the magnetic field is retried up to this many times
+    /// before the error is returned. The default is 3;
+    /// with 0, a failed read is returned immediately.
+    /// Only short reads, and the bus errors that `RetryPolicy::is_recoverable` accepts,
+    /// are retried; a NACK or an unsupported transfer is returned at once.
+    /// Successful reads are not slowed down either way.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_read_retries(10);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_read_retries(&mut self, retries: u8) {
+        self.read_retries = retries;
+    }
+
+
+    /// Get how many times a failed read of the output is retried.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// assert_eq!(sensor.read_retries(), 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_retries(&self) -> u8 {
+        self.read_retries
+    }
+
+
+    /// Set how multi-byte registers outside the output are read.
+    ///
+    /// Some I2C adapters, such as bit-banged buses, do not implement
+    /// the SMBus block read, which fails with `ErrorKind::SmbusUnsupported`.
+    /// `TransferMode::Plain` writes the register address and reads the values
+    /// with `I2CWriteRead::write_read`, which any adapter can do.
+    /// The default is `TransferMode::Smbus`.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::magnetometer::TransferMode;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_transfer_mode(TransferMode::Plain);
+    /// let raw = sensor.read_temperature()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_transfer_mode(&mut self, mode: TransferMode) {
+        self.transfer_mode = mode;
+    }
+
+
+    /// Set how to wait for the sensor, while polling for a measurement.
+    ///
+    /// The default is `delay::StdDelay`, which puts the thread to sleep.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::delay::DelayProvider;
+    /// # use std::time::Duration;
+    /// struct Yield;
+    ///
+    /// impl DelayProvider for Yield {
+    ///     fn delay(&mut self, _duration: Duration) {
+    ///         std::thread::yield_now();
+    ///     }
+    /// }
+    ///
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_delay(Yield);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_delay<D>(&mut self, delay: D)
+    where
+        D: DelayProvider + Send + 'static,
+    {
+        self.delay = Box::new(delay);
+    }
+
+
+    /// Get how multi-byte registers outside the output are read.
+    pub fn transfer_mode(&self) -> TransferMode {
+        self.transfer_mode
+    }
+
+
+    /// Set whether configuration writes are read back and checked.
+    ///
+    /// Some buses swallow a failed write, which would leave the cached
+    /// configuration out of sync with the chip.
+    /// With verification on, `set_gain` reads the register back,
+    /// and fails with `ErrorKind::VerifyFailed` if it does not match.
+    /// This costs an extra read, so it can be turned off; the default is on.
+    ///
+    /// ```no_run
+    /// # use lsm303::magnetometer::{Magnetometer, Gain};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_verify_writes(false);
+    /// sensor.set_gain(Gain::Gain_1_9)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_verify_writes(&mut self, verify_writes: bool) {
+        self.verify_writes = verify_writes;
+    }
+
+
+    /// Get whether configuration writes are read back and checked.
+    pub fn verify_writes(&self) -> bool {
+        self.verify_writes
+    }
+
+
+    /// The sensitivity of the XY and Z axes, in LSB/Gauss.
+    fn lsb_per_gauss(&self) -> (f32, f32) {
+        self.variant.lsb_per_gauss(self.gain)
+    }
+
+
+    /// Set the measurement mode, leaving the other settings alone.
+    ///
+    /// `sleep`, `wake`, and `read_magnetic_field_single` are shorthands for this.
+    /// After a single conversion, the magnetometer goes to sleep by itself,
+    /// though `mode` still reports `Mode::SingleConversion`.
+    /// Every mode is allowed at every data rate.
+    ///
+    /// ```no_run
+    /// # use lsm303::magnetometer::{Magnetometer, Mode};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_mode(Mode::Sleep)?;
+    /// assert_eq!(sensor.mode(), Mode::Sleep);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_mode(&mut self, mode: Mode) -> Result<()> {
+        use crate::registers::CfgRegAM;
+        type R = CfgRegAM;
+
+        match self.variant {
+            Variant::Dlhc => {
+                let mr_reg_m = mode.bits();
+                self.write_flags(MagRegister::MrReg, mr_reg_m.bits())?;
+            }
+            Variant::Agr => {
+                let mut flags = self.read_flags(MagRegister::CfgRegA, R::from_bits_truncate)?;
+                flags.remove(R::MD1 | R::MD0);
+                flags.insert(mode.agr_bits());
+                self.write_flags(MagRegister::CfgRegA, flags.bits())?;
+            }
+        }
+        log_info!("magnetometer mode set to {:?}", mode);
+        self.mode = mode;
+
+        Ok(())
+    }
+
+
+    /// Get the current measurement mode.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+
+    /// Get the variant of the LSM303.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::magnetometer::Variant;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// assert_eq!(sensor.variant(), Variant::Dlhc);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+
+    /// Read the magnetometer, returning the compass heading in degrees.
+    ///
+    /// The heading is in the range `[0, 360)`, measured from magnetic north.
+    /// This assumes that the board is held flat;
+    /// fails with `ErrorKind::NoFieldDetected` if there is no horizontal field.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let heading = sensor.heading()?;
+    /// println!("Heading: {} degrees", heading);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn heading(&mut self) -> Result<f32> {
+        self.heading_with_declination(0.0)
+    }
+
+
+    /// Read the magnetometer, returning the heading relative to true north.
+    ///
+    /// The declination is the angle from true north to magnetic north,
+    /// in degrees, with east being positive.
+    /// It is wrapped to a single turn, as by `compass::apply_declination`;
+    /// fails with `ErrorKind::InvalidArgument`, without reading the sensor,
+    /// if it is NaN or infinite.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// // Ottawa, Canada
+    /// let heading = sensor.heading_with_declination(-12.8)?;
+    /// println!("Heading: {} degrees", heading);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn heading_with_declination(&mut self, declination: f32) -> Result<f32> {
+        if !declination.is_finite() {
+            bail!(ErrorKind::InvalidArgument);
+        }
+
+        // Use scaled values, since remapping may bring in the Z axis.
+        let field = self.read_magnetic_field_gauss()?;
+        if field.x == 0.0 && field.y == 0.0 {
+            bail!(ErrorKind::NoFieldDetected);
+        }
+
+        let heading = field.y.atan2(field.x).to_degrees();
+        apply_declination(heading, declination)
+    }
+
+
+    /// Take a single measurement, leaving the magnetometer idle afterwards.
+    ///
+    /// This is useful for battery powered applications
+    /// that only need an occasional reading.
+    /// Fails with `ErrorKind::ConversionTimeout` if the measurement
+    /// does not complete in a reasonable amount of time.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let raw = sensor.read_magnetic_field_single()?;
+    /// println!("Raw magnetic field: ({}, {}, {})",
+    ///     raw.x, raw.y, raw.z);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_magnetic_field_single(&mut self) -> Result<Vector3<i16>> {
+        self.set_mode(Mode::SingleConversion)?;
+
+        let result = self.wait_for_data().and_then(|_| self.read_raw_magnetic_field());
+
+        // Leave the device idle, whether or not the conversion succeeded.
+        self.sleep()?;
+
+        result
+    }
+
+
+    /// Read the magnetometer continuously, as an iterator of raw measurements.
+    ///
+    /// Each call to `next` waits for a new measurement, at the current data rate,
+    /// and then reads it, so no measurement is returned twice.
+    /// The iterator never ends by itself; combine it with `take` for a fixed count.
+    /// To read as fast as the bus allows instead, without waiting,
+    /// use `std::iter::repeat_with(|| sensor.read_raw_magnetic_field())`.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let log = sensor.samples().take(100).collect::<lsm303::Result<Vec<_>>>()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn samples(&mut self) -> impl Iterator<Item = Result<Vector3<i16>>> + '_ {
+        std::iter::repeat_with(move || {
+            self.wait_for_data()?;
+            self.read_raw_magnetic_field()
+        })
+    }
+
+
+    /// Fill `out` with consecutive raw measurements, without allocating.
+    ///
+    /// As with `samples`, each measurement is waited for, so none is read twice.
+    /// Returns the number of measurements captured, which is `out.len()`
+    /// unless an error interrupted the capture; the rest of `out` is left untouched.
+    /// The error is only returned if no measurement was captured at all.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::common::Vector3;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let mut buffer = [Vector3 { x: 0, y: 0, z: 0 }; 256];
+    /// let captured = sensor.read_n(&mut buffer)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_n(&mut self, out: &mut [Vector3<i16>]) -> Result<usize> {
+        for (captured, slot) in out.iter_mut().enumerate() {
+            match self.wait_for_data().and_then(|_| self.read_raw_magnetic_field()) {
+                Ok(reading) => *slot = reading,
+                Err(e) if captured == 0 => return Err(e),
+                Err(_) => return Ok(captured),
+            }
+        }
+        Ok(out.len())
+    }
+
+
+    /// Wait until a new measurement is available.
+    ///
+    /// Fails with `ErrorKind::ConversionTimeout` if it takes
+    /// much longer than expected for the current data rate.
+    fn wait_for_data(&mut self) -> Result<()> {
+        let interval = self.sample_interval() / 10;
+        for _ in 0..CONVERSION_RETRIES {
+            if self.data_ready()? {
+                return Ok(());
+            }
+            self.delay.delay(interval);
+        }
+        bail!(ErrorKind::ConversionTimeout);
+    }
+
+
+    /// Check whether a new measurement is available.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// loop {
+    ///     if sensor.data_ready()? {
+    ///         let field = sensor.read_magnetic_field()?;
+    ///         // ...
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn data_ready(&mut self) -> Result<bool> {
+        let status = read_register(&mut *self.device, self.variant.status_register())?;
+        Ok(self.variant.data_ready(status))
+    }
+
+
+    /// Check whether the output registers are locked.
+    ///
+    /// The lock is set when some, but not all,
+    /// of the output registers have been read.
+    /// New measurements are not written to the output
+    /// until the lock is cleared, so data may be lost.
+    /// On the LSM303AGR, this instead reports that a measurement
+    /// was overwritten before it was read.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// if sensor.data_overrun()? {
+    ///     println!("Some measurements were missed");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn data_overrun(&mut self) -> Result<bool> {
+        use crate::registers::{SrRegM, StatusRegM};
+
+        if self.variant == Variant::Agr {
+            let flags = self.read_flags(MagRegister::StatusReg, StatusRegM::from_bits_truncate)?;
+            return Ok(flags.contains(StatusRegM::Zyxor));
+        }
+
+        let flags = self.read_flags(MagRegister::SrReg, SrRegM::from_bits_truncate)?;
+        Ok(flags.contains(SrRegM::LOCK))
+    }
+
+
+    /// Put the magnetometer to sleep, stopping continuous measurement.
+    ///
+    /// The gain and data rate are preserved, and are restored by `wake`.
+    /// Note that `read_magnetic_field` will return stale data
+    /// while the magnetometer is asleep.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.sleep()?;
+    /// // ...
+    /// sensor.wake()?;
+    /// let field = sensor.read_magnetic_field()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn sleep(&mut self) -> Result<()> {
+        self.set_mode(Mode::Sleep)
+    }
+
+
+    /// Wake the magnetometer, resuming continuous measurement.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.sleep()?;
+    /// sensor.wake()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn wake(&mut self) -> Result<()> {
+        self.set_mode(Mode::Continuous)
+    }
+
+
+    /// Set the gain of the magnetometer.
+    ///
+    /// Fails with `ErrorKind::UnsupportedByVariant` on the LSM303AGR,
+    /// which has a fixed sensitivity.
+    /// Unless `set_verify_writes(false)` has been called, the gain is read back,
+    /// and the current gain is only updated if it matches.
+    ///
+    /// ```no_run
+    /// # use lsm303::magnetometer::{Magnetometer, Gain};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_gain(Gain::Gain_4_0)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_gain(&mut self, gain: Gain) -> Result<()>
+    where
+        Dev::Error: Send + 'static,
+    {
+        use crate::registers::CrbRegM;
+        type R = CrbRegM;
+
+        if self.variant == Variant::Agr {
+            bail!(ErrorKind::UnsupportedByVariant);
+        }
+
+        let mut flags = self.read_flags(MagRegister::CrbReg, CrbRegM::from_bits_truncate)?;
+
+        flags.remove(R::GN2 | R::GN1 | R::GN0);
+        flags.insert(gain.bits());
+
+        self.write_flags(MagRegister::CrbReg, flags.bits())?;
+        if self.verify_writes {
+            if let Err(e) = self.verify_register(MagRegister::CrbReg, flags.bits()) {
+                if let ErrorKind::UnexpectedRegisterValue(..) = *e.kind() {
+                    return Err(e).chain_err(|| ErrorKind::VerifyFailed);
+                }
+                return Err(e);
+            }
+        }
+        log_info!("magnetometer gain set to {:?}", gain);
+        self.gain = gain;
+
+        Ok(())
+    }
+
+
+    /// Get the current gain of the magnetometer.
+    ///
+    /// ```no_run
+    /// # use lsm303::magnetometer::{Magnetometer, Gain};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_gain(Gain::Gain_4_0)?;
+    /// assert_eq!(sensor.gain(), Gain::Gain_4_0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn gain(&self) -> Gain {
+        self.gain
+    }
+
+
+    /// Set the rate at which the magnetic field is measured.
+    ///
+    /// The LSM303AGR only supports 10, 20, 50, and 100 Hz;
+    /// the nearest of those is used, and `data_rate` then reports
+    /// the `DataRate` that selects it; see `AgrDataRate`.
+    /// Every data rate is allowed in every mode.
+    ///
+    /// ```no_run
+    /// # use lsm303::magnetometer::{Magnetometer, DataRate};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_data_rate(DataRate::Rate75Hz)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_data_rate(&mut self, rate: DataRate) -> Result<()> {
+        use crate::registers::{CfgRegAM, CraRegM};
+        type R = CraRegM;
+
+        if self.variant == Variant::Agr {
+            let mut flags = self.read_flags(MagRegister::CfgRegA, CfgRegAM::from_bits_truncate)?;
+            flags.remove(CfgRegAM::ODR1 | CfgRegAM::ODR0);
+            flags.insert(rate.agr_bits());
+            self.write_flags(MagRegister::CfgRegA, flags.bits())?;
+
+            let flags = self.read_flags(MagRegister::CfgRegA, CfgRegAM::from_bits_truncate)?;
+            let rate = AgrDataRate::from_bits(flags);
+            log_info!("magnetometer data rate set to {:?}", rate);
+            self.data_rate = DataRate::from(rate);
+            return Ok(());
+        }
+
+        let mut flags = self.read_flags(MagRegister::CraReg, CraRegM::from_bits_truncate)?;
+        flags.remove(R::DO2 | R::DO1 | R::DO0);
+        flags.insert(rate.bits());
+
+        self.write_flags(MagRegister::CraReg, flags.bits())?;
+
+        // Read the setting back, so that the cached rate reflects the device.
+        let flags = self.read_flags(MagRegister::CraReg, CraRegM::from_bits_truncate)?;
+        self.data_rate = DataRate::from_bits(flags);
+        log_info!("magnetometer data rate set to {:?}", self.data_rate);
+
+        Ok(())
+    }
+
+
+    /// Set the gain and the data rate together.
+    ///
+    /// Both registers are read in one transfer, and written in another,
+    /// so the device is never left with only one of the changes.
+    /// Fails with `ErrorKind::UnsupportedByVariant` on the LSM303AGR,
+    /// which has a fixed sensitivity.
+    ///
+    /// ```no_run
+    /// # use lsm303::magnetometer::{DataRate, Gain, Magnetometer};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.reconfigure(Gain::Gain_4_0, DataRate::Rate75Hz)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reconfigure(&mut self, gain: Gain, rate: DataRate) -> Result<()> {
+        use crate::registers::{CraRegM, CrbRegM};
+
+        if self.variant == Variant::Agr {
+            bail!(ErrorKind::UnsupportedByVariant);
+        }
+
+        // CRA_REG_M and CRB_REG_M are adjacent, so the address auto-increments.
+        let mut bytes = [0; 2];
+        self.read_block(MagRegister::CraReg, &mut bytes)?;
+
+        let mut cra_reg_m = CraRegM::from_bits_truncate(bytes[0]);
+        cra_reg_m.remove(CraRegM::DO2 | CraRegM::DO1 | CraRegM::DO0);
+        cra_reg_m.insert(rate.bits());
+
+        let mut crb_reg_m = CrbRegM::from_bits_truncate(bytes[1]);
+        crb_reg_m.remove(CrbRegM::GN2 | CrbRegM::GN1 | CrbRegM::GN0);
+        crb_reg_m.insert(gain.bits());
+
+        let values = [cra_reg_m.bits(), crb_reg_m.bits()];
+        write_registers(&mut *self.device, MagRegister::CraReg, &values)?;
+        log_info!("magnetometer gain set to {:?}, and data rate to {:?}", gain, rate);
+        self.gain = gain;
+        self.data_rate = rate;
+
+        Ok(())
+    }
+
+
+    /// Get the current data rate of the magnetometer.
+    ///
+    /// ```no_run
+    /// # use lsm303::magnetometer::{Magnetometer, DataRate};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// assert_eq!(sensor.data_rate(), DataRate::Rate15Hz);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// On the LSM303AGR, this is the `DataRate` that selects the rate in use;
+    /// `agr_data_rate` gives the rate itself.
+    pub fn data_rate(&self) -> DataRate {
+        self.data_rate
+    }
+
+
+    /// Get the data rate of the LSM303AGR, or `None` on the LSM303DLHC.
+    ///
+    /// ```no_run
+    /// # extern crate i2cdev;
+    /// # extern crate lsm303;
+    /// # use i2cdev::linux::LinuxI2CDevice;
+    /// # use lsm303::magnetometer::{AgrDataRate, DataRate, MagnetometerBuilder, Variant};
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let device = LinuxI2CDevice::new("/dev/i2c-1", 0x3C >> 1)?;
+    /// let mut sensor = MagnetometerBuilder::new()
+    ///     .variant(Variant::Agr)
+    ///     .build(device)?;
+    /// sensor.set_data_rate(DataRate::Rate75Hz)?;
+    /// assert_eq!(sensor.agr_data_rate(), Some(AgrDataRate::Rate50Hz));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn agr_data_rate(&self) -> Option<AgrDataRate> {
+        match self.variant {
+            Variant::Dlhc => None,
+            Variant::Agr => Some(AgrDataRate::from(self.data_rate)),
+        }
+    }
+
+
+    /// The nominal time between measurements, at the current data rate.
+    ///
+    /// This is the time step for integrating the readings.
+    /// Comparing it with the timestamps of successive snapshots
+    /// shows whether the actual timing has drifted.
+    /// On the LSM303AGR, this is the interval of the rate that is actually used.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use std::thread;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let first = sensor.snapshot()?;
+    /// thread::sleep(sensor.sample_interval());
+    /// let second = sensor.snapshot()?;
+    /// let actual = second.timestamp - first.timestamp;
+    /// if actual > sensor.sample_interval() * 3 / 2 {
+    ///     println!("Sampling is running late: {:?}", actual);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn sample_interval(&self) -> Duration {
+        Duration::from_micros(u64::from(self.sample_interval_us()))
+    }
+
+
+    /// The nominal time between measurements, in microseconds.
+    ///
+    /// This is `sample_interval`, for integer-only code.
+    pub fn sample_interval_us(&self) -> u32 {
+        match self.variant {
+            Variant::Dlhc => self.data_rate.period_us(),
+            Variant::Agr => AgrDataRate::from(self.data_rate).period_us(),
+        }
+    }
+
+
+    /// Enable or disable the thermometer.
+    ///
+    /// The thermometer is enabled by default.
+    /// Disabling it saves a bit of power if it is not needed.
+    /// Fails with `ErrorKind::UnsupportedByVariant` on the LSM303AGR,
+    /// which has no thermometer on the magnetometer.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_temperature_enabled(false)?;
+    /// assert!(sensor.read_temperature().is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_temperature_enabled(&mut self, enabled: bool) -> Result<()> {
+        use crate::registers::CraRegM;
+
+        if self.variant == Variant::Agr {
+            bail!(ErrorKind::UnsupportedByVariant);
+        }
+
+        let mut flags = self.read_flags(MagRegister::CraReg, CraRegM::from_bits_truncate)?;
+        flags.set(CraRegM::TEMP_EN, enabled);
+
+        self.write_flags(MagRegister::CraReg, flags.bits())?;
+        log_info!("magnetometer thermometer enabled: {}", enabled);
+        self.temperature_enabled = enabled;
+
+        Ok(())
+    }
+
+
+    /// Check whether the thermometer is enabled.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// assert!(sensor.temperature_enabled());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn temperature_enabled(&self) -> bool {
+        self.temperature_enabled
+    }
+
+
+    /// Read the configuration from the device.
+    ///
+    /// The registers are decoded as they are, rather than taken from
+    /// the settings cached by this driver, so this shows the actual state of the chip.
+    /// Fails with `ErrorKind::UnsupportedByVariant` on the LSM303AGR,
+    /// and with `ErrorKind::UnexpectedRegisterValue` if the gain bits are not a valid setting.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let config = sensor.read_config()?;
+    /// println!("{:?}", config);
+    ///
+    /// sensor.reset()?;
+    /// sensor.apply_config(config)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_config(&mut self) -> Result<MagnetometerConfig> {
+        use crate::registers::{CraRegM, CrbRegM, MrRegM};
+
+        if self.variant == Variant::Agr {
+            bail!(ErrorKind::UnsupportedByVariant);
+        }
+
+        // CRA_REG_M, CRB_REG_M, and MR_REG_M are adjacent, so they are read together.
+        let mut bytes = [0; 3];
+        self.read_block(MagRegister::CraReg, &mut bytes)?;
+        let cra_reg_m = CraRegM::from_bits_truncate(bytes[0]);
+        let crb_reg_m = CrbRegM::from_bits_truncate(bytes[1]);
+        let mr_reg_m = MrRegM::from_bits_truncate(bytes[2]);
+
+        let gain = match Gain::from_bits(crb_reg_m) {
+            Some(gain) => gain,
+            None => bail!(ErrorKind::UnexpectedRegisterValue(
+                MagRegister::CrbReg.addr(),
+                Gain::Gain_1_3.bits().bits(),
+                bytes[1],
+            )),
+        };
+
+        Ok(MagnetometerConfig {
+            gain,
+            data_rate: DataRate::from_bits(cra_reg_m),
+            mode: Mode::from_bits(mr_reg_m),
+            temperature_enabled: cra_reg_m.contains(CraRegM::TEMP_EN),
+        })
+    }
+
+
+    /// Write a configuration to the device, as returned by `read_config`.
+    ///
+    /// All three registers are written in one transfer.
+    /// Fails with `ErrorKind::UnsupportedByVariant` on the LSM303AGR.
+    pub fn apply_config(&mut self, config: MagnetometerConfig) -> Result<()> {
+        use crate::registers::CraRegM;
+
+        if self.variant == Variant::Agr {
+            bail!(ErrorKind::UnsupportedByVariant);
+        }
+
+        let mut cra_reg_m = config.data_rate.bits();
+        cra_reg_m.set(CraRegM::TEMP_EN, config.temperature_enabled);
+        let crb_reg_m = config.gain.bits();
+        let mr_reg_m = config.mode.bits();
+
+        let values = [cra_reg_m.bits(), crb_reg_m.bits(), mr_reg_m.bits()];
+        write_registers(&mut *self.device, MagRegister::CraReg, &values)?;
+        self.gain = config.gain;
+        self.data_rate = config.data_rate;
+        self.mode = config.mode;
+        self.temperature_enabled = config.temperature_enabled;
+
+        Ok(())
+    }
+
+
+    /// Read the thermometer, returning the raw signed 12 bit value.
+    ///
+    /// The datasheet does not have quite enough information
+    /// to interpret this value; prefer `read_temperature_celsius`.
+    /// Discussions can be found in various places, such as
+    /// https://forum.pololu.com/t/16-bit-values-in-lsm303/8499/8
+    ///
+    /// Fails with `ErrorKind::UnsupportedByVariant` on the LSM303AGR,
+    /// which has no thermometer on the magnetometer,
+    /// with `ErrorKind::TemperatureDisabled` if the thermometer is off, and
+    /// with `ErrorKind::NotEnoughData`, rather than panicking,
+    /// if the device returns fewer than two bytes.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let raw = sensor.read_temperature()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_temperature(&mut self) -> Result<i16> {
+        if self.variant == Variant::Agr {
+            bail!(ErrorKind::UnsupportedByVariant);
+        }
+        if !self.temperature_enabled {
+            bail!(ErrorKind::TemperatureDisabled);
+        }
+        let data = match self.transfer_mode {
+            TransferMode::Smbus => {
+                let data = self.device
+                    .smbus_read_i2c_block_data(MagRegister::TempOutH.addr(), 2)
+                    .chain_smbus_err(|| ErrorKind::FailedToReadRegister)?;
+                if data.len() < 2 {
+                    bail!(ErrorKind::NotEnoughData(2, data.len()));
+                }
+                [data[0], data[1]]
+            }
+            TransferMode::Plain => {
+                let mut data = [0; 2];
+                self.read_block(MagRegister::TempOutH, &mut data)?;
+                data
+            }
+        };
+
+        Ok(decode_temperature(data[0], data[1]))
+    }
+
+
+    /// Read the thermometer, returning the temperature in degrees Celsius.
+    ///
+    /// The sensor has a resolution of 8 LSB/°C, but it is not calibrated;
+    /// a nominal reference of 25°C is assumed, unless `set_temp_calibration` says otherwise.
+    /// Expect the absolute value to be off by several degrees,
+    /// though changes in temperature are tracked reasonably well.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let temp = sensor.read_temperature_celsius()?;
+    /// println!("Temperature: {} °C", temp);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_temperature_celsius(&mut self) -> Result<f32> {
+        let raw = self.read_temperature()?;
+        Ok(self.temp_calibration.celsius(raw))
+    }
+
+
+    /// Set the conversion of the thermometer output to degrees Celsius.
+    ///
+    /// The thermometer is not calibrated at the factory;
+    /// comparing it against a reference thermometer at two temperatures
+    /// gives both the sensitivity and the offset.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::magnetometer::TempCalibration;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.set_temp_calibration(TempCalibration { lsb_per_deg: 8.2, ref_offset_c: 21.5 });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_temp_calibration(&mut self, calibration: TempCalibration) {
+        self.temp_calibration = calibration;
+    }
+
+
+    /// Get the conversion of the thermometer output to degrees Celsius.
+    pub fn temp_calibration(&self) -> TempCalibration {
+        self.temp_calibration
+    }
+
+
+    /// Read the thermometer, returning the moving average of the last `window` readings in °C.
+    ///
+    /// The recent readings are kept in the sensor,
+    /// so this smooths out the jitter of a degree or two between samples.
+    /// Until `window` readings have been taken, the average is over those so far.
+    /// Calling this with a different `window` discards the previous readings;
+    /// a `window` of zero is treated as one.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use std::time::Duration;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// loop {
+    ///     let temp = sensor.read_temperature_filtered(8)?;
+    ///     println!("Temperature: {:.1} °C", temp);
+    ///     std::thread::sleep(Duration::from_secs(1));
+    /// }
+    /// # }
+    /// ```
+    pub fn read_temperature_filtered(&mut self, window: usize) -> Result<f32> {
+        let window = window.max(1);
+        if window != self.temperature_window {
+            self.temperature_window = window;
+            self.temperature_samples.clear();
+            self.temperature_samples.reserve(window);
+        }
+
+        let temp = self.read_temperature_celsius()?;
+        if self.temperature_samples.len() == window {
+            self.temperature_samples.pop_front();
+        }
+        self.temperature_samples.push_back(temp);
+
+        let sum: f32 = self.temperature_samples.iter().sum();
+        Ok(sum / self.temperature_samples.len() as f32)
+    }
+
+
+    /// Read the magnetic field and temperature as close together as possible.
+    ///
+    /// The reads are issued back to back, and the time is recorded
+    /// just before them, which is useful for sensor fusion filters.
+    /// Fails with `ErrorKind::TemperatureDisabled` if the thermometer is off.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let snapshot = sensor.snapshot()?;
+    /// println!("{:?}: {} at {}", snapshot.timestamp,
+    ///     snapshot.magnetic_field, snapshot.temperature);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn snapshot(&mut self) -> Result<Snapshot> {
+        let timestamp = Instant::now();
+        let magnetic_field = self.read_raw_magnetic_field()?;
+        let temperature = self.read_temperature()?;
+
+        Ok(Snapshot {
+            magnetic_field,
+            temperature,
+            timestamp,
+        })
     }
-}
 
 
-impl<Dev> Magnetometer<Dev>
-where
-    Dev: I2CDevice,
-    Error: From<Dev::Error>,
-    Dev::Error: Send + 'static,
-{
-    /// Initialize the magnetometer, given an open I2C device.
+    /// Read a single register of the magnetometer.
     ///
-    /// The opening of the device is platform specific,
-    /// but initialization of the sensor is not.
-    /// Prefer to use `Accelerometer::new`, unless you are using an
-    /// implementation of `I2CDevice` that is not covered by this crate.
+    /// The register addresses are defined in the `registers` module.
     ///
     /// ```no_run
-    /// # extern crate lsm303;
     /// # use lsm303::Magnetometer;
-    /// # extern crate i2cdev;
-    /// # use i2cdev::linux::LinuxI2CDevice;
+    /// # use lsm303::registers;
     /// # fn main() { test().unwrap(); }
     /// # fn test() -> lsm303::Result<()> {
-    /// let device = LinuxI2CDevice::new("/dev/i2c-1", 0x3C >> 1)?;
-    /// let sensor = Magnetometer::from_i2c_device(device)?;
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let cra_reg_m = sensor.read_register(registers::CRA_REG_M)?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn from_i2c_device(mut device: Dev) -> Result<Magnetometer<Dev>> {
-        use registers::{CRA_REG_M, MR_REG_M, CraRegM, MrRegM};
-
-        // Set magnetometer to continuous mode
-        let mr_reg_m = MrRegM::empty();
-        write_register!(device, MR_REG_M, mr_reg_m)?;
-
-        // enable temperature; set output rate to 15 Hz
-        let cra_reg_m = CraRegM::TEMP_EN | CraRegM::DO2;
-        write_register!(device, CRA_REG_M, cra_reg_m)?;
-
-        let gain = Gain::Gain_1_3;
-
-        let mut magnetometer = Magnetometer { device, gain };
-        magnetometer.set_gain(Gain::Gain_1_3)?;
-
-        Ok(magnetometer)
+    pub fn read_register(&mut self, register: u8) -> Result<u8> {
+        let value = self.device.smbus_read_byte_data(register).chain_bus_err(|| {
+            ErrorKind::FailedToReadRegister
+        })?;
+        Ok(value)
     }
 
 
-    /// Read the magnetometer, returning the magnetic field as a vector.
+    /// Write a single register of the magnetometer.
+    ///
+    /// This is an escape hatch for features that are not otherwise supported.
+    /// The settings cached by this type are not updated,
+    /// so writing the configuration registers directly
+    /// can leave them out of sync with the device.
+    /// In particular, changing the gain this way
+    /// will make `read_magnetic_field` scale by the wrong amount;
+    /// use `set_gain` instead.
     ///
     /// ```no_run
     /// # use lsm303::Magnetometer;
+    /// # use lsm303::registers;
     /// # fn main() { test().unwrap(); }
     /// # fn test() -> lsm303::Result<()> {
     /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
-    /// let field = sensor.read_magnetic_field()?;
-    /// println!("Magnetic field: ({}, {}, {})",
-    ///     field.x, field.y, field.z);
+    /// sensor.write_register(registers::MR_REG_M, 0x00)?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn read_magnetic_field(&mut self) -> Result<MagneticField> {
-        use byteorder::{ByteOrder, BigEndian};
-
-        let data = self.device.smbus_read_i2c_block_data(
-            registers::OUT_X_H_M,
-            6,
-        )?;
-        if data.len() < 6 {
-            bail!(ErrorKind::NotEnoughData);
-        }
-
-        // Refer to Table 3 or Table 75 of the datasheet.
-        let (scale_xy, scale_z) = match self.gain {
-            Gain::Gain_1_3 => (1100., 980.),
-            Gain::Gain_1_9 => (855.0, 760.),
-            Gain::Gain_2_5 => (670., 600.),
-            Gain::Gain_4_0 => (450., 400.),
-            Gain::Gain_4_7 => (400., 355.),
-            Gain::Gain_5_6 => (330., 295.),
-            Gain::Gain_8_1 => (230., 205.),
-        };
-        let scale_xy: si::Tesla<f64> = (ucum::GS / scale_xy).into();
-        let scale_z: si::Tesla<f64> = (ucum::GS / scale_z).into();
+    pub fn write_register(&mut self, register: u8, value: u8) -> Result<()> {
+        self.device.smbus_write_byte_data(register, value).chain_bus_err(|| {
+            ErrorKind::FailedToWriteRegister
+        })?;
+        Ok(())
+    }
 
-        // Yes indeed, the registers are ordered as X, Z, Y
-        let x = BigEndian::read_i16(&data[0..2]) as f64 * scale_xy;
-        let z = BigEndian::read_i16(&data[2..4]) as f64 * scale_z;
-        let y = BigEndian::read_i16(&data[4..6]) as f64 * scale_xy;
 
-        let out = MagneticField { x, y, z };
-        Ok(out)
+    /// Use the underlying `I2CDevice` directly, and then resynchronize the cached settings.
+    ///
+    /// Unlike `DerefMut`, which gives unchecked access,
+    /// the gain, data rate, mode, and thermometer setting are read back from the device
+    /// once the closure returns, so that they cannot drift.
+    /// This fails if they cannot be read back, after the closure has run.
+    ///
+    /// ```no_run
+    /// # extern crate i2cdev;
+    /// # extern crate lsm303;
+    /// # use i2cdev::core::I2CDevice;
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::magnetometer::Gain;
+    /// # use lsm303::registers;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// sensor.with_raw_device(|device| device.smbus_write_byte_data(registers::CRB_REG_M, 0xE0))??;
+    /// assert_eq!(sensor.gain(), Gain::Gain_8_1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_raw_device<R, F>(&mut self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Dev) -> R,
+    {
+        let result = f(&mut *self.device);
+        self.sync_config()?;
+        Ok(result)
     }
 
 
-    /// Set the gain of the magnetometer.
+    /// Take back the underlying `I2CDevice`, without putting the sensor to sleep.
+    ///
+    /// The sensor keeps its configuration, and carries on measuring.
     ///
     /// ```no_run
-    /// # use lsm303::magnetometer::{Magnetometer, Gain};
+    /// # use lsm303::Magnetometer;
     /// # fn main() { test().unwrap(); }
     /// # fn test() -> lsm303::Result<()> {
-    /// let mut sensor = Magnetometer::new("/dev/i2c-1")?;
-    /// sensor.set_gain(Gain::Gain_4_0)?;
+    /// let sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let device = sensor.into_inner();
     /// # Ok(())
     /// # }
     /// ```
-    pub fn set_gain(&mut self, gain: Gain) -> Result<()>
+    pub fn into_inner(self) -> Dev {
+        self.device.into_inner()
+    }
+
+
+    /// Move the configured sensor onto an async I2C bus, at `address`.
+    ///
+    /// The async sensor carries over the variant, gain, axis remapping
+    /// and output registers, so it reads the field as this one does.
+    /// The sensor is not put to sleep, and the blocking device is closed.
+    /// Requires the `async` feature; see the `asynch` module.
+    #[cfg(feature = "async")]
+    pub fn into_async<I2C>(self, i2c: I2C, address: u8) -> AsyncMagnetometer<I2C>
     where
-        Dev::Error: Send + 'static,
+        I2C: ::embedded_hal_async::i2c::I2c,
     {
-        use registers::{CRB_REG_M, CrbRegM};
-        type R = CrbRegM;
-        let mut flags = read_register!(self.device, CRB_REG_M, CrbRegM)?;
+        let mut magnetometer =
+            AsyncMagnetometer::new(i2c, address, self.variant, self.gain, self.axis_remap);
+        magnetometer.set_output_layout(self.output_register, self.output_layout);
+        drop(self.into_inner());
+        magnetometer
+    }
 
-        flags.remove(R::GN2 | R::GN1 | R::GN0);
-        let setting = match gain {
-            Gain::Gain_1_3 => /* --  |  ---- */ R::GN0,
-            Gain::Gain_1_9 => /* -- */ R::GN1,
-            Gain::Gain_2_5 => /* -- */ R::GN1 | R::GN0,
-            Gain::Gain_4_0 => R::GN2,
-            Gain::Gain_4_7 => R::GN2 | /* -- */ R::GN0,
-            Gain::Gain_5_6 => R::GN2 | R::GN1,
-            Gain::Gain_8_1 => R::GN2 | R::GN1 | R::GN0,
-        };
-        flags.insert(setting);
 
-        write_register!(self.device, CRB_REG_M, flags)?;
-        self.gain = gain;
+    /// Update the cached settings from the device.
+    fn sync_config(&mut self) -> Result<()> {
+        use crate::registers::CfgRegAM;
+
+        if self.variant == Variant::Agr {
+            let flags = self.read_flags(MagRegister::CfgRegA, CfgRegAM::from_bits_truncate)?;
+            self.data_rate = DataRate::from(AgrDataRate::from_bits(flags));
+            self.mode = Mode::from_agr_bits(flags);
+            return Ok(());
+        }
 
+        let config = self.read_config()?;
+        self.gain = config.gain;
+        self.data_rate = config.data_rate;
+        self.mode = config.mode;
+        self.temperature_enabled = config.temperature_enabled;
         Ok(())
     }
+}
 
 
-    // It is unclear how to interpret the TEMP_OUT registers.
-    // The datasheet does not have quite enough information.
-    // Discussions can be found in various places, such as
-    // https://forum.pololu.com/t/16-bit-values-in-lsm303/8499/8
-    // Until this is figured out, this function is being left out.
-    #[cfg(none)]
-    /// Read the thermometer.
-    pub fn read_temperature(&mut self) -> Result<i16> {
+#[cfg(feature = "std")]
+impl<Dev> Magnetometer<Dev>
+where
+    Dev: I2CWriteRead + Send + 'static,
+    Error: From<Dev::Error>,
+    Dev::Error: Send + 'static,
+{
+    /// Move the sensor to a background thread, which takes a `snapshot`
+    /// at the given data rate and sends it over a channel.
+    ///
+    /// The data rate of the sensor is set first,
+    /// and the thread keeps to its `sample_interval`.
+    /// The thread stops once the receiver is dropped, putting the sensor to sleep,
+    /// and then the handle yields `Ok(())`.
+    /// If a read fails, the thread stops, and the handle yields the error.
+    ///
+    /// This streams the magnetometer only, and needs the thermometer,
+    /// which the LSM303AGR does not have; `Lsm303::spawn_sampler` streams both sensors.
+    ///
+    /// ```no_run
+    /// # use lsm303::Magnetometer;
+    /// # use lsm303::magnetometer::DataRate;
+    /// # fn main() { test().unwrap(); }
+    /// # fn test() -> lsm303::Result<()> {
+    /// let sensor = Magnetometer::new("/dev/i2c-1")?;
+    /// let (handle, snapshots) = sensor.spawn_sampler(DataRate::Rate15Hz);
+    /// for snapshot in snapshots.iter().take(15) {
+    ///     println!("{}", snapshot.magnetic_field);
+    /// }
+    /// drop(snapshots);
+    /// handle.join().unwrap()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn spawn_sampler(mut self, rate: DataRate) -> (JoinHandle<Result<()>>, Receiver<Snapshot>) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            self.set_data_rate(rate)?;
+            let period = self.sample_interval();
+            sample_periodically(period, &sender, || self.snapshot())
+        });
+        (handle, receiver)
+    }
+}
 
-        let data = self.device.smbus_read_i2c_block_data(
-            registers::TEMP_OUT_H_M,
-            2,
-        )?;
-        if data.len() < 2 {
-            bail!(ErrorKind::NotEnoughData);
-        }
 
-        let temp = (data[0] as i16) << 4 | data[1] as i16 >> 4;
-        Ok(temp)
+/// Send a sample every `period` until the receiver is dropped, or a sample fails.
+#[cfg(feature = "std")]
+pub(crate) fn sample_periodically<T, F>(
+    period: Duration,
+    sender: &Sender<T>,
+    mut sample: F,
+) -> Result<()>
+where
+    F: FnMut() -> Result<T>,
+{
+    let mut next = Instant::now();
+    loop {
+        if sender.send(sample()?).is_err() {
+            return Ok(());
+        }
+        // Keep to the schedule, rather than drifting by the time of each read.
+        next += period;
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else {
+            next = now;
+        }
     }
 }
 
@@ -223,6 +3766,7 @@ where
 /// Access the underlying `I2CDevice`.
 ///
 /// Most of the methods require a mutable reference; `DerefMut` is implemented as well.
+#[cfg(feature = "std")]
 impl<Dev> Deref for Magnetometer<Dev>
 where
     Dev: I2CDevice,
@@ -235,9 +3779,130 @@ where
 }
 
 
+/// The device of a `Magnetometer`, which puts the sensor to sleep when dropped.
+///
+/// The device is only taken out by `into_inner`, which consumes the guard.
+#[cfg(feature = "std")]
+struct SleepOnDrop<Dev>
+where
+    Dev: I2CDevice,
+{
+    device: Option<Dev>,
+    variant: Variant,
+}
+
+
+#[cfg(feature = "std")]
+impl<Dev> SleepOnDrop<Dev>
+where
+    Dev: I2CDevice,
+{
+    fn new(device: Dev, variant: Variant) -> SleepOnDrop<Dev> {
+        SleepOnDrop { device: Some(device), variant }
+    }
+
+    /// Take the device, leaving the sensor as it is.
+    fn into_inner(mut self) -> Dev {
+        self.device.take().expect("the device is only taken once")
+    }
+}
+
+
+#[cfg(feature = "std")]
+impl<Dev> Deref for SleepOnDrop<Dev>
+where
+    Dev: I2CDevice,
+{
+    type Target = Dev;
+
+    fn deref(&self) -> &Dev {
+        self.device.as_ref().expect("the device is only taken once")
+    }
+}
+
+
+#[cfg(feature = "std")]
+impl<Dev> DerefMut for SleepOnDrop<Dev>
+where
+    Dev: I2CDevice,
+{
+    fn deref_mut(&mut self) -> &mut Dev {
+        self.device.as_mut().expect("the device is only taken once")
+    }
+}
+
+
+/// Put the sensor to sleep, unless the device has been taken.
+///
+/// This is best effort: errors are ignored, since they cannot be reported.
+#[cfg(feature = "std")]
+impl<Dev> Drop for SleepOnDrop<Dev>
+where
+    Dev: I2CDevice,
+{
+    fn drop(&mut self) {
+        use crate::registers::CfgRegAM;
+        use crate::registers::MagRegister::{CfgRegA, MrReg};
+        type R = CfgRegAM;
+
+        let device = match self.device.as_mut() {
+            Some(device) => device,
+            None => return,
+        };
+        let _ = match self.variant {
+            Variant::Dlhc => device.smbus_write_byte_data(MrReg.addr(), Mode::Sleep.bits().bits()),
+            Variant::Agr => device.smbus_read_byte_data(CfgRegA.addr()).and_then(|bits| {
+                let mut flags = CfgRegAM::from_bits_truncate(bits);
+                flags.remove(R::MD1 | R::MD0);
+                flags.insert(Mode::Sleep.agr_bits());
+                device.smbus_write_byte_data(CfgRegA.addr(), flags.bits())
+            }),
+        };
+    }
+}
+
+
+/// Show the device and the cached configuration.
+///
+/// This does not touch the bus, so it is safe to use from error paths;
+/// the settings shown are those last written or read back.
+#[cfg(feature = "std")]
+impl<Dev> fmt::Debug for Magnetometer<Dev>
+where
+    Dev: I2CDevice + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Magnetometer")
+            .field("device", &*self.device)
+            .field("variant", &self.variant)
+            .field("gain", &self.gain)
+            .field("data_rate", &self.data_rate)
+            .field("mode", &self.mode)
+            .field("temperature_enabled", &self.temperature_enabled)
+            .field("hard_iron_offset", &self.hard_iron_offset)
+            .field("soft_iron_matrix", &self.soft_iron_matrix)
+            .field("axis_remap", &self.axis_remap)
+            .field("read_retries", &self.read_retries)
+            .field("verify_writes", &self.verify_writes)
+            .field("temp_coefficient", &self.temp_coefficient)
+            .field("temp_calibration", &self.temp_calibration)
+            .field("transfer_mode", &self.transfer_mode)
+            .field("output_register", &self.output_register)
+            .field("output_layout", &self.output_layout)
+            .field("last_reading_flags", &self.last_reading_flags)
+            .field("responsive_samples", &self.responsive_samples)
+            .field("responsive_interval", &self.responsive_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+
 /// Access the underlying `I2CDevice`.
 ///
 /// Refer to the LSM303 datasheet if you plan on accessing the device directly.
+/// Writing the configuration registers this way leaves the cached settings,
+/// such as `gain`, out of date; prefer `with_raw_device`, which resynchronizes them.
+#[cfg(feature = "std")]
 impl<Dev> DerefMut for Magnetometer<Dev>
 where
     Dev: I2CDevice,
@@ -246,3 +3911,470 @@ where
         &mut self.device
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AgrDataRate, DataRate, Gain, Magnetometer, MagnetometerBuilder, MagnetometerConfig, Mode,
+        OutputLayout, ReadingFlags, TempCalibration, TransferMode, Variant, batch_writes,
+        to_tesla,
+    };
+    use crate::common::Vector3;
+    use crate::registers::{
+        CFG_REG_A_M, CRA_REG_M, CRB_REG_M, MR_REG_M, MagRegister, OUTX_L_REG_M, OUT_X_H_M,
+        OUT_X_L_M, OUT_Y_H_M, OUT_Y_L_M, OUT_Z_H_M, OUT_Z_L_M, SR_REG_M, TEMP_OUT_H_M, TEMP_OUT_L_M,
+    };
+    use crate::test_util::MockI2CDevice;
+    use crate::ErrorKind;
+    use i2cdev::core::I2CDevice;
+    use std::collections::{HashMap, HashSet};
+    use std::time::Duration;
+
+    // A device that does not identify as a magnetometer is rejected, unless the check is skipped.
+    #[test]
+    fn rejects_wrong_device_unless_unchecked() {
+        match *Magnetometer::from_i2c_device(MockI2CDevice::new()).err().unwrap().kind() {
+            ErrorKind::WrongDevice => {}
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+
+        let sensor = Magnetometer::from_i2c_device_unchecked(MockI2CDevice::new()).unwrap();
+        assert!(!sensor.writes().is_empty());
+    }
+
+    // A negative temperature is sign extended, rather than read as a large positive value.
+    #[test]
+    fn negative_temperature_is_sign_extended() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(TEMP_OUT_H_M, 0xF8);
+        device.set_register(TEMP_OUT_L_M, 0x00);
+
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        assert_eq!(sensor.read_temperature().unwrap(), -128);
+        assert_eq!(sensor.read_temperature_celsius().unwrap(), 9.0);
+    }
+
+    // The filtered temperature averages over the window, and starts over when the window changes.
+    #[test]
+    fn filtered_temperature_restarts_with_new_window() {
+        let mut sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        assert_eq!(sensor.read_temperature_filtered(2).unwrap(), 25.0);
+
+        // 0x01 in the high register is 16 LSB, or 2 °C above the reference.
+        sensor.set_register(TEMP_OUT_H_M, 0x01);
+        assert_eq!(sensor.read_temperature_filtered(2).unwrap(), 26.0);
+        assert_eq!(sensor.read_temperature_filtered(2).unwrap(), 27.0);
+        assert_eq!(sensor.read_temperature_filtered(3).unwrap(), 27.0);
+    }
+
+    // The configuration is decoded from the registers, and written back unchanged.
+    #[test]
+    fn config_round_trips() {
+        let mut sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        let config = sensor.read_config().unwrap();
+        assert_eq!(config, MagnetometerConfig {
+            gain: Gain::Gain_1_3,
+            data_rate: DataRate::Rate15Hz,
+            mode: Mode::Continuous,
+            temperature_enabled: true,
+        });
+
+        let config = MagnetometerConfig { gain: Gain::Gain_5_6, mode: Mode::Sleep, ..config };
+        sensor.apply_config(config).unwrap();
+        assert_eq!(sensor.read_config().unwrap(), config);
+        assert_eq!(sensor.gain(), Gain::Gain_5_6);
+    }
+
+    // Settings changed through `with_raw_device` are read back.
+    #[test]
+    fn settings_changed_through_raw_device_are_read_back() {
+        let mut sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        sensor.with_raw_device(|device| {
+            device.smbus_write_byte_data(CRA_REG_M, 0x1C).unwrap();
+            device.smbus_write_byte_data(CRB_REG_M, 0xE0).unwrap();
+        }).unwrap();
+        assert_eq!(sensor.gain(), Gain::Gain_8_1);
+        assert_eq!(sensor.data_rate(), DataRate::Rate220Hz);
+        assert!(!sensor.temperature_enabled());
+    }
+
+    // A short block read fails with `NotEnoughData`, rather than panicking.
+    #[test]
+    fn short_block_read_is_not_enough_data() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.truncate_block_reads(1);
+
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        match *sensor.read_temperature().unwrap_err().kind() {
+            ErrorKind::NotEnoughData(2, 1) => {}
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+    }
+
+    // A busy bus is retried, up to `read_retries` times, but a NACK is returned at once.
+    #[test]
+    fn read_retries_only_transient_errors() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(OUT_X_L_M, 0x2C);
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+
+        sensor.fail_next(libc::EAGAIN);
+        sensor.fail_next(libc::EBUSY);
+        assert_eq!(sensor.read_raw_magnetic_field().unwrap().x, 0x2C);
+
+        sensor.fail_next(libc::ENXIO);
+        sensor.fail_next(libc::EAGAIN);
+        match *sensor.read_raw_magnetic_field().unwrap_err().kind() {
+            ErrorKind::NoDeviceAtAddress => {}
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+        // The NACK was not retried, so the next failure is still queued.
+        sensor.set_read_retries(0);
+        assert!(sensor.read_raw_magnetic_field().is_err());
+        assert_eq!(sensor.read_raw_magnetic_field().unwrap().x, 0x2C);
+    }
+
+    // A saturated axis fails with `FieldOverflow`.
+    #[test]
+    fn saturated_axis_is_field_overflow() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(OUT_Y_H_M, 0xF0);
+        device.set_register(OUT_Y_L_M, 0x00);
+
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        assert_eq!(sensor.read_raw_magnetic_field().unwrap().y, -4096);
+        match *sensor.read_magnetic_field_gauss().unwrap_err().kind() {
+            ErrorKind::FieldOverflow => {}
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+    }
+
+    // The output is read without the SMBus block read, which allocates.
+    #[test]
+    fn output_read_avoids_smbus_block_read() {
+        let mut sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        sensor.read_raw_magnetic_field().unwrap();
+        assert_eq!(sensor.block_reads(), 0);
+    }
+
+    // An adapter without SMBus block reads is reported as such, and the plain transfer mode works
+    // around it.
+    #[test]
+    fn plain_transfer_mode_works_without_smbus() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.reject_smbus_block_reads();
+        device.set_register(TEMP_OUT_H_M, 0x01);
+
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        match *sensor.read_temperature().err().unwrap().kind() {
+            ErrorKind::SmbusUnsupported => {}
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+
+        sensor.set_transfer_mode(TransferMode::Plain);
+        assert_eq!(sensor.read_temperature().unwrap(), 16);
+    }
+
+    // Taking the device back does not put the sensor to sleep.
+    #[test]
+    fn into_inner_leaves_sensor_measuring() {
+        let sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        let device = sensor.into_inner();
+        assert_eq!(device.register(MR_REG_M), 0x00);
+    }
+
+    // A bulk capture fills the whole buffer when every read succeeds.
+    #[test]
+    fn read_n_fills_buffer() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(SR_REG_M, 0x01);
+        device.set_register(OUT_X_L_M, 0x05);
+
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        let mut buffer = [Vector3 { x: 0, y: 0, z: 0 }; 4];
+        assert_eq!(sensor.read_n(&mut buffer).unwrap(), 4);
+        assert!(buffer.iter().all(|reading| reading.x == 5));
+    }
+
+    // The integer milligauss read rounds toward zero.
+    #[test]
+    fn milligauss_rounds_toward_zero() {
+        let mut device = MockI2CDevice::magnetometer();
+        // 1100 LSB is 1 G on X at the default gain, and -1000 LSB is -1.0204 G on Z.
+        device.set_register(OUT_X_H_M, 0x04);
+        device.set_register(OUT_X_L_M, 0x4C);
+        device.set_register(OUT_Z_H_M, 0xFC);
+        device.set_register(OUT_Z_L_M, 0x18);
+
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        let field = sensor.read_magnetic_field_milligauss().unwrap();
+        assert_eq!((field.x, field.z), (1000, -1020));
+    }
+
+    // The debug output shows the cached configuration.
+    #[test]
+    fn debug_shows_cached_configuration() {
+        let sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        let debug = format!("{:?}", sensor);
+        assert!(debug.contains("gain: Gain_1_3"));
+        assert!(debug.contains("mode: Continuous"));
+    }
+
+    // The hard iron calibration reports each sample, and stops when asked to.
+    #[test]
+    fn hard_iron_calibration_stops_when_asked() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(SR_REG_M, 0x01);
+        device.set_register(OUT_X_H_M, 0x00);
+        device.set_register(OUT_X_L_M, 0x64);
+
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        let mut seen = Vec::new();
+        let offset = sensor.calibrate_hard_iron(10, |taken, raw| {
+            seen.push((taken, raw.x));
+            taken < 3
+        }).unwrap();
+        assert_eq!(seen, [(1, 100), (2, 100), (3, 100)]);
+        assert_eq!(offset.x, 100);
+    }
+
+    // A sensor that keeps repeating the same measurement is reported as stuck.
+    #[test]
+    fn repeated_measurement_is_not_responsive() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(SR_REG_M, 0x01);
+
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        assert!(!sensor.is_responsive().unwrap());
+    }
+
+    // A target range selects the finest gain that covers it.
+    #[test]
+    fn target_range_selects_finest_gain() {
+        let sensor = MagnetometerBuilder::new()
+            .target_range_gauss(3.0)
+            .unwrap()
+            .build(MockI2CDevice::magnetometer())
+            .unwrap();
+        assert_eq!(sensor.gain(), Gain::Gain_4_0);
+    }
+
+    // A sampler thread stops cleanly once its receiver is dropped.
+    #[test]
+    fn sampler_stops_when_receiver_dropped() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(OUT_X_H_M, 0x01);
+        device.set_register(OUT_X_L_M, 0x2C);
+        let sensor = Magnetometer::from_i2c_device(device).unwrap();
+        let (handle, snapshots) = sensor.spawn_sampler(DataRate::Rate220Hz);
+        let received: Vec<_> = snapshots.iter().take(3).collect();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0].magnetic_field.x, 300);
+        assert!(received[1].timestamp > received[0].timestamp);
+        drop(snapshots);
+        assert!(handle.join().unwrap().is_ok());
+    }
+
+    // The sample interval follows the data rate, and the variant.
+    #[test]
+    fn sample_interval_follows_data_rate() {
+        let mut sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        sensor.set_data_rate(DataRate::Rate75Hz).unwrap();
+        assert_eq!(sensor.sample_interval_us(), 13_333);
+        assert_eq!(sensor.sample_interval(), Duration::from_micros(13_333));
+    }
+
+    // With the `log` feature, the register transactions are logged.
+    #[cfg(feature = "log")]
+    #[test]
+    fn register_transactions_are_logged() {
+        use log::{Level, LevelFilter, Log, Metadata, Record};
+        use super::Gain;
+        use crate::test_util::MockI2CDevice;
+        use crate::Magnetometer;
+        use std::sync::Mutex;
+
+        struct Recorder(Mutex<Vec<(Level, String)>>);
+
+        impl Log for Recorder {
+            fn enabled(&self, _: &Metadata) -> bool { true }
+            fn log(&self, record: &Record) {
+                self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+            }
+            fn flush(&self) {}
+        }
+
+        static RECORDER: Recorder = Recorder(Mutex::new(Vec::new()));
+        log::set_logger(&RECORDER).unwrap();
+        log::set_max_level(LevelFilter::Debug);
+
+        let mut sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        sensor.set_gain(Gain::Gain_4_0).unwrap();
+
+        let records = RECORDER.0.lock().unwrap();
+        assert!(records.contains(&(Level::Debug, "write 0x80 to register 0x01".to_string())));
+        assert!(records.contains(&(Level::Info, "magnetometer gain set to Gain_4_0".to_string())));
+    }
+
+    // The defaults of the settings are those of a newly initialized sensor.
+    #[test]
+    fn defaults_match_initialized_sensor() {
+        let mut sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        assert_eq!(sensor.read_config().unwrap(), MagnetometerConfig::default());
+        assert_eq!(sensor.gain(), Gain::default());
+    }
+
+    // The settings can be used as keys, and equal settings collapse.
+    #[test]
+    fn settings_are_hashable() {
+        let gains: HashSet<Gain> =
+            vec![Gain::Gain_1_3, Gain::Gain_4_0, Gain::Gain_1_3].into_iter().collect();
+        assert_eq!(gains.len(), 2);
+        assert!(gains.contains(&Gain::Gain_4_0));
+
+        let mut rates = HashMap::new();
+        rates.insert(DataRate::Rate15Hz, "default");
+        rates.insert(DataRate::Rate220Hz, "fastest");
+        rates.insert(DataRate::Rate15Hz, "nominal");
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[&DataRate::Rate15Hz], "nominal");
+
+        let modes: HashSet<Mode> =
+            vec![Mode::Continuous, Mode::Sleep, Mode::Continuous].into_iter().collect();
+        assert_eq!(modes.len(), 2);
+        assert!(!modes.contains(&Mode::SingleConversion));
+    }
+
+    // The output is read from the registers of the variant, in its own layout, or from any other
+    // registers for a clone.
+    #[test]
+    fn output_layout_follows_variant() {
+        let mut device = MockI2CDevice::magnetometer();
+        for (i, value) in [0x01, 0x02, 0x03, 0x04, 0x05, 0x06].iter().enumerate() {
+            device.set_register(OUT_X_H_M + i as u8, *value);
+            device.set_register(OUTX_L_REG_M + i as u8, *value);
+        }
+
+        let mut sensor = Magnetometer::from_i2c_device(device.clone()).unwrap();
+        let raw = sensor.read_raw_magnetic_field().unwrap();
+        assert_eq!((raw.x, raw.y, raw.z), (0x0102, 0x0506, 0x0304));
+
+        let mut sensor = MagnetometerBuilder::new()
+            .variant(Variant::Agr)
+            .build_unchecked(device.clone())
+            .unwrap();
+        assert_eq!(sensor.output_layout(), (MagRegister::OutxLReg, OutputLayout::XyzLittleEndian));
+        let raw = sensor.read_raw_magnetic_field().unwrap();
+        assert_eq!((raw.x, raw.y, raw.z), (0x0201, 0x0403, 0x0605));
+
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        sensor.set_output_layout(MagRegister::OutxLReg, OutputLayout::XyzLittleEndian);
+        let raw = sensor.read_raw_magnetic_field().unwrap();
+        assert_eq!((raw.x, raw.y, raw.z), (0x0201, 0x0403, 0x0605));
+    }
+
+    // The reading flags report only the corrections that changed the last reading, and every read
+    // clears them.
+    #[test]
+    fn reading_flags_report_applied_corrections() {
+        let mut sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        sensor.read_magnetic_field_calibrated().unwrap();
+        assert_eq!(sensor.last_reading_flags(), ReadingFlags::empty());
+
+        sensor.set_hard_iron_offset(Vector3 { x: 12, y: -40, z: 3 });
+        sensor.read_magnetic_field_calibrated().unwrap();
+        assert_eq!(sensor.last_reading_flags(), ReadingFlags::HARD_IRON);
+
+        sensor.read_magnetic_field_gauss().unwrap();
+        assert!(sensor.last_reading_flags().is_empty());
+
+        sensor.set_temp_coefficient(-0.0003);
+        sensor.read_magnetic_field_temp_compensated().unwrap();
+        assert_eq!(sensor.last_reading_flags(), ReadingFlags::TEMPERATURE);
+    }
+
+    // Temperature compensation is relative to the reference of the thermometer calibration.
+    #[test]
+    fn temp_compensation_uses_calibrated_reference() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(OUT_X_H_M, 0x01);
+        device.set_register(OUT_X_L_M, 0x2C);
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        assert_eq!(sensor.temp_coefficient(), 0.0);
+        let field = sensor.read_magnetic_field_gauss().unwrap();
+
+        // The thermometer reads zero, so the temperature is the reference.
+        sensor.set_temp_calibration(TempCalibration { lsb_per_deg: 8.0, ref_offset_c: 35.0 });
+        sensor.set_temp_coefficient(0.01);
+        assert_eq!(sensor.read_magnetic_field_temp_compensated().unwrap(), field);
+    }
+
+    // The LSM303AGR caches the rate it actually uses, and paces its polling by it.
+    #[test]
+    fn agr_data_rate_is_the_rate_in_use() {
+        let mut sensor = MagnetometerBuilder::new()
+            .variant(Variant::Agr)
+            .data_rate(DataRate::Rate3Hz)
+            .build_unchecked(MockI2CDevice::new())
+            .unwrap();
+        assert_eq!(sensor.data_rate(), DataRate::Rate15Hz);
+        assert_eq!(sensor.agr_data_rate(), Some(AgrDataRate::Rate10Hz));
+        assert_eq!(sensor.sample_interval(), Duration::from_millis(100));
+
+        sensor.set_data_rate(DataRate::Rate75Hz).unwrap();
+        assert_eq!(sensor.register(CFG_REG_A_M) & 0x0C, 0x08);
+        assert_eq!(sensor.data_rate(), DataRate::Rate75Hz);
+        assert_eq!(sensor.agr_data_rate(), Some(AgrDataRate::Rate50Hz));
+        assert_eq!(sensor.sample_interval(), Duration::from_millis(20));
+
+        sensor.set_data_rate(DataRate::Rate1_5Hz).unwrap();
+        assert_eq!(sensor.data_rate(), DataRate::Rate15Hz);
+
+        match *sensor.read_temperature().unwrap_err().kind() {
+            ErrorKind::UnsupportedByVariant => {}
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+
+        let sensor = Magnetometer::from_i2c_device(MockI2CDevice::magnetometer()).unwrap();
+        assert_eq!(sensor.agr_data_rate(), None);
+    }
+
+    // A measurement that lands during a synced read is caught by the data ready flag,
+    // which the read should have cleared, and the read is repeated.
+    #[test]
+    fn synced_read_retries_when_a_measurement_lands_mid_read() {
+        let mut device = MockI2CDevice::magnetometer();
+        // Ready, a new measurement during the read, ready again, and then quiet.
+        device.queue_reads(SR_REG_M, &[0x01, 0x01, 0x01, 0x00]);
+        device.queue_reads(OUT_X_L_M, &[0x10, 0x20]);
+
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        let expected = to_tesla(Vector3 { x: 0x20, y: 0, z: 0 }, Gain::Gain_1_3.lsb_per_gauss());
+        assert_eq!(sensor.read_magnetic_field_synced().unwrap(), expected);
+
+        // A measurement lands during every attempt.
+        sensor.set_register(SR_REG_M, 0x01);
+        match *sensor.read_magnetic_field_synced().unwrap_err().kind() {
+            ErrorKind::DataOverrun => {}
+            ref kind => panic!("unexpected error: {}", kind),
+        }
+    }
+
+    // The builder writes CRA_REG_M, CRB_REG_M and MR_REG_M together on the LSM303DLHC, but the
+    // AGR's configuration registers are written one at a time, CFG_REG_C_M first.
+    #[test]
+    fn builder_batches_adjacent_register_writes() {
+        let builder = MagnetometerBuilder::new().gain(Gain::Gain_4_0);
+        let batches: Vec<_> = batch_writes(builder.register_writes()).collect();
+        assert_eq!(batches.len(), 1);
+        let (base, values, len) = batches[0];
+        assert_eq!((base, len), (MagRegister::CraReg, 3));
+        assert_eq!(values[1], Gain::Gain_4_0.bits().bits());
+
+        let builder = MagnetometerBuilder::new().variant(Variant::Agr);
+        let bases: Vec<_> =
+            batch_writes(builder.register_writes()).map(|(base, _, len)| (base, len)).collect();
+        assert_eq!(bases, [(MagRegister::CfgRegC, 1), (MagRegister::CfgRegA, 1)]);
+    }
+}
@@ -0,0 +1,314 @@
+//! The register map behind the simulated devices.
+//!
+//! `test_util::MockI2CDevice` and `sim::SimulatedI2CDevice` both answer
+//! transfers from a map of registers, and differ only in where the values come from.
+//! The map is kept by `RegisterMap`, and `register_device!` implements the
+//! I2C traits on top of it, so both devices address, auto-increment and fail alike.
+
+use crate::registers::AUTO_INCREMENT;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+
+
+/// The registers of a simulated device, and its register pointer.
+///
+/// The address wraps at 0x7F, since its high bit requests auto-increment.
+#[derive(Clone, Debug)]
+pub(crate) struct RegisterMap {
+    registers: [u8; 0x80],
+    pointer: u8,
+    increment: bool,
+    writes: Vec<(u8, u8)>,
+    block_reads: usize,
+    failures: VecDeque<i32>,
+    queued_reads: HashMap<u8, VecDeque<u8>>,
+    /// Return at most this many bytes per block read.
+    pub block_read_limit: Option<usize>,
+    /// Only auto-increment if the high bit of the address is set.
+    pub strict_auto_increment: bool,
+    /// Fail SMBus block reads with EOPNOTSUPP.
+    pub smbus_block_unsupported: bool,
+}
+
+
+impl Default for RegisterMap {
+    fn default() -> RegisterMap {
+        RegisterMap {
+            registers: [0; 0x80],
+            pointer: 0,
+            increment: true,
+            writes: Vec::new(),
+            block_reads: 0,
+            failures: VecDeque::new(),
+            queued_reads: HashMap::new(),
+            block_read_limit: None,
+            strict_auto_increment: false,
+            smbus_block_unsupported: false,
+        }
+    }
+}
+
+
+impl RegisterMap {
+    /// Set a register, without recording a write.
+    pub fn set(&mut self, register: u8, value: u8) {
+        self.registers[usize::from(register & 0x7F)] = value;
+    }
+
+    /// Get the current value of a register.
+    pub fn get(&self, register: u8) -> u8 {
+        self.registers[usize::from(register & 0x7F)]
+    }
+
+    /// Queue values for the next reads of a register.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn queue_reads(&mut self, register: u8, values: &[u8]) {
+        self.queued_reads.entry(register & 0x7F).or_default().extend(values);
+    }
+
+    /// Every register write so far, as `(register, value)`, in order.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn writes(&self) -> &[(u8, u8)] {
+        &self.writes
+    }
+
+    /// Forget the writes recorded so far.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn clear_writes(&mut self) {
+        self.writes.clear();
+    }
+
+    /// The number of SMBus block reads so far.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn block_reads(&self) -> usize {
+        self.block_reads
+    }
+
+    /// Queue a failure with `errno` for the next transfer.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn fail_next(&mut self, errno: i32) {
+        self.failures.push_back(errno);
+    }
+
+    /// Fail this transfer, if a failure is queued.
+    pub fn check_failure(&mut self) -> io::Result<()> {
+        match self.failures.pop_front() {
+            Some(errno) => Err(io::Error::from_raw_os_error(errno)),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the register address for the next transfer.
+    #[cfg(any(test, feature = "sim"))]
+    pub fn pointer(&self) -> u8 {
+        self.pointer
+    }
+
+    /// Set the register address for the next transfer.
+    pub fn seek(&mut self, register: u8) {
+        self.pointer = register & 0x7F;
+        self.increment = !self.strict_auto_increment || register & AUTO_INCREMENT != 0;
+    }
+
+    /// Move to the next register, if auto-increment is enabled.
+    fn advance(&mut self) {
+        if self.increment {
+            self.pointer = self.pointer.wrapping_add(1) & 0x7F;
+        }
+    }
+
+    /// Read the current register, and move to the next.
+    pub fn next(&mut self) -> u8 {
+        let queued = self.queued_reads.get_mut(&self.pointer).and_then(VecDeque::pop_front);
+        let value = queued.unwrap_or_else(|| self.get(self.pointer));
+        self.advance();
+        value
+    }
+
+    /// Write the current register, and move to the next.
+    pub fn put(&mut self, value: u8) {
+        let register = self.pointer;
+        self.registers[usize::from(register)] = value;
+        self.writes.push((register, value));
+        self.advance();
+    }
+}
+
+
+/// A simulated device, answering transfers from a `RegisterMap`.
+pub(crate) trait RegisterDevice {
+    /// The registers of the device.
+    fn map(&mut self) -> &mut RegisterMap;
+
+    /// Set the register address for the next transfer.
+    ///
+    /// Devices that update their registers when they are addressed do it here.
+    fn seek(&mut self, register: u8) {
+        self.map().seek(register);
+    }
+
+    /// Address a register, and write the values after it.
+    fn write_registers(&mut self, data: &[u8]) {
+        if let Some((&register, values)) = data.split_first() {
+            self.seek(register);
+            for &value in values {
+                self.map().put(value);
+            }
+        }
+    }
+
+    /// Read from the current register on.
+    fn read_registers(&mut self, buffer: &mut [u8]) {
+        for byte in buffer.iter_mut() {
+            *byte = self.map().next();
+        }
+    }
+
+    /// Read consecutive registers, as a block read.
+    fn read_block(&mut self, register: u8, len: usize) -> Vec<u8> {
+        let map = self.map();
+        map.block_reads += 1;
+        let len = map.block_read_limit.map_or(len, |limit| len.min(limit));
+        self.seek(register);
+        (0..len).map(|_| self.map().next()).collect()
+    }
+}
+
+
+/// Implement `I2CDevice` and `I2CWriteRead` for a `RegisterDevice`.
+macro_rules! register_device {
+    ($device:ty) => {
+        impl ::i2cdev::core::I2CDevice for $device {
+            type Error = ::std::io::Error;
+
+            fn read(&mut self, data: &mut [u8]) -> ::std::io::Result<()> {
+                self.map().check_failure()?;
+                self.read_registers(data);
+                Ok(())
+            }
+
+            fn write(&mut self, data: &[u8]) -> ::std::io::Result<()> {
+                self.map().check_failure()?;
+                self.write_registers(data);
+                Ok(())
+            }
+
+            fn smbus_write_quick(&mut self, _bit: bool) -> ::std::io::Result<()> {
+                self.map().check_failure()
+            }
+
+            fn smbus_read_byte_data(&mut self, register: u8) -> ::std::io::Result<u8> {
+                self.map().check_failure()?;
+                self.seek(register);
+                Ok(self.map().next())
+            }
+
+            fn smbus_write_byte_data(&mut self, register: u8, value: u8) -> ::std::io::Result<()> {
+                self.write(&[register, value])
+            }
+
+            fn smbus_read_block_data(&mut self, register: u8) -> ::std::io::Result<Vec<u8>> {
+                // The first byte is the length of the block.
+                let len = usize::from(self.smbus_read_byte_data(register)?);
+                Ok(self.read_block(register.wrapping_add(1), len))
+            }
+
+            fn smbus_read_i2c_block_data(
+                &mut self,
+                register: u8,
+                len: u8,
+            ) -> ::std::io::Result<Vec<u8>> {
+                self.map().check_failure()?;
+                if self.map().smbus_block_unsupported {
+                    return Err(::std::io::Error::from_raw_os_error(::libc::EOPNOTSUPP));
+                }
+                Ok(self.read_block(register, usize::from(len)))
+            }
+
+            fn smbus_write_block_data(
+                &mut self,
+                register: u8,
+                values: &[u8],
+            ) -> ::std::io::Result<()> {
+                self.map().check_failure()?;
+                self.seek(register);
+                self.map().put(values.len() as u8);
+                for &value in values {
+                    self.map().put(value);
+                }
+                Ok(())
+            }
+
+            fn smbus_process_block(
+                &mut self,
+                register: u8,
+                values: &[u8],
+            ) -> ::std::io::Result<()> {
+                self.smbus_write_block_data(register, values)
+            }
+        }
+
+        /// The register address and the read are one transfer, so one queued failure fails both.
+        impl $crate::bus::I2CWriteRead for $device {
+            fn write_read(&mut self, data: &[u8], buffer: &mut [u8]) -> ::std::io::Result<()> {
+                self.map().check_failure()?;
+                self.write_registers(data);
+                self.read_registers(buffer);
+                Ok(())
+            }
+        }
+    };
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{RegisterDevice, RegisterMap};
+    use crate::bus::I2CWriteRead;
+    use i2cdev::core::I2CDevice;
+
+    /// A device that counts how often its output register is addressed.
+    #[derive(Default)]
+    struct Counter {
+        map: RegisterMap,
+    }
+
+    impl RegisterDevice for Counter {
+        fn map(&mut self) -> &mut RegisterMap {
+            &mut self.map
+        }
+
+        fn seek(&mut self, register: u8) {
+            self.map.seek(register);
+            if self.map.pointer() == 0x10 {
+                let count = self.map.get(0x10);
+                self.map.set(0x10, count + 1);
+            }
+        }
+    }
+
+    register_device!(Counter);
+
+    // Every transfer that addresses a register goes through the device's seek.
+    #[test]
+    fn transfers_seek_through_the_device() {
+        let mut device = Counter::default();
+        let mut buffer = [0; 1];
+        device.write_read(&[0x10], &mut buffer).unwrap();
+        assert_eq!(buffer, [1]);
+        assert_eq!(device.smbus_read_byte_data(0x10).unwrap(), 2);
+        assert_eq!(device.smbus_read_i2c_block_data(0x10, 1).unwrap(), vec![3]);
+    }
+
+    // A queued failure fails one combined transfer, and is then used up.
+    #[test]
+    fn a_failure_fails_one_write_read() {
+        let mut device = Counter::default();
+        device.map.set(0x20, 0x42);
+        device.map.fail_next(libc::EIO);
+        let mut buffer = [0; 1];
+        assert!(device.write_read(&[0x20], &mut buffer).is_err());
+        device.write_read(&[0x20], &mut buffer).unwrap();
+        assert_eq!(buffer, [0x42]);
+    }
+}
@@ -2,6 +2,8 @@
 // It is derived directly from the datasheet,
 // which should serve as its best documentation.
 #![allow(missing_docs)]
+// Flag names follow the datasheet, which is not always upper case.
+#![allow(non_upper_case_globals)]
 
 //! Type and address definitions for most of the LSM303 registers.
 //!
@@ -9,38 +11,93 @@
 //! use cases, you may require more direct control of the sensors.
 //! For those purposes, both `Accelerometer` and `Magnetometer` implement
 //! `Deref` and `DerefMut`, giving access to the underlying I2C device.
+//! `Magnetometer` also provides `read_register` and `write_register`.
 //!
 //! This module defines all of the register addresses as `u8` constants.
 //! It also defines bitflags for most of the registers.
 //! Refer to the [datasheet](http://www.st.com/resource/en/datasheet/lsm303dlhc.pdf)
 //! for more extensive documentation.
 
+/// Log a debug record, if the `log` feature is enabled.
+///
+/// Without the feature, the arguments are not evaluated.
+#[cfg(feature = "std")]
+macro_rules! log_debug {
+    ( $($arg:tt)* ) => {{
+        #[cfg(feature = "log")]
+        log::debug!($($arg)*);
+    }}
+}
+
+
+/// Log an info record, if the `log` feature is enabled.
+///
+/// Without the feature, the arguments are not evaluated.
+#[cfg(feature = "std")]
+macro_rules! log_info {
+    ( $($arg:tt)* ) => {{
+        #[cfg(feature = "log")]
+        log::info!($($arg)*);
+    }}
+}
+
+
 /// Read a register and convert to a bitflag.
 ///
-/// ```
+/// ```ignore
 /// let mut flags = read_register(self.device, CRA_REG_M, CraRegM)?;
 /// ```
+#[cfg(feature = "std")]
 macro_rules! read_register {
-    ( $device:expr, $register:expr, $flag_type:ident ) => {
+    ( $device:expr, $register:expr, $flag_type:ident ) => {{
+        let register = $register;
         $device
-            .smbus_read_byte_data($register)
-            .chain_err(|| ErrorKind::FailedToReadRegister)
+            .smbus_read_byte_data(register)
+            .map(|value| {
+                log_debug!("read 0x{:02X} from register 0x{:02X}", value, register);
+                value
+            })
+            .chain_bus_err(|| ErrorKind::FailedToReadRegister)
             .map($flag_type::from_bits_truncate)
-    }
+    }}
 }
 
 
 /// Write a bitflag to a register.
 ///
-/// ```
+/// ```ignore
 /// write_register!(self.device, CRA_REG_M, flags)?;
 /// ```
+#[cfg(feature = "std")]
 macro_rules! write_register {
-    ( $device:expr, $register:expr, $bitflag:ident ) => {
+    ( $device:expr, $register:expr, $bitflag:ident ) => {{
+        let register = $register;
+        log_debug!("write 0x{:02X} to register 0x{:02X}", $bitflag.bits(), register);
         $device
-            .smbus_write_byte_data($register, $bitflag.bits())
-            .chain_err(|| ErrorKind::FailedToWriteRegister)
-    }
+            .smbus_write_byte_data(register, $bitflag.bits())
+            .chain_bus_err(|| ErrorKind::FailedToWriteRegister)
+    }}
+}
+
+
+/// Read consecutive registers into a buffer, without allocating.
+///
+/// The register address is written, and the buffer is filled,
+/// in a single `I2CWriteRead::write_read` transfer.
+///
+/// ```ignore
+/// let mut data = [0; 6];
+/// read_registers!(self.device, OUT_X_H_M, data)?;
+/// ```
+#[cfg(feature = "std")]
+macro_rules! read_registers {
+    ( $device:expr, $register:expr, $buffer:expr ) => {{
+        let register = $register;
+        $device
+            .write_read(&[register], &mut $buffer)
+            .map(|_| log_debug!("read {:02X?} from register 0x{:02X}", &$buffer[..], register))
+            .chain_bus_err(|| ErrorKind::FailedToReadRegister)
+    }}
 }
 
 
@@ -67,7 +124,7 @@ register_addresses! {
     0x26 => REFERENCE_A;
     0x27 => STATUS_REG_A;
     0x28 => OUT_X_L_A;
-    0x29 => OUT_X_H_H;
+    0x29 => OUT_X_H_A;
     0x2A => OUT_Y_L_A;
     0x2B => OUT_Y_H_A;
     0x2C => OUT_Z_L_A;
@@ -112,6 +169,221 @@ register_addresses! {
 }
 
 
+// The LSM303AGR magnetometer has a different register map.
+// This is based on Table 16 of the LSM303AGR datasheet.
+register_addresses! {
+    0x45 => OFFSET_X_REG_L_M;
+    0x46 => OFFSET_X_REG_H_M;
+    0x47 => OFFSET_Y_REG_L_M;
+    0x48 => OFFSET_Y_REG_H_M;
+    0x49 => OFFSET_Z_REG_L_M;
+    0x4A => OFFSET_Z_REG_H_M;
+    // 0x4B - 0x4E => reserved
+    0x4F => WHO_AM_I_M;
+    // 0x50 - 0x5F => reserved
+    0x60 => CFG_REG_A_M;
+    0x61 => CFG_REG_B_M;
+    0x62 => CFG_REG_C_M;
+    0x63 => INT_CTRL_REG_M;
+    0x64 => INT_SOURCE_REG_M;
+    0x65 => INT_THS_L_REG_M;
+    0x66 => INT_THS_H_REG_M;
+    0x67 => STATUS_REG_M;
+    0x68 => OUTX_L_REG_M;
+    0x69 => OUTX_H_REG_M;
+    0x6A => OUTY_L_REG_M;
+    0x6B => OUTY_H_REG_M;
+    0x6C => OUTZ_L_REG_M;
+    0x6D => OUTZ_H_REG_M;
+}
+
+
+/// The address of a magnetometer register.
+///
+/// The magnetometer takes these rather than bare addresses,
+/// so that an accelerometer register cannot be passed by mistake.
+/// Each variant has the address of the constant of the same name.
+///
+/// ```
+/// # use lsm303::registers::{MagRegister, CRA_REG_M};
+/// assert_eq!(MagRegister::CraReg.addr(), CRA_REG_M);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MagRegister {
+    // LSM303DLHC
+    /// `CRA_REG_M`
+    CraReg = CRA_REG_M,
+    /// `CRB_REG_M`
+    CrbReg = CRB_REG_M,
+    /// `MR_REG_M`
+    MrReg = MR_REG_M,
+    /// `OUT_X_H_M`
+    OutXH = OUT_X_H_M,
+    /// `OUT_X_L_M`
+    OutXL = OUT_X_L_M,
+    /// `OUT_Z_H_M`
+    OutZH = OUT_Z_H_M,
+    /// `OUT_Z_L_M`
+    OutZL = OUT_Z_L_M,
+    /// `OUT_Y_H_M`
+    OutYH = OUT_Y_H_M,
+    /// `OUT_Y_L_M`
+    OutYL = OUT_Y_L_M,
+    /// `SR_REG_M`
+    SrReg = SR_REG_M,
+    /// `IRA_REG_M`
+    IraReg = IRA_REG_M,
+    /// `IRB_REG_M`
+    IrbReg = IRB_REG_M,
+    /// `IRC_REG_M`
+    IrcReg = IRC_REG_M,
+    /// `TEMP_OUT_H_M`
+    TempOutH = TEMP_OUT_H_M,
+    /// `TEMP_OUT_L_M`
+    TempOutL = TEMP_OUT_L_M,
+
+    // LSM303AGR
+    /// `OFFSET_X_REG_L_M`
+    OffsetXRegL = OFFSET_X_REG_L_M,
+    /// `OFFSET_X_REG_H_M`
+    OffsetXRegH = OFFSET_X_REG_H_M,
+    /// `OFFSET_Y_REG_L_M`
+    OffsetYRegL = OFFSET_Y_REG_L_M,
+    /// `OFFSET_Y_REG_H_M`
+    OffsetYRegH = OFFSET_Y_REG_H_M,
+    /// `OFFSET_Z_REG_L_M`
+    OffsetZRegL = OFFSET_Z_REG_L_M,
+    /// `OFFSET_Z_REG_H_M`
+    OffsetZRegH = OFFSET_Z_REG_H_M,
+    /// `WHO_AM_I_M`
+    WhoAmI = WHO_AM_I_M,
+    /// `CFG_REG_A_M`
+    CfgRegA = CFG_REG_A_M,
+    /// `CFG_REG_B_M`
+    CfgRegB = CFG_REG_B_M,
+    /// `CFG_REG_C_M`
+    CfgRegC = CFG_REG_C_M,
+    /// `INT_CTRL_REG_M`
+    IntCtrlReg = INT_CTRL_REG_M,
+    /// `INT_SOURCE_REG_M`
+    IntSourceReg = INT_SOURCE_REG_M,
+    /// `INT_THS_L_REG_M`
+    IntThsLReg = INT_THS_L_REG_M,
+    /// `INT_THS_H_REG_M`
+    IntThsHReg = INT_THS_H_REG_M,
+    /// `STATUS_REG_M`
+    StatusReg = STATUS_REG_M,
+    /// `OUTX_L_REG_M`
+    OutxLReg = OUTX_L_REG_M,
+    /// `OUTX_H_REG_M`
+    OutxHReg = OUTX_H_REG_M,
+    /// `OUTY_L_REG_M`
+    OutyLReg = OUTY_L_REG_M,
+    /// `OUTY_H_REG_M`
+    OutyHReg = OUTY_H_REG_M,
+    /// `OUTZ_L_REG_M`
+    OutzLReg = OUTZ_L_REG_M,
+    /// `OUTZ_H_REG_M`
+    OutzHReg = OUTZ_H_REG_M,
+}
+
+
+impl MagRegister {
+    /// The address of the register.
+    pub fn addr(self) -> u8 {
+        self as u8
+    }
+}
+
+
+// The LSM303D has both sensors behind one address, with a merged register map.
+// This is based on Table 16 of the LSM303D datasheet.
+// The names are suffixed with `_D`, since several clash with the LSM303DLHC.
+register_addresses! {
+    // 0x00 - 0x04 => reserved
+    0x05 => TEMP_OUT_L_D;
+    0x06 => TEMP_OUT_H_D;
+    0x07 => STATUS_M_D;
+    0x08 => OUT_X_L_M_D;
+    0x09 => OUT_X_H_M_D;
+    0x0A => OUT_Y_L_M_D;
+    0x0B => OUT_Y_H_M_D;
+    0x0C => OUT_Z_L_M_D;
+    0x0D => OUT_Z_H_M_D;
+    // 0x0E => reserved
+    0x0F => WHO_AM_I_D;
+    // 0x10 - 0x11 => reserved
+    0x12 => INT_CTRL_M_D;
+    0x13 => INT_SRC_M_D;
+    0x14 => INT_THS_L_M_D;
+    0x15 => INT_THS_H_M_D;
+    0x16 => OFFSET_X_L_M_D;
+    0x17 => OFFSET_X_H_M_D;
+    0x18 => OFFSET_Y_L_M_D;
+    0x19 => OFFSET_Y_H_M_D;
+    0x1A => OFFSET_Z_L_M_D;
+    0x1B => OFFSET_Z_H_M_D;
+    0x1C => REFERENCE_X_D;
+    0x1D => REFERENCE_Y_D;
+    0x1E => REFERENCE_Z_D;
+    0x1F => CTRL0_D;
+    0x20 => CTRL1_D;
+    0x21 => CTRL2_D;
+    0x22 => CTRL3_D;
+    0x23 => CTRL4_D;
+    0x24 => CTRL5_D;
+    0x25 => CTRL6_D;
+    0x26 => CTRL7_D;
+    0x27 => STATUS_A_D;
+    0x28 => OUT_X_L_A_D;
+    0x29 => OUT_X_H_A_D;
+    0x2A => OUT_Y_L_A_D;
+    0x2B => OUT_Y_H_A_D;
+    0x2C => OUT_Z_L_A_D;
+    0x2D => OUT_Z_H_A_D;
+    0x2E => FIFO_CTRL_D;
+    0x2F => FIFO_SRC_D;
+    0x30 => IG_CFG1_D;
+    0x31 => IG_SRC1_D;
+    0x32 => IG_THS1_D;
+    0x33 => IG_DUR1_D;
+    0x34 => IG_CFG2_D;
+    0x35 => IG_SRC2_D;
+    0x36 => IG_THS2_D;
+    0x37 => IG_DUR2_D;
+    0x38 => CLICK_CFG_D;
+    0x39 => CLICK_SRC_D;
+    0x3A => CLICK_THS_D;
+    0x3B => TIME_LIMIT_D;
+    0x3C => TIME_LATENCY_D;
+    0x3D => TIME_WINDOW_D;
+    0x3E => ACT_THS_D;
+    0x3F => ACT_DUR_D;
+}
+
+
+/// The bit of the sub-address that enables auto-increment on the accelerometer.
+///
+/// Without it, a multi-byte read of the accelerometer returns
+/// the same register over and over.
+/// The magnetometer always auto-increments, and does not use this bit,
+/// so its addresses are used as they are.
+/// The LSM303D needs it for both sensors.
+pub const AUTO_INCREMENT: u8 = 0x80;
+
+
+/// The sub-address for reading consecutive accelerometer registers, starting at `base`.
+///
+/// ```
+/// # use lsm303::registers::{block_read_addr, OUT_X_L_A};
+/// assert_eq!(block_read_addr(OUT_X_L_A), 0xA8);
+/// ```
+pub fn block_read_addr(base: u8) -> u8 {
+    base | AUTO_INCREMENT
+}
+
+
 /// Declare multiple bitflags using an abbreviated syntax.
 ///
 /// All of the registers are 8 bits, with each flag being a single bit.
@@ -193,7 +465,7 @@ define_registers!{
         3, FSS3        | 2, FSS2        | 1, FSS1        | 0, FSS0        |
     }
     IntCfgA {
-        7, AOI         | 6, _6D         | 5, ZHIE        | 4, ZLIO        |
+        7, AOI         | 6, _6D         | 5, ZHIE        | 4, ZLIE        |
         3, YHIE        | 2, YLIE        | 1, XHIE        | 0, XLIE        |
     }
     IntSrcA {
@@ -209,7 +481,7 @@ define_registers!{
         3, YD          | 2, YS          | 1, XD          | 0, XS          |
     }
     ClickSrcA {
-        /* ---------- */ 6, IA_click    | 5, DCLICK      | 5, SCLICK      |
+        /* ---------- */ 6, IA_click    | 5, DCLICK      | 4, SCLICK      |
         3, Sign        | 2, Z           | 1, Y           | 0, X           |
     }
 
@@ -231,4 +503,42 @@ define_registers!{
         /* ----------- | -------------- | -------------- | ------------- */
         /* ----------- | ------------- */ 1, LOCK        | 0, DRDY        |
     }
+
+    // Magnetometer (LSM303AGR)
+
+    CfgRegAM {
+        7, COMP_TEMP_EN | 6, REBOOT     | 5, SOFT_RST    | 4, LP          |
+        3, ODR1        | 2, ODR0        | 1, MD1         | 0, MD0         |
+    }
+    CfgRegCM {
+        /* ---------- */ 6, INT_MAG_PIN | 5, I2C_DIS     | 4, BDU         |
+        3, BLE         | /* ---------- */ 1, Self_test   | 0, INT_MAG     |
+    }
+    StatusRegM {
+        7, Zyxor       | 6, zor         | 5, yor         | 4, xor         |
+        3, Zyxda       | 2, zda         | 1, yda         | 0, xda         |
+    }
+
+    // LSM303D
+
+    Ctrl1D {
+        7, AODR3       | 6, AODR2       | 5, AODR1       | 4, AODR0       |
+        3, BDU         | 2, AZEN        | 1, AYEN        | 0, AXEN        |
+    }
+    Ctrl2D {
+        7, ABW1        | 6, ABW0        | 5, AFS2        | 4, AFS1        |
+        3, AFS0        | /* ---------- */ 1, AST         | 0, SIM         |
+    }
+    Ctrl5D {
+        7, TEMP_EN     | 6, M_RES1      | 5, M_RES0      | 4, M_ODR2      |
+        3, M_ODR1      | 2, M_ODR0      | 1, LIR2        | 0, LIR1        |
+    }
+    Ctrl6D {
+        /* ---------- */ 6, MFS1        | 5, MFS0        | /* ---------- */
+        /* ----------- | -------------- | -------------- | ------------- */
+    }
+    Ctrl7D {
+        7, AHPM1       | 6, AHPM0       | 5, AFDS        | 4, T_ONLY      |
+        /* ---------- */ 2, MLP         | 1, MD1         | 0, MD0         |
+    }
 }
@@ -0,0 +1,281 @@
+//! Retrying transfers that fail because the bus is busy.
+//!
+//! On a bus shared with other peripherals, or with other processes,
+//! a transfer can fail with `EAGAIN` or `EBUSY` and succeed a moment later.
+//! Wrapping the device in a `RetryingDevice` retries those transfers,
+//! waiting longer before each attempt, so that a sampling loop is not aborted.
+//! Other errors are returned at once;
+//! in particular, a NACK means that nothing is at the address,
+//! and retrying would only delay reporting it.
+//! See `RetryPolicy::is_recoverable` for the classification.
+//!
+//! ```no_run
+//! # extern crate i2cdev;
+//! # extern crate lsm303;
+//! # use i2cdev::linux::LinuxI2CDevice;
+//! # use lsm303::retry::{RetryingDevice, RetryPolicy};
+//! # use lsm303::{Magnetometer, MAGNETOMETER_ADDRESS};
+//! # use std::time::Duration;
+//! # fn main() { test().unwrap(); }
+//! # fn test() -> lsm303::Result<()> {
+//! let device = LinuxI2CDevice::new("/dev/i2c-1", MAGNETOMETER_ADDRESS)?;
+//! let policy = RetryPolicy::new(5, Duration::from_millis(2));
+//! let mut sensor = Magnetometer::from_i2c_device(RetryingDevice::new(device, policy))?;
+//! let field = sensor.read_magnetic_field_gauss()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::bus::{I2CBus, I2CWriteRead};
+use crate::delay::{DelayProvider, StdDelay};
+use i2cdev::core::I2CDevice;
+use std::io;
+use std::time::Duration;
+
+
+/// How often, and how patiently, to retry a transfer.
+///
+/// The default is 3 attempts, starting with a backoff of 1 ms.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The number of attempts at each transfer, including the first.
+    /// Zero is treated as one.
+    pub max_attempts: u32,
+    /// The wait before the first retry; it doubles before each one after that.
+    pub initial_backoff: Duration,
+}
+
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+        }
+    }
+}
+
+
+impl RetryPolicy {
+    /// Make up to `max_attempts` attempts, waiting `initial_backoff` before the first retry.
+    pub fn new(max_attempts: u32, initial_backoff: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff,
+        }
+    }
+
+    /// Whether a transfer that failed with this error is worth retrying.
+    ///
+    /// `EAGAIN` and `EBUSY` mean that the bus was in use,
+    /// and `EINTR` that the transfer was interrupted before it started.
+    /// Anything else is permanent, including a NACK
+    /// (`ENXIO` or `EREMOTEIO`) and an unsupported operation.
+    ///
+    /// ```
+    /// # extern crate libc;
+    /// # extern crate lsm303;
+    /// # use lsm303::retry::RetryPolicy;
+    /// # use std::io;
+    /// # fn main() {
+    /// for &errno in &[libc::EAGAIN, libc::EBUSY, libc::EINTR] {
+    ///     assert!(RetryPolicy::is_recoverable(&io::Error::from_raw_os_error(errno)));
+    /// }
+    /// for &errno in &[libc::ENXIO, libc::EREMOTEIO, libc::EIO, libc::EOPNOTSUPP] {
+    ///     assert!(!RetryPolicy::is_recoverable(&io::Error::from_raw_os_error(errno)));
+    /// }
+    /// assert!(!RetryPolicy::is_recoverable(&io::Error::other("not from the OS")));
+    /// # }
+    /// ```
+    pub fn is_recoverable(error: &io::Error) -> bool {
+        matches!(error.raw_os_error(), Some(libc::EAGAIN) | Some(libc::EBUSY) | Some(libc::EINTR))
+    }
+}
+
+
+/// An `I2CDevice` that retries transfers according to a `RetryPolicy`.
+///
+/// Each transfer is retried on its own. A failed transfer has not reached
+/// the sensor, so retrying it does not move the register pointer twice.
+/// Errors are converted to `io::Error`, in order to classify them.
+///
+/// `Magnetometer::set_read_retries` is separate, and retries a failed read
+/// of the output immediately, on the same errors; set it to 0 to leave retrying to the policy.
+pub struct RetryingDevice<Dev> {
+    device: Dev,
+    policy: RetryPolicy,
+    delay: Box<dyn DelayProvider + Send>,
+}
+
+
+impl<Dev> RetryingDevice<Dev>
+where
+    Dev: I2CDevice,
+    Dev::Error: Into<io::Error>,
+{
+    /// Retry the transfers of `device` according to `policy`.
+    ///
+    /// The backoff is waited with `StdDelay`; see `set_delay`.
+    pub fn new(device: Dev, policy: RetryPolicy) -> RetryingDevice<Dev> {
+        RetryingDevice {
+            device,
+            policy,
+            delay: Box::new(StdDelay),
+        }
+    }
+
+    /// Wait for the backoff with the given `DelayProvider`.
+    pub fn set_delay<D>(&mut self, delay: D)
+    where
+        D: DelayProvider + Send + 'static,
+    {
+        self.delay = Box::new(delay);
+    }
+
+    /// Get the retry policy.
+    pub fn policy(&self) -> RetryPolicy {
+        self.policy
+    }
+
+    /// Change the retry policy.
+    pub fn set_policy(&mut self, policy: RetryPolicy) {
+        self.policy = policy;
+    }
+
+    /// Access the underlying device directly, without retries.
+    pub fn get_mut(&mut self) -> &mut Dev {
+        &mut self.device
+    }
+
+    /// Take back the underlying device.
+    pub fn into_inner(self) -> Dev {
+        self.device
+    }
+
+    /// Run a transfer, retrying it while it fails with a recoverable error.
+    fn retry<F, R>(&mut self, mut transfer: F) -> io::Result<R>
+    where
+        F: FnMut(&mut Dev) -> Result<R, Dev::Error>,
+    {
+        let mut backoff = self.policy.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            let error = match transfer(&mut self.device) {
+                Ok(value) => return Ok(value),
+                Err(e) => e.into(),
+            };
+            if attempt >= self.policy.max_attempts || !RetryPolicy::is_recoverable(&error) {
+                return Err(error);
+            }
+            self.delay.delay(backoff);
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+
+impl<Dev> I2CDevice for RetryingDevice<Dev>
+where
+    Dev: I2CDevice,
+    Dev::Error: Into<io::Error>,
+{
+    type Error = io::Error;
+
+    fn read(&mut self, data: &mut [u8]) -> io::Result<()> {
+        self.retry(|device| device.read(data))
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.retry(|device| device.write(data))
+    }
+
+    fn smbus_write_quick(&mut self, bit: bool) -> io::Result<()> {
+        self.retry(|device| device.smbus_write_quick(bit))
+    }
+
+    fn smbus_read_byte_data(&mut self, register: u8) -> io::Result<u8> {
+        self.retry(|device| device.smbus_read_byte_data(register))
+    }
+
+    fn smbus_write_byte_data(&mut self, register: u8, value: u8) -> io::Result<()> {
+        self.retry(|device| device.smbus_write_byte_data(register, value))
+    }
+
+    fn smbus_read_block_data(&mut self, register: u8) -> io::Result<Vec<u8>> {
+        self.retry(|device| device.smbus_read_block_data(register))
+    }
+
+    fn smbus_read_i2c_block_data(&mut self, register: u8, len: u8) -> io::Result<Vec<u8>> {
+        self.retry(|device| device.smbus_read_i2c_block_data(register, len))
+    }
+
+    fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> io::Result<()> {
+        self.retry(|device| device.smbus_write_block_data(register, values))
+    }
+
+    fn smbus_process_block(&mut self, register: u8, values: &[u8]) -> io::Result<()> {
+        self.retry(|device| device.smbus_process_block(register, values))
+    }
+}
+
+
+impl<Dev> I2CWriteRead for RetryingDevice<Dev>
+where
+    Dev: I2CWriteRead,
+    Dev::Error: Into<io::Error>,
+{
+    fn write_read(&mut self, data: &[u8], buffer: &mut [u8]) -> io::Result<()> {
+        self.retry(|device| device.write_read(data, buffer))
+    }
+}
+
+
+impl<Dev> I2CBus for RetryingDevice<Dev>
+where
+    Dev: I2CBus,
+    Dev::Error: Into<io::Error>,
+{
+    fn set_address(&mut self, address: u16) -> io::Result<()> {
+        self.retry(|device| device.set_address(address))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{RetryPolicy, RetryingDevice};
+    use crate::registers::{OUT_X_H_M, OUT_X_L_M};
+    use crate::test_util::MockI2CDevice;
+    use crate::Magnetometer;
+    use std::time::Duration;
+
+    // A `RetryingDevice` retries a busy bus, but not a NACK.
+    #[test]
+    fn retries_busy_bus_but_not_nack() {
+        let mut device = MockI2CDevice::magnetometer();
+        device.set_register(OUT_X_H_M, 0x01);
+        device.set_register(OUT_X_L_M, 0x2C);
+        let policy = RetryPolicy::new(3, Duration::from_micros(10));
+        let device = RetryingDevice::new(device, policy);
+        let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+        sensor.set_read_retries(0);
+
+        // Two busy attempts, and the third succeeds.
+        sensor.get_mut().fail_next(libc::EAGAIN);
+        sensor.get_mut().fail_next(libc::EBUSY);
+        assert_eq!(sensor.read_raw_magnetic_field().unwrap().x, 300);
+
+        // Three busy attempts use up the policy.
+        for _ in 0..3 {
+            sensor.get_mut().fail_next(libc::EBUSY);
+        }
+        assert!(sensor.read_raw_magnetic_field().is_err());
+
+        // A NACK is returned at once, without using up the busy attempt that follows.
+        sensor.get_mut().fail_next(libc::ENXIO);
+        sensor.get_mut().fail_next(libc::EAGAIN);
+        assert!(sensor.read_raw_magnetic_field().is_err());
+        assert_eq!(sensor.read_raw_magnetic_field().unwrap().x, 300);
+    }
+}
@@ -0,0 +1,194 @@
+//! A simulated LSM303DLHC magnetometer, for developing without hardware.
+//!
+//! Unlike `test_util::MockI2CDevice`, which returns whatever was preloaded,
+//! `SimulatedI2CDevice` generates measurements from a magnetic field:
+//! a horizontal component pointing at the simulated heading,
+//! a vertical component, and optional noise.
+//! The field is scaled at the gain the driver selected, as the chip would,
+//! and it can rotate by a fixed angle with every measurement.
+//!
+//! Enable the `sim` feature to use this module.
+//!
+//! ```
+//! # extern crate lsm303;
+//! # fn main() {
+//! # #[cfg(feature = "sim")] {
+//! use lsm303::sim::SimulatedI2CDevice;
+//! use lsm303::Magnetometer;
+//!
+//! let mut device = SimulatedI2CDevice::new();
+//! device.set_heading(30.0);
+//! device.set_rotation(90.0);
+//!
+//! let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+//! let heading = sensor.heading().unwrap();
+//! assert!((heading - 30.0).abs() < 0.5);
+//! let heading = sensor.heading().unwrap();
+//! assert!((heading - 120.0).abs() < 0.5);
+//! # }
+//! # }
+//! ```
+
+use crate::common::Vector3;
+use crate::magnetometer::{sensitivity, Gain, OVERFLOW};
+use crate::register_map::{RegisterDevice, RegisterMap};
+use crate::registers::{CrbRegM, CRB_REG_M, IRA_REG_M, IRB_REG_M, IRC_REG_M, OUT_X_H_M, SR_REG_M};
+
+
+/// The range of a valid output, from Table 3 of the datasheet.
+const OUTPUT_RANGE: (i16, i16) = (-2048, 2047);
+
+
+/// An `I2CDevice` that behaves like the magnetometer of an LSM303DLHC.
+///
+/// Every read of the output takes a new measurement, and data is always ready.
+/// The thermometer reads a constant 25 °C.
+#[derive(Clone, Debug)]
+pub struct SimulatedI2CDevice {
+    map: RegisterMap,
+    heading: f32,
+    rotation: f32,
+    horizontal: f32,
+    vertical: f32,
+    noise: f32,
+    seed: u32,
+}
+
+
+impl Default for SimulatedI2CDevice {
+    fn default() -> SimulatedI2CDevice {
+        let mut map = RegisterMap::default();
+        map.set(IRA_REG_M, b'H');
+        map.set(IRB_REG_M, b'4');
+        map.set(IRC_REG_M, b'3');
+        map.set(CRB_REG_M, Gain::Gain_1_3.bits().bits());
+        map.set(SR_REG_M, 0x01);
+
+        SimulatedI2CDevice {
+            map,
+            heading: 0.0,
+            rotation: 0.0,
+            horizontal: 0.2,
+            vertical: 0.4,
+            noise: 0.0,
+            seed: 0x2545_F491,
+        }
+    }
+}
+
+
+impl SimulatedI2CDevice {
+    /// Create a device facing magnetic north, in a field of 0.2 G horizontally
+    /// and 0.4 G vertically, without noise.
+    pub fn new() -> SimulatedI2CDevice {
+        SimulatedI2CDevice::default()
+    }
+
+    /// Face the given heading, in degrees, as `Magnetometer::heading` reports it.
+    pub fn set_heading(&mut self, heading: f32) {
+        self.heading = heading;
+    }
+
+    /// Get the current heading, in degrees.
+    pub fn heading(&self) -> f32 {
+        self.heading
+    }
+
+    /// Turn by the given angle, in degrees, after every measurement.
+    pub fn set_rotation(&mut self, degrees_per_sample: f32) {
+        self.rotation = degrees_per_sample;
+    }
+
+    /// Set the horizontal and vertical components of the field, in Gauss.
+    ///
+    /// The vertical component is positive downwards, as in the northern hemisphere.
+    pub fn set_field(&mut self, horizontal: f32, vertical: f32) {
+        self.horizontal = horizontal;
+        self.vertical = vertical;
+    }
+
+    /// Add uniform noise of up to `amplitude` Gauss to each axis.
+    pub fn set_noise(&mut self, amplitude: f32) {
+        self.noise = amplitude;
+    }
+
+    /// Seed the noise, so that a run can be reproduced.
+    pub fn set_seed(&mut self, seed: u32) {
+        // Xorshift never leaves zero.
+        self.seed = seed.max(1);
+    }
+
+    /// The field that the chip would measure now, in Gauss.
+    fn field(&mut self) -> Vector3<f32> {
+        let (sin, cos) = self.heading.to_radians().sin_cos();
+        Vector3 {
+            x: self.horizontal * cos + self.next_noise(),
+            y: self.horizontal * sin + self.next_noise(),
+            z: -self.vertical + self.next_noise(),
+        }
+    }
+
+    /// A uniformly distributed value in `[-noise, noise]`.
+    fn next_noise(&mut self) -> f32 {
+        if self.noise == 0.0 {
+            return 0.0;
+        }
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        let unit = self.seed as f32 / u32::MAX as f32;
+        (unit * 2.0 - 1.0) * self.noise
+    }
+
+    /// Take a measurement, and store it in the output registers.
+    fn measure(&mut self) {
+        let crb_reg_m = CrbRegM::from_bits_truncate(self.map.get(CRB_REG_M));
+        // The chip's behaviour with the invalid gain is undocumented; use the finest.
+        let gain = Gain::from_bits(crb_reg_m).unwrap_or(Gain::Gain_1_3);
+        let (scale_xy, scale_z) = sensitivity(gain);
+
+        let field = self.field();
+        let to_raw = |gauss: f32, scale: f32| {
+            let (low, high) = OUTPUT_RANGE;
+            let raw = (gauss * scale).round();
+            if raw < f32::from(low) || raw > f32::from(high) {
+                OVERFLOW
+            } else {
+                raw as i16
+            }
+        };
+        let raw = Vector3 {
+            x: to_raw(field.x, scale_xy),
+            y: to_raw(field.y, scale_xy),
+            z: to_raw(field.z, scale_z),
+        };
+
+        // The registers are ordered as X, Z, Y, high byte first.
+        let values = [raw.x, raw.z, raw.y];
+        for (register, value) in (OUT_X_H_M..).step_by(2).zip(values.iter()) {
+            let bytes = value.to_be_bytes();
+            self.map.set(register, bytes[0]);
+            self.map.set(register + 1, bytes[1]);
+        }
+
+        self.heading = (self.heading + self.rotation) % 360.0;
+    }
+}
+
+
+impl RegisterDevice for SimulatedI2CDevice {
+    fn map(&mut self) -> &mut RegisterMap {
+        &mut self.map
+    }
+
+    /// A read of the output starts a new measurement.
+    fn seek(&mut self, register: u8) {
+        self.map.seek(register);
+        if self.map.pointer() == OUT_X_H_M {
+            self.measure();
+        }
+    }
+}
+
+
+register_device!(SimulatedI2CDevice);
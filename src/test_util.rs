@@ -0,0 +1,265 @@
+//! A simulated I2C device, for testing without hardware.
+//!
+//! The sensors are generic over `I2CDevice`, so they can be driven by
+//! `MockI2CDevice` just as well as by a real bus.
+//! Registers are preloaded with `set_register`, and every register write
+//! is recorded, so tests can check exactly what the driver did.
+//! Since the sensors implement `Deref`, the mock remains accessible
+//! after it has been handed over.
+//! `MockI2CBus` puts a mock at each of several addresses,
+//! for sensors that share a bus.
+//!
+//! Enable the `test-util` feature to use this module.
+//!
+//! ```
+//! # extern crate lsm303;
+//! # fn main() {
+//! # #[cfg(feature = "test-util")] {
+//! use lsm303::registers::{MR_REG_M, OUT_X_H_M, OUT_X_L_M};
+//! use lsm303::test_util::MockI2CDevice;
+//! use lsm303::Magnetometer;
+//!
+//! let mut device = MockI2CDevice::magnetometer();
+//! device.set_register(OUT_X_H_M, 0x01);
+//! device.set_register(OUT_X_L_M, 0x2C);
+//!
+//! let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+//! assert_eq!(sensor.read_raw_magnetic_field().unwrap().x, 300);
+//!
+//! // Initialization ends by selecting continuous mode.
+//! assert_eq!(sensor.writes().last(), Some(&(MR_REG_M, 0x00)));
+//! # }
+//! # }
+//! ```
+
+use crate::bus::{I2CBus, I2CWriteRead};
+use crate::register_map::{RegisterDevice, RegisterMap};
+use crate::registers::{IRA_REG_M, IRB_REG_M, IRC_REG_M};
+use i2cdev::core::I2CDevice;
+use std::collections::HashMap;
+use std::io;
+
+
+/// An `I2CDevice` that simulates a register map.
+///
+/// Unset registers read as zero.
+/// Consecutive reads and writes auto-increment the register address,
+/// as on the LSM303; the high bit of the address,
+/// which the accelerometer uses to request auto-increment, is ignored
+/// unless `strict_auto_increment` is called.
+#[derive(Clone, Debug, Default)]
+pub struct MockI2CDevice {
+    map: RegisterMap,
+}
+
+
+impl MockI2CDevice {
+    /// Create a device with every register set to zero.
+    pub fn new() -> MockI2CDevice {
+        MockI2CDevice::default()
+    }
+
+    /// Create a device that identifies as an LSM303DLHC magnetometer.
+    pub fn magnetometer() -> MockI2CDevice {
+        let mut device = MockI2CDevice::new();
+        device.set_register(IRA_REG_M, b'H');
+        device.set_register(IRB_REG_M, b'4');
+        device.set_register(IRC_REG_M, b'3');
+        device
+    }
+
+    /// Preload a register, without recording a write.
+    pub fn set_register(&mut self, register: u8, value: u8) {
+        self.map.set(register, value);
+    }
+
+    /// Queue values for the next reads of a register, as if the device changed it.
+    ///
+    /// Each read of the register takes the next value;
+    /// once they are used up, the register reads as it was set.
+    pub fn queue_reads(&mut self, register: u8, values: &[u8]) {
+        self.map.queue_reads(register, values);
+    }
+
+    /// Get the current value of a register.
+    pub fn register(&self, register: u8) -> u8 {
+        self.map.get(register)
+    }
+
+    /// Every register write so far, as `(register, value)`, in order.
+    pub fn writes(&self) -> &[(u8, u8)] {
+        self.map.writes()
+    }
+
+    /// Forget the writes recorded so far.
+    pub fn clear_writes(&mut self) {
+        self.map.clear_writes();
+    }
+
+    /// The number of SMBus block reads so far.
+    pub fn block_reads(&self) -> usize {
+        self.map.block_reads()
+    }
+
+    /// Simulate a flaky bus, by returning at most `len` bytes per block read.
+    pub fn truncate_block_reads(&mut self, len: usize) {
+        self.map.block_read_limit = Some(len);
+    }
+
+    /// Only auto-increment if the high bit of the address is set, as on the accelerometer.
+    pub fn strict_auto_increment(&mut self) {
+        self.map.strict_auto_increment = true;
+    }
+
+    /// Simulate an adapter without SMBus block transfers, which fail with EOPNOTSUPP.
+    pub fn reject_smbus_block_reads(&mut self) {
+        self.map.smbus_block_unsupported = true;
+    }
+
+    /// Queue a failure with `errno` for the next transfer; queued failures are used up in order.
+    ///
+    /// This simulates a flaky bus.
+    pub fn fail_next(&mut self, errno: i32) {
+        self.map.fail_next(errno);
+    }
+}
+
+
+impl RegisterDevice for MockI2CDevice {
+    fn map(&mut self) -> &mut RegisterMap {
+        &mut self.map
+    }
+}
+
+
+register_device!(MockI2CDevice);
+
+
+/// Every address sees the same simulated registers; see `MockI2CBus` for separate devices.
+impl I2CBus for MockI2CDevice {
+    fn set_address(&mut self, _address: u16) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+/// A simulated I2C bus, with a separate `MockI2CDevice` at each address.
+///
+/// Transfers go to the device at the address last set with `set_address`.
+/// Nothing acknowledges an address without a device,
+/// so transfers to it fail with ENXIO, as on Linux.
+///
+/// ```
+/// # extern crate lsm303;
+/// # fn main() {
+/// # #[cfg(feature = "test-util")] {
+/// use lsm303::registers::OUT_X_L_M;
+/// use lsm303::test_util::{MockI2CBus, MockI2CDevice};
+/// use lsm303::{Lsm303, ACCELEROMETER_ADDRESS, MAGNETOMETER_ADDRESS};
+///
+/// let mut bus = MockI2CBus::new();
+/// bus.attach(ACCELEROMETER_ADDRESS, MockI2CDevice::new());
+/// bus.attach(MAGNETOMETER_ADDRESS, MockI2CDevice::magnetometer());
+/// bus.device_mut(MAGNETOMETER_ADDRESS).set_register(OUT_X_L_M, 0x2C);
+///
+/// let mut sensor = Lsm303::from_bus(bus).unwrap();
+/// let (_, field) = sensor.read_all().unwrap();
+/// assert_eq!(field.x, 0x2C);
+/// # }
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MockI2CBus {
+    devices: HashMap<u16, MockI2CDevice>,
+    address: u16,
+}
+
+
+impl MockI2CBus {
+    /// Create a bus without any devices.
+    pub fn new() -> MockI2CBus {
+        MockI2CBus::default()
+    }
+
+    /// Attach a device at `address`, replacing any that was there.
+    pub fn attach(&mut self, address: u16, device: MockI2CDevice) {
+        self.devices.insert(address, device);
+    }
+
+    /// Get the device at `address`.
+    ///
+    /// Panics if there is none.
+    pub fn device(&self, address: u16) -> &MockI2CDevice {
+        &self.devices[&address]
+    }
+
+    /// Get the device at `address`, to change its registers.
+    ///
+    /// Panics if there is none.
+    pub fn device_mut(&mut self, address: u16) -> &mut MockI2CDevice {
+        self.devices.get_mut(&address).expect("no device at the address")
+    }
+
+    /// The device that the next transfer goes to.
+    fn current(&mut self) -> io::Result<&mut MockI2CDevice> {
+        self.devices
+            .get_mut(&self.address)
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::ENXIO))
+    }
+}
+
+
+impl I2CDevice for MockI2CBus {
+    type Error = io::Error;
+
+    fn read(&mut self, data: &mut [u8]) -> io::Result<()> {
+        self.current()?.read(data)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.current()?.write(data)
+    }
+
+    fn smbus_write_quick(&mut self, bit: bool) -> io::Result<()> {
+        self.current()?.smbus_write_quick(bit)
+    }
+
+    fn smbus_read_byte_data(&mut self, register: u8) -> io::Result<u8> {
+        self.current()?.smbus_read_byte_data(register)
+    }
+
+    fn smbus_write_byte_data(&mut self, register: u8, value: u8) -> io::Result<()> {
+        self.current()?.smbus_write_byte_data(register, value)
+    }
+
+    fn smbus_read_block_data(&mut self, register: u8) -> io::Result<Vec<u8>> {
+        self.current()?.smbus_read_block_data(register)
+    }
+
+    fn smbus_read_i2c_block_data(&mut self, register: u8, len: u8) -> io::Result<Vec<u8>> {
+        self.current()?.smbus_read_i2c_block_data(register, len)
+    }
+
+    fn smbus_write_block_data(&mut self, register: u8, values: &[u8]) -> io::Result<()> {
+        self.current()?.smbus_write_block_data(register, values)
+    }
+
+    fn smbus_process_block(&mut self, register: u8, values: &[u8]) -> io::Result<()> {
+        self.current()?.smbus_process_block(register, values)
+    }
+}
+
+
+impl I2CWriteRead for MockI2CBus {
+    fn write_read(&mut self, data: &[u8], buffer: &mut [u8]) -> io::Result<()> {
+        self.current()?.write_read(data, buffer)
+    }
+}
+
+
+impl I2CBus for MockI2CBus {
+    fn set_address(&mut self, address: u16) -> io::Result<()> {
+        self.address = address;
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+//! Reading the sensors in a sampling loop must not allocate.
+//!
+//! This is its own test binary, since it replaces the global allocator.
+
+extern crate lsm303;
+
+use lsm303::registers::{OUT_X_H_M, OUT_X_L_M};
+use lsm303::test_util::MockI2CDevice;
+use lsm303::{Accelerometer, Magnetometer};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+
+/// The system allocator, counting the allocations made on each thread.
+struct CountingAllocator;
+
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+
+/// The number of allocations that `f` makes on this thread.
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+
+// Reading the raw magnetic field into a stack buffer does not allocate.
+#[test]
+fn magnetometer_read_does_not_allocate() {
+    let mut device = MockI2CDevice::magnetometer();
+    device.set_register(OUT_X_H_M, 0x01);
+    device.set_register(OUT_X_L_M, 0x2C);
+    let mut sensor = Magnetometer::from_i2c_device(device).unwrap();
+
+    let mut raw = None;
+    assert_eq!(allocations(|| raw = Some(sensor.read_raw_magnetic_field().unwrap())), 0);
+    assert_eq!(raw.unwrap().x, 300);
+    assert_eq!(allocations(|| {
+        sensor.read_magnetic_field().unwrap();
+    }), 0);
+}
+
+
+// Neither does reading the raw acceleration.
+#[test]
+fn accelerometer_read_does_not_allocate() {
+    let mut sensor = Accelerometer::from_i2c_device(MockI2CDevice::new()).unwrap();
+    assert_eq!(allocations(|| {
+        sensor.read_raw_acceleration().unwrap();
+    }), 0);
+}